@@ -0,0 +1,18 @@
+-- Your SQL goes here
+CREATE TABLE uploads (
+    id VARCHAR(36) PRIMARY KEY, -- UUID
+    owner_id INTEGER NOT NULL,
+    file_id VARCHAR(36) NOT NULL, -- UUID of the file the upload is finalised into
+    upload_length BIGINT NOT NULL, -- total size in bytes announced by the client
+    upload_offset BIGINT NOT NULL DEFAULT 0, -- number of bytes received so far
+    filename TEXT,
+    media_type VARCHAR(256),
+    patching BOOL NOT NULL DEFAULT false, -- true while a PATCH request is writing a chunk, one at a time
+    quota_user_id INTEGER NOT NULL, -- user whose storage holds upload_length while the upload is active
+    expires_at timestamp NOT NULL, -- the upload and its chunks are removed after this, moved on by every PATCH
+    -- metadata
+    created_at timestamp DEFAULT CURRENT_TIMESTAMP,
+    updated_at timestamp DEFAULT CURRENT_TIMESTAMP,
+    created_by INTEGER NOT NULL DEFAULT 0,
+    updated_by INTEGER NOT NULL DEFAULT 0,
+    active BOOL NOT NULL DEFAULT true);
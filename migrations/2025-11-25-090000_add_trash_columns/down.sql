@@ -0,0 +1,6 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE files DROP COLUMN deleted_at;
+ALTER TABLE files DROP COLUMN deleted_by;
+
+ALTER TABLE file_folders DROP COLUMN deleted_at;
+ALTER TABLE file_folders DROP COLUMN deleted_by;
@@ -0,0 +1,6 @@
+-- Your SQL goes here
+ALTER TABLE files ADD COLUMN deleted_at timestamp;
+ALTER TABLE files ADD COLUMN deleted_by INTEGER;
+
+ALTER TABLE file_folders ADD COLUMN deleted_at timestamp;
+ALTER TABLE file_folders ADD COLUMN deleted_by INTEGER;
@@ -0,0 +1,13 @@
+-- Your SQL goes here
+CREATE TABLE file_versions (
+    id VARCHAR(36) PRIMARY KEY, -- UUID
+    file_id VARCHAR(36) NOT NULL, -- UUID
+    version INTEGER NOT NULL, -- starts at 1 and increases with every upload
+    size BIGINT NOT NULL, -- size of the contents in bytes
+    checksum VARCHAR(64) NOT NULL, -- hex encoded SHA-256 of the contents
+    media_type VARCHAR(256),
+    orginal_filename TEXT,
+    -- metadata
+    created_at timestamp DEFAULT CURRENT_TIMESTAMP,
+    created_by INTEGER NOT NULL DEFAULT 0,
+    UNIQUE (file_id, version));
@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE files DROP COLUMN blob_hash;
+
+DROP TABLE blobs;
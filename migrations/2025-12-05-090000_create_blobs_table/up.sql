@@ -0,0 +1,11 @@
+-- Your SQL goes here
+CREATE TABLE blobs (
+    hash VARCHAR(64) PRIMARY KEY, -- hex encoded SHA-256 of the contents
+    size BIGINT NOT NULL, -- size of the contents in bytes
+    ref_count INTEGER NOT NULL DEFAULT 0, -- number of file versions using the blob
+    -- metadata
+    created_at timestamp DEFAULT CURRENT_TIMESTAMP);
+
+CREATE INDEX blobs_ref_count_idx ON blobs (ref_count);
+
+ALTER TABLE files ADD COLUMN blob_hash VARCHAR(64);
@@ -0,0 +1,5 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE files DROP COLUMN size;
+
+ALTER TABLE users DROP COLUMN storage_used;
+ALTER TABLE users DROP COLUMN storage_quota;
@@ -0,0 +1,12 @@
+-- Your SQL goes here
+ALTER TABLE files ADD COLUMN size BIGINT NOT NULL DEFAULT 0; -- size of the current contents in bytes
+
+ALTER TABLE users ADD COLUMN storage_used BIGINT NOT NULL DEFAULT 0; -- bytes used by all versions of the user's files, including the trash
+ALTER TABLE users ADD COLUMN storage_quota BIGINT; -- maximum bytes the user may use, NULL for the configured default
+
+-- Files in the blob store already know their size, older uploads start at zero
+UPDATE files SET size = COALESCE((SELECT blobs.size FROM blobs WHERE blobs.hash = files.blob_hash), 0);
+UPDATE users SET storage_used = COALESCE((
+    SELECT SUM(file_versions.size) FROM file_versions
+    JOIN files ON files.id = file_versions.file_id
+    WHERE files.owner_id = users.id), 0);
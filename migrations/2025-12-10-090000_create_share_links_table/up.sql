@@ -0,0 +1,19 @@
+-- Your SQL goes here
+CREATE TABLE share_links (
+    id VARCHAR(36) PRIMARY KEY, -- UUID
+    token VARCHAR(64) NOT NULL UNIQUE, -- random URL safe token handed out in the public link
+    owner_id INTEGER NOT NULL,
+    file_id VARCHAR(36), -- UUID of the shared file, either this or folder_id is set
+    folder_id VARCHAR(36), -- UUID of the shared folder
+    password TEXT, -- argon2 hash, NULL when the link is not password protected
+    expires_at timestamp,
+    max_downloads INTEGER,
+    download_count INTEGER NOT NULL DEFAULT 0,
+    failed_attempts INTEGER NOT NULL DEFAULT 0, -- wrong passwords in a row
+    locked_until timestamp, -- no passwords are checked before, set after too many wrong ones
+    -- metadata
+    created_at timestamp DEFAULT CURRENT_TIMESTAMP,
+    updated_at timestamp DEFAULT CURRENT_TIMESTAMP,
+    created_by INTEGER NOT NULL DEFAULT 0,
+    updated_by INTEGER NOT NULL DEFAULT 0,
+    active BOOL NOT NULL DEFAULT true);
@@ -0,0 +1,16 @@
+-- Your SQL goes here
+CREATE TABLE user_shares (
+    id VARCHAR(36) PRIMARY KEY, -- UUID
+    owner_id INTEGER NOT NULL, -- the user sharing the item
+    user_id INTEGER NOT NULL, -- the user the item is shared with
+    file_id VARCHAR(36), -- UUID of the shared file, either this or folder_id is set
+    folder_id VARCHAR(36), -- UUID of the shared folder, everything below it is shared as well
+    permission INTEGER NOT NULL DEFAULT 1, -- 1: read, 2: read-write
+    -- metadata
+    created_at timestamp DEFAULT CURRENT_TIMESTAMP,
+    updated_at timestamp DEFAULT CURRENT_TIMESTAMP,
+    created_by INTEGER NOT NULL DEFAULT 0,
+    updated_by INTEGER NOT NULL DEFAULT 0,
+    active BOOL NOT NULL DEFAULT true);
+
+CREATE INDEX user_shares_user_id_idx ON user_shares (user_id);
@@ -0,0 +1,6 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE files DROP COLUMN group_id;
+ALTER TABLE file_folders DROP COLUMN group_id;
+
+DROP TABLE group_members;
+DROP TABLE groups;
@@ -0,0 +1,30 @@
+-- Your SQL goes here
+CREATE TABLE groups (
+    id VARCHAR(36) PRIMARY KEY, -- UUID
+    name TEXT NOT NULL,
+    description TEXT,
+    folder_id VARCHAR(36) NOT NULL, -- UUID of the group's root folder in storage
+    -- metadata
+    created_at timestamp DEFAULT CURRENT_TIMESTAMP,
+    updated_at timestamp DEFAULT CURRENT_TIMESTAMP,
+    created_by INTEGER NOT NULL DEFAULT 0,
+    updated_by INTEGER NOT NULL DEFAULT 0,
+    active BOOL NOT NULL DEFAULT true);
+
+CREATE TABLE group_members (
+    id VARCHAR(36) PRIMARY KEY, -- UUID
+    group_id VARCHAR(36) NOT NULL,
+    user_id INTEGER NOT NULL,
+    role INTEGER NOT NULL DEFAULT 2, -- 1: viewer, 2: editor, 3: admin
+    -- metadata
+    created_at timestamp DEFAULT CURRENT_TIMESTAMP,
+    updated_at timestamp DEFAULT CURRENT_TIMESTAMP,
+    created_by INTEGER NOT NULL DEFAULT 0,
+    updated_by INTEGER NOT NULL DEFAULT 0,
+    active BOOL NOT NULL DEFAULT true);
+
+CREATE INDEX group_members_user_id_idx ON group_members (user_id);
+
+-- Items owned by a group, owner_id then is the member who created them and whose quota they use
+ALTER TABLE files ADD COLUMN group_id VARCHAR(36);
+ALTER TABLE file_folders ADD COLUMN group_id VARCHAR(36);
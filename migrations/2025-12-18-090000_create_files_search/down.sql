@@ -0,0 +1,7 @@
+-- This file should undo anything in `up.sql`
+DROP TRIGGER files_search_delete;
+DROP TRIGGER files_search_update;
+DROP TRIGGER files_search_insert;
+
+DROP TABLE files_search_rowids;
+DROP TABLE files_search;
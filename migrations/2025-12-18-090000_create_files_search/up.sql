@@ -0,0 +1,42 @@
+-- Your SQL goes here
+-- Full-text index over the metadata of the files and the text of text-like contents
+CREATE VIRTUAL TABLE files_search USING fts5(
+    file_id UNINDEXED, -- UUID of the file
+    title,
+    description,
+    filename, -- original filename of the current contents
+    content, -- text of the current contents, NULL for other media types
+    tokenize = 'unicode61 remove_diacritics 2');
+
+-- The row of each file in files_search, so the index is updated by rowid instead of scanning it
+-- for the UNINDEXED file_id. The rowids of files itself may change on VACUUM, so they are not used
+CREATE TABLE files_search_rowids (
+    file_id TEXT PRIMARY KEY NOT NULL, -- UUID of the file
+    search_rowid INTEGER NOT NULL -- rowid of the file's row in files_search
+);
+
+INSERT INTO files_search (file_id, title, description, filename)
+    SELECT id, title, description, orginal_filename FROM files;
+
+INSERT INTO files_search_rowids (file_id, search_rowid)
+    SELECT file_id, rowid FROM files_search;
+
+-- The metadata follows the files table, the content is indexed when it is uploaded
+CREATE TRIGGER files_search_insert AFTER INSERT ON files BEGIN
+    INSERT INTO files_search (file_id, title, description, filename)
+        VALUES (new.id, new.title, new.description, new.orginal_filename);
+    INSERT INTO files_search_rowids (file_id, search_rowid)
+        VALUES (new.id, last_insert_rowid());
+END;
+
+CREATE TRIGGER files_search_update AFTER UPDATE OF title, description, orginal_filename ON files BEGIN
+    UPDATE files_search
+        SET title = new.title, description = new.description, filename = new.orginal_filename
+        WHERE rowid = (SELECT search_rowid FROM files_search_rowids WHERE file_id = new.id);
+END;
+
+CREATE TRIGGER files_search_delete AFTER DELETE ON files BEGIN
+    DELETE FROM files_search
+        WHERE rowid = (SELECT search_rowid FROM files_search_rowids WHERE file_id = old.id);
+    DELETE FROM files_search_rowids WHERE file_id = old.id;
+END;
@@ -0,0 +1,18 @@
+-- Your SQL goes here
+CREATE TABLE jobs (
+    id VARCHAR(36) PRIMARY KEY, -- UUID
+    kind TEXT NOT NULL,
+    payload TEXT NOT NULL, -- JSON, the work to do depends on the kind
+    status INTEGER NOT NULL DEFAULT 0, -- 0: queued, 1: running, 2: done, 3: failed
+    attempts INTEGER NOT NULL DEFAULT 0,
+    max_attempts INTEGER NOT NULL DEFAULT 5,
+    run_at timestamp NOT NULL DEFAULT CURRENT_TIMESTAMP, -- not run before, pushed back after each failed attempt
+    last_error TEXT,
+    -- metadata
+    created_at timestamp DEFAULT CURRENT_TIMESTAMP,
+    updated_at timestamp DEFAULT CURRENT_TIMESTAMP,
+    created_by INTEGER NOT NULL DEFAULT 0,
+    updated_by INTEGER NOT NULL DEFAULT 0,
+    active BOOL NOT NULL DEFAULT true);
+
+CREATE INDEX jobs_status_run_at_idx ON jobs (status, run_at);
@@ -0,0 +1,3 @@
+-- Your SQL goes here
+-- Mails are sent to addresses no matter their case, so an address can belong to one user only
+CREATE UNIQUE INDEX users_email_address_lower ON users (lower(email_address));
@@ -0,0 +1,13 @@
+-- Your SQL goes here
+CREATE TABLE password_resets (
+    id VARCHAR(36) PRIMARY KEY, -- UUID
+    user_id INTEGER NOT NULL,
+    token_hash VARCHAR(64) NOT NULL UNIQUE, -- hex SHA-256 of the mailed token, the token itself is not stored
+    expires_at timestamp NOT NULL,
+    used_at timestamp, -- NULL until the token was used, a token works only once
+    -- metadata
+    created_at timestamp DEFAULT CURRENT_TIMESTAMP,
+    updated_at timestamp DEFAULT CURRENT_TIMESTAMP,
+    created_by INTEGER NOT NULL DEFAULT 0,
+    updated_by INTEGER NOT NULL DEFAULT 0,
+    active BOOL NOT NULL DEFAULT true);
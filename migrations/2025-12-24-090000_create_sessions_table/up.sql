@@ -0,0 +1,27 @@
+-- Your SQL goes here
+CREATE TABLE sessions (
+    id VARCHAR(36) PRIMARY KEY, -- UUID, carried in the access tokens as `sid`
+    user_id INTEGER NOT NULL,
+    refresh_token_hash VARCHAR(64) NOT NULL UNIQUE, -- hex SHA-256 of the current refresh token
+    user_agent TEXT,
+    ip_address TEXT,
+    last_used_at timestamp DEFAULT CURRENT_TIMESTAMP, -- last login or refresh
+    expires_at timestamp NOT NULL, -- pushed back on every refresh
+    revoked_at timestamp,
+    -- metadata
+    created_at timestamp DEFAULT CURRENT_TIMESTAMP,
+    updated_at timestamp DEFAULT CURRENT_TIMESTAMP,
+    created_by INTEGER NOT NULL DEFAULT 0,
+    updated_by INTEGER NOT NULL DEFAULT 0,
+    active BOOL NOT NULL DEFAULT true);
+
+CREATE INDEX sessions_user_id_idx ON sessions (user_id);
+
+-- Every refresh token handed out for a session, the current one included. Presenting any of the
+-- replaced ones again means it was copied, which revokes the session
+CREATE TABLE session_tokens (
+    token_hash VARCHAR(64) PRIMARY KEY, -- hex SHA-256 of the refresh token
+    session_id VARCHAR(36) NOT NULL,
+    created_at timestamp DEFAULT CURRENT_TIMESTAMP);
+
+CREATE INDEX session_tokens_session_id_idx ON session_tokens (session_id);
@@ -1,5 +1,7 @@
+use diesel::prelude::*;
 use serde::{Deserialize, Serialize};
 use utoipa::ToSchema;
+use crate::schema::password_resets;
 
 
 // #[allow(non_snake_case)]
@@ -16,6 +18,8 @@ use utoipa::ToSchema;
 #[derive(Debug, Serialize, Deserialize)]
 pub struct TokenClaims {
     pub sub: String,
+    // The session the token belongs to, the token stops working once it is revoked
+    pub sid: String,
     pub iat: usize,
     pub exp: usize,
 }
@@ -45,9 +49,103 @@ pub struct LoginRequestDto {
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
 pub struct LoginResponseDto {
-    // {"status": "success", "token": token, "user": user}
+    // {"status": "success", "token": token, "refreshToken": refresh_token, "user": user}
     pub status: String,
     pub token: String,
+    // Gets a new token from `/api/auth/refresh` once this one expires
+    pub refresh_token: String,
     pub user: crate::shared::dto::UserDto, //UserProfileDto,
 }
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct StorageUsageDto {
+    // Bytes used by all versions of the user's files, including the trash
+    pub used: i64,
+    pub quota: i64,
+    pub available: i64,
+    // Bytes used by files in the trash with their versions, freed once the trash is purged
+    pub trash: i64,
+    // Bytes used by earlier versions of the files, kept until their file is purged
+    pub versions: i64,
+    pub folders: Vec<FolderUsageDto>,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct FolderUsageDto {
+    pub folder_id: String,
+    // None for the user's root folder
+    pub title: Option<String>,
+    // Bytes used by the files directly in the folder
+    pub used: i64,
+    pub file_count: i64,
+}
+
+///
+/// Claims of the tokens mailed to users, like the email verification link
+///
+/// They are signed with a key derived from the purpose, so they never pass as login tokens
+///
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MailTokenClaims {
+    pub sub: String,
+    // The token is void once the user changes their email address
+    pub email: String,
+    pub iat: usize,
+    pub exp: usize,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct VerifyEmailParams {
+    pub token: String,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct ResendVerificationDto {
+    pub email: String,
+}
+
+#[derive(Debug, Clone, Queryable, Selectable, Insertable)]
+#[diesel(table_name = password_resets)]
+pub struct PasswordResetDto {
+    pub id: String,
+    pub user_id: i32,
+    // Hex SHA-256 of the mailed token
+    pub token_hash: String,
+    pub expires_at: chrono::NaiveDateTime,
+    pub used_at: Option<chrono::NaiveDateTime>,
+    pub created_at: Option<chrono::NaiveDateTime>,
+    pub updated_at: Option<chrono::NaiveDateTime>,
+    pub created_by: i32,
+    pub updated_by: i32,
+    pub active: bool,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct ForgotPasswordDto {
+    pub email: String,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct ResetPasswordDto {
+    // Token from the password reset mail
+    pub token: String,
+    pub password: String,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ChangePasswordDto {
+    pub current_password: String,
+    pub new_password: String,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct RefreshTokenDto {
+    // Taken from the `refresh_token` cookie when missing
+    pub refresh_token: Option<String>,
+}
@@ -1,13 +1,16 @@
 use core::fmt;
-use std::future::{ready, Ready};
+use std::future::ready;
 
-use actix_web::error::ErrorUnauthorized;
+use futures_util::future::LocalBoxFuture;
+
+use actix_web::error::{ErrorInternalServerError, ErrorUnauthorized};
 use actix_web::{dev::Payload, Error as ActixWebError};
 use actix_web::{http, web, FromRequest, HttpMessage, HttpRequest};
 use jsonwebtoken::{decode, DecodingKey, Validation};
 use serde::Serialize;
 
 use super::dto::TokenClaims;
+use crate::sessions::service::is_session_active;
 
 use super::AppState;
 
@@ -27,11 +30,13 @@ impl fmt::Display for ErrorResponse {
 
 pub struct JwtMiddleware {
     pub user_id: i32,
+    // Empty for the guest user outside of prod mode
+    pub session_id: String,
 }
 
 impl FromRequest for JwtMiddleware {
     type Error = ActixWebError;
-    type Future = Ready<Result<Self, Self::Error>>;
+    type Future = LocalBoxFuture<'static, Result<Self, Self::Error>>;
     fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
         let data = req.app_data::<web::Data<AppState>>().unwrap();
 
@@ -47,15 +52,16 @@ impl FromRequest for JwtMiddleware {
 
         if token.is_none() {
             if !data.is_prod_mode() {
-                return ready(Ok(JwtMiddleware {
+                return Box::pin(ready(Ok(JwtMiddleware {
                     user_id: 0,
-                }));
+                    session_id: String::new(),
+                })));
             }
             let json_error = ErrorResponse {
                 status: "fail".to_string(),
                 message: "You are not logged in, please provide token".to_string(),
             };
-            return ready(Err(ErrorUnauthorized(json_error)));
+            return Box::pin(ready(Err(ErrorUnauthorized(json_error))));
         }
 
         let claims = match decode::<TokenClaims>(
@@ -69,16 +75,42 @@ impl FromRequest for JwtMiddleware {
                     status: "fail".to_string(),
                     message: "Invalid token".to_string(),
                 };
-                return ready(Err(ErrorUnauthorized(json_error)));
+                return Box::pin(ready(Err(ErrorUnauthorized(json_error))));
             }
         };
 
         let user_id = claims.sub;
         let user_id = user_id.parse::<i32>().unwrap();
-        req.extensions_mut().insert::<i32>(user_id);
 
-        ready(Ok(JwtMiddleware {
-            user_id,
-        }))
+        let req = req.clone();
+        let data = data.clone();
+        Box::pin(async move {
+            // Logging out, changing the password or revoking the session ends it before the token expires
+            let session_id = claims.sid.clone();
+            let active = web::block(move || {
+                data.get_connection().and_then(|mut conn| is_session_active(&mut conn, &session_id, user_id))
+            })
+            .await
+            .map_err(|err| ErrorInternalServerError(err.to_string()))?;
+
+            match active {
+                Ok(true) => {}
+                Ok(false) => {
+                    let json_error = ErrorResponse {
+                        status: "fail".to_string(),
+                        message: "Session has ended".to_string(),
+                    };
+                    return Err(ErrorUnauthorized(json_error));
+                }
+                Err(err) => return Err(ErrorInternalServerError(err.to_string())),
+            }
+
+            req.extensions_mut().insert::<i32>(user_id);
+
+            Ok(JwtMiddleware {
+                user_id,
+                session_id: claims.sid,
+            })
+        })
     }
 }
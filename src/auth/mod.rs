@@ -1,10 +1,11 @@
 pub mod dto;
 pub mod jwt_auth;
 pub mod service;
+pub mod tokens;
 
 use actix_web::{
     cookie::{time::Duration as ActixWebDuration, Cookie},
-    get, post, web, Error, HttpResponse,
+    get, http::header, post, web, Error, HttpRequest, HttpResponse, HttpResponseBuilder,
 };
 
 use chrono::{prelude::*, Duration};
@@ -13,21 +14,34 @@ use serde_json::json;
 
 use log::info;
 
-use dto::RegisterUserDto;
+use dto::{ChangePasswordDto, ForgotPasswordDto, RefreshTokenDto, RegisterUserDto, ResetPasswordDto, ResendVerificationDto, VerifyEmailParams};
 
-use crate::{auth::dto::{LoginRequestDto, TokenClaims}, shared::dto::NewUserDto};
-use crate::shared::common::ServiceError;
+use crate::{auth::dto::{LoginRequestDto, StorageUsageDto, TokenClaims}, shared::dto::NewUserDto};
+use crate::mail::dto::Mail;
+use crate::sessions::dto::SessionDto;
+use crate::sessions::service::{create_session, revoke_session, rotate_session};
+use crate::shared::common::{DbError, ServiceError};
 use crate::{
     auth::dto::LoginResponseDto,
     shared::dto::{UserDto, UserProfileDto},
     shared::common::AppState,
 };
-use service::{create_user, find_user_by_username_and_password, is_exists, get_user};
+use service::{create_user, find_user_by_username_and_password, is_exists, is_email_taken, is_unique_violation, get_user};
+use service::{activate_user, get_user_by_email, get_user_by_username};
+use service::{create_password_reset, reset_password, set_password, verify_password};
+use service::{get_storage_usage, get_trash_usage, get_version_usage, get_folder_usage};
+use tokens::{create_mail_token, decode_mail_token, VERIFY_EMAIL};
+
+// Access tokens are short lived, the refresh token gets a new one
+const ACCESS_TOKEN_MINUTES: i64 = 60;
+const REFRESH_COOKIE: &str = "refresh_token";
+// Applies to registering, resetting and changing a password
+const MIN_PASSWORD_LENGTH: usize = 8;
 
 ///
 /// Registers a new user
 ///
-/// Creates a default page and block for the user
+/// Creates a default page and block for the user, and mails them a link to verify their email address
 ///
 #[utoipa::path(
     post,
@@ -47,31 +61,137 @@ pub async fn register_user_handler(
         .get_connection()
         .map_err(|err| ServiceError::NotFound(err.to_string()))?;
 
+    check_password(&body.password)?;
+
     let exists = is_exists(&mut conn, body.username.to_owned()).ok().unwrap_or_default();
         //.map_err(|err| ServiceError::NotFound(err.to_string()))?;
 
     if exists {
+        return Ok(HttpResponse::Conflict().json(
+            serde_json::json!({"status": "fail","message": "User with that username already exists"}),
+        ));
+    }
+    if is_email_taken(&mut conn, &body.email).map_err(|err| ServiceError::InternalServerError(err.to_string()))? {
         return Ok(HttpResponse::Conflict().json(
             serde_json::json!({"status": "fail","message": "User with that email already exists"}),
         ));
     }
 
-    let _created = web::block(move || {
+    let storage = app.get_storage_service().clone();
+    let created = web::block(move || {
         create_user(
             &mut conn,
-            app.get_storage_service(),
+            storage.as_ref(),
             NewUserDto{
                 username: body.username.to_owned(),
                 email_address: body.email.to_owned(),
                 password: body.password.to_owned(),
             }
-        )
+        )?;
+        get_user_by_username(&mut conn, &body.username)
     })
-    .await?
-    .map_err(|err| ServiceError::NotFound(err.to_string()))?;
+    .await?;
+    let user = match created {
+        Ok(user) => user,
+        // Taken by a registration running at the same time
+        Err(err) if is_unique_violation(&err) => {
+            return Ok(HttpResponse::Conflict().json(
+                serde_json::json!({"status": "fail","message": "User with that username or email already exists"}),
+            ));
+        }
+        Err(err) => return Err(ServiceError::NotFound(err.to_string()).into()),
+    };
+
+    // The user can ask for another mail, so the registration stands when sending fails
+    if let Err(err) = send_verification_mail(&app, &user).await {
+        log::error!("Failed to send the verification mail to user: {}, Error: {}", user.username, err);
+    }
     Ok(HttpResponse::Created().finish())
 }
 
+///
+/// Verifies the email address of a user with the token from the mailed link
+///
+/// The user is activated, which they need to login when `REQUIRE_EMAIL_VERIFICATION` is set
+///
+#[utoipa::path(
+    get,
+    tag = "Authentication",
+    path = "/api/auth/verify",
+    params(
+        ("token" = String, Query, description = "Token from the verification mail")
+    ),
+    responses(
+        (status = 200, description = "Successfully verified the email address of the user"),
+        (status = 400, description = "The token is invalid or has expired")
+    )
+)]
+#[get("/verify")]
+async fn verify_email_handler(
+    app: web::Data<AppState>,
+    query: web::Query<VerifyEmailParams>,
+) -> Result<HttpResponse, Error> {
+    let claims = decode_mail_token(&app.get_config().jwt_secret, VERIFY_EMAIL, &query.token)
+        .map_err(|_| ServiceError::BadRequest("Invalid or expired token".to_string()))?;
+    let user_id = claims.sub.parse::<i32>()
+        .map_err(|_| ServiceError::BadRequest("Invalid or expired token".to_string()))?;
+
+    let mut conn = app.get_connection().map_err(|err| ServiceError::InternalServerError(err.to_string()))?;
+    match activate_user(&mut conn, user_id, &claims.email) {
+        // The user is gone or changed their email address since the mail was sent
+        Ok(0) => Err(ServiceError::BadRequest("Invalid or expired token".to_string()).into()),
+        Ok(_) => {
+            info!("Verified the email address of user: {}", user_id);
+            Ok(HttpResponse::Ok().json(json!({"status": "success"})))
+        }
+        Err(err) => Err(ServiceError::InternalServerError(err.to_string()).into()),
+    }
+}
+
+///
+/// Mails a new verification link to a user that has not verified their email address
+///
+/// Always succeeds, so it does not tell which addresses are registered
+///
+#[utoipa::path(
+    post,
+    tag = "Authentication",
+    path = "/api/auth/verify/resend",
+    request_body = ResendVerificationDto,
+    responses(
+        (status = 200, description = "A verification mail was sent if the address belongs to an unverified user")
+    )
+)]
+#[post("/verify/resend")]
+async fn resend_verification_handler(
+    app: web::Data<AppState>,
+    web::Json(body): web::Json<ResendVerificationDto>,
+) -> Result<HttpResponse, Error> {
+    let mut conn = app.get_connection().map_err(|err| ServiceError::InternalServerError(err.to_string()))?;
+    if let Ok(user) = get_user_by_email(&mut conn, &body.email) && !user.active {
+        drop(conn);
+        if let Err(err) = send_verification_mail(&app, &user).await {
+            log::error!("Failed to send the verification mail to user: {}, Error: {}", user.username, err);
+        }
+    }
+    Ok(HttpResponse::Ok().json(json!({"status": "success"})))
+}
+
+async fn send_verification_mail(app: &AppState, user: &UserDto) -> Result<(), DbError> {
+    let config = app.get_config();
+    let user_id = user.id.ok_or("User has no id")?;
+    let token = create_mail_token(&config.jwt_secret, VERIFY_EMAIL, user_id, &user.email_address, config.email_verification_hours)?;
+    let mail = Mail {
+        to: user.email_address.clone(),
+        subject: "Verify your email address".to_string(),
+        body: format!(
+            "Hello {},\n\nPlease verify your email address by opening the link below, it expires in {} hours:\n\n{}/api/auth/verify?token={}\n",
+            user.username, config.email_verification_hours, config.public_url, token),
+    };
+    app.get_mail_service().send_mail(&mail).await?;
+    Ok(())
+}
+
 ///
 ///  Login a user
 ///
@@ -87,9 +207,9 @@ pub async fn register_user_handler(
 #[post("/login")]
 async fn login_user_handler(
     app: web::Data<AppState>,
+    req: HttpRequest,
     web::Json(body): web::Json<LoginRequestDto>,
 ) -> Result<HttpResponse, Error> {
-    let secret = app.get_config().jwt_secret.clone();
     // let user = web::block(move || {
     //     let mut conn = app.get_connection()?;
     //     find_user_by_username_and_password(&mut conn, body.username, body.password)
@@ -97,12 +217,16 @@ async fn login_user_handler(
     // .await?
     // .map_err(|err| ServiceError::BadRequest(err.to_string()))?;
 
-    info!("Attempting to login user: {}/{}", body.username, body.password);
+    info!("Attempting to login user: {}", body.username);
 
     
     let mut conn = app.get_connection().map_err(|err| ServiceError::InternalServerError(err.to_string()))?;
     let user = find_user_by_username_and_password(&mut conn, body.username, body.password).map_err(|err| ServiceError::NotFound(err.to_string()))?;
 
+    if app.get_config().require_email_verification && !user.active {
+        return Err(ServiceError::Forbidden("The email address has not been verified yet".to_string()).into());
+    }
+
 
     // let parsed_hash = PasswordHash::new(&user.password).unwrap();
     // let is_valid = Argon2::default()
@@ -114,35 +238,241 @@ async fn login_user_handler(
     //         .json(json!({"status": "fail", "message": "Invalid email or password"})));
     // }
 
+    let user_id = user.id.unwrap();  // If an object is returned then it must have an id
+    let user_agent = req.headers().get(header::USER_AGENT).and_then(|value| value.to_str().ok()).map(String::from);
+    let ip_address = req.connection_info().realip_remote_addr().map(String::from);
+    let (session, refresh_token) = create_session(&mut conn, user_id, user_agent, ip_address, app.get_config().refresh_token_days)
+        .map_err(|err| ServiceError::InternalServerError(err.to_string()))?;
+
+    info!("Started session: {} for user: {}", session.id, user_id);
+    session_response(&app, &session, refresh_token, user)
+}
+
+///
+/// Swaps a refresh token for a new access token and refresh token
+///
+/// The refresh token is taken from the body or else from the `refresh_token` cookie, and works only once.
+/// Using a refresh token a second time revokes its session, as it must have been copied
+///
+#[utoipa::path(
+    post,
+    tag = "Authentication",
+    path = "/api/auth/refresh",
+    request_body = RefreshTokenDto,
+    responses(
+        (status = 200, description = "Successfully refreshed the session", body = [LoginResponseDto]),
+        (status = 401, description = "The refresh token is invalid, used, revoked or has expired")
+    )
+)]
+#[post("/refresh")]
+async fn refresh_handler(
+    app: web::Data<AppState>,
+    req: HttpRequest,
+    body: Option<web::Json<RefreshTokenDto>>,
+) -> Result<HttpResponse, Error> {
+    let token = body
+        .and_then(|body| body.into_inner().refresh_token)
+        .or_else(|| req.cookie(REFRESH_COOKIE).map(|cookie| cookie.value().to_string()))
+        .ok_or(ServiceError::Unauthorized)?;
+
+    let days = app.get_config().refresh_token_days;
+    let mut conn = app.get_connection().map_err(|err| ServiceError::InternalServerError(err.to_string()))?;
+    let refreshed = web::block(move || {
+        let Some((session, refresh_token)) = rotate_session(&mut conn, &token, days)? else {
+            return Ok(None);
+        };
+        let user = get_user(&mut conn, session.user_id)?;
+        Ok::<_, DbError>(Some((session, refresh_token, user)))
+    })
+    .await?
+    .map_err(|err| ServiceError::InternalServerError(err.to_string()))?;
+
+    match refreshed {
+        Some((session, refresh_token, user)) => session_response(&app, &session, refresh_token, user),
+        None => Err(ServiceError::Unauthorized.into()),
+    }
+}
+
+// Answers a login or refresh with a new access token for the session, both tokens are also set as cookies
+fn session_response(app: &AppState, session: &SessionDto, refresh_token: String, user: UserDto) -> Result<HttpResponse, Error> {
     let now = Utc::now();
-    let iat = now.timestamp() as usize;
-    let exp = (now + Duration::minutes(60)).timestamp() as usize;
-    let claims: TokenClaims = TokenClaims {
-        sub: user.id.unwrap().to_string(),  // If an object is returned then it must have an id
-        exp,
-        iat,
+    let claims = TokenClaims {
+        sub: session.user_id.to_string(),
+        sid: session.id.clone(),
+        iat: now.timestamp() as usize,
+        exp: (now + Duration::minutes(ACCESS_TOKEN_MINUTES)).timestamp() as usize,
     };
 
     let token = encode(
         &Header::default(),
         &claims,
-        &EncodingKey::from_secret(secret.as_ref()),
+        &EncodingKey::from_secret(app.get_config().jwt_secret.as_ref()),
     )
-    .unwrap();
+    .map_err(|err| ServiceError::InternalServerError(err.to_string()))?;
 
     let cookie = Cookie::build("token", token.to_owned())
         .path("/")
-        .max_age(ActixWebDuration::new(60 * 60, 0))
+        .max_age(ActixWebDuration::minutes(ACCESS_TOKEN_MINUTES))
+        .http_only(true)
+        .finish();
+    let refresh_cookie = Cookie::build(REFRESH_COOKIE, refresh_token.to_owned())
+        .path("/api/auth")
+        .max_age(ActixWebDuration::days(app.get_config().refresh_token_days))
         .http_only(true)
         .finish();
 
-    Ok(HttpResponse::Ok().cookie(cookie).json(LoginResponseDto {
+    Ok(HttpResponse::Ok().cookie(cookie).cookie(refresh_cookie).json(LoginResponseDto {
         status: String::from("success"),
         token,
+        refresh_token,
         user, //UserProfileDto::from(user),
     }))
 }
 
+// Removes the token cookies, at logout or when the session was ended otherwise
+fn clear_session_cookies(response: &mut HttpResponseBuilder) -> &mut HttpResponseBuilder {
+    let cookie = Cookie::build("token", "")
+        .path("/")
+        .max_age(ActixWebDuration::new(-1, 0))
+        .http_only(true)
+        .finish();
+    let refresh_cookie = Cookie::build(REFRESH_COOKIE, "")
+        .path("/api/auth")
+        .max_age(ActixWebDuration::new(-1, 0))
+        .http_only(true)
+        .finish();
+    response.cookie(cookie).cookie(refresh_cookie)
+}
+
+///
+/// Mails a link to reset the password to the user with the email address
+///
+/// The link works once and expires after `PASSWORD_RESET_MINUTES`, an earlier link stops working.
+/// Always succeeds, so it does not tell which addresses are registered
+///
+#[utoipa::path(
+    post,
+    tag = "Authentication",
+    path = "/api/auth/password/forgot",
+    request_body = ForgotPasswordDto,
+    responses(
+        (status = 200, description = "A password reset mail was sent if the address belongs to a user")
+    )
+)]
+#[post("/password/forgot")]
+async fn forgot_password_handler(
+    app: web::Data<AppState>,
+    web::Json(body): web::Json<ForgotPasswordDto>,
+) -> Result<HttpResponse, Error> {
+    let config = app.get_config();
+    let mut conn = app.get_connection().map_err(|err| ServiceError::InternalServerError(err.to_string()))?;
+    let Ok(user) = get_user_by_email(&mut conn, &body.email) else {
+        return Ok(HttpResponse::Ok().json(json!({"status": "success"})));
+    };
+    let Some(user_id) = user.id else {
+        return Ok(HttpResponse::Ok().json(json!({"status": "success"})));
+    };
+
+    let token = create_password_reset(&mut conn, user_id, config.password_reset_minutes)
+        .map_err(|err| ServiceError::InternalServerError(err.to_string()))?;
+    drop(conn);
+
+    let mail = Mail {
+        to: user.email_address.clone(),
+        subject: "Reset your password".to_string(),
+        body: format!(
+            "Hello {},\n\nYou can choose a new password by opening the link below, it expires in {} minutes:\n\n{}?token={}\n\nIf you did not ask for this, you can ignore this mail.\n",
+            user.username, config.password_reset_minutes, config.password_reset_url, token),
+    };
+    if let Err(err) = app.get_mail_service().send_mail(&mail).await {
+        log::error!("Failed to send the password reset mail to user: {}, Error: {}", user.username, err);
+    }
+    Ok(HttpResponse::Ok().json(json!({"status": "success"})))
+}
+
+///
+/// Sets a new password with the token from the password reset mail
+///
+/// Everyone logged in as the user is logged out
+///
+#[utoipa::path(
+    post,
+    tag = "Authentication",
+    path = "/api/auth/password/reset",
+    request_body = ResetPasswordDto,
+    responses(
+        (status = 200, description = "Successfully reset the password of the user"),
+        (status = 400, description = "The token is invalid, used or has expired")
+    )
+)]
+#[post("/password/reset")]
+async fn reset_password_handler(
+    app: web::Data<AppState>,
+    web::Json(body): web::Json<ResetPasswordDto>,
+) -> Result<HttpResponse, Error> {
+    check_password(&body.password)?;
+
+    let mut conn = app.get_connection().map_err(|err| ServiceError::InternalServerError(err.to_string()))?;
+    let user_id = web::block(move || reset_password(&mut conn, &body.token, &body.password))
+        .await?
+        .map_err(|err| ServiceError::InternalServerError(err.to_string()))?
+        .ok_or(ServiceError::BadRequest("Invalid or expired token".to_string()))?;
+
+    info!("Reset the password of user: {}", user_id);
+    Ok(HttpResponse::Ok().json(json!({"status": "success"})))
+}
+
+///
+/// Changes the password of the current user
+///
+/// Requires the current password. Every session of the user ends, including this one, so they login again
+///
+#[utoipa::path(
+    post,
+    tag = "Authentication",
+    path = "/api/auth/password/change",
+    request_body = ChangePasswordDto,
+    responses(
+        (status = 200, description = "Successfully changed the password of the user"),
+        (status = 401, description = "The current password is wrong")
+    )
+)]
+#[post("/password/change")]
+async fn change_password_handler(
+    app: web::Data<AppState>,
+    jwt: jwt_auth::JwtMiddleware,
+    web::Json(body): web::Json<ChangePasswordDto>,
+) -> Result<HttpResponse, Error> {
+    let user_id = jwt.user_id;
+    check_password(&body.new_password)?;
+
+    let mut conn = app.get_connection().map_err(|err| ServiceError::InternalServerError(err.to_string()))?;
+    let changed = web::block(move || {
+        if !verify_password(&mut conn, user_id, &body.current_password)? {
+            return Ok(false);
+        }
+        set_password(&mut conn, user_id, &body.new_password)?;
+        Ok::<_, DbError>(true)
+    })
+    .await?
+    .map_err(|err| ServiceError::InternalServerError(err.to_string()))?;
+
+    if !changed {
+        return Err(ServiceError::Unauthorized.into());
+    }
+
+    info!("Changed the password of user: {}", user_id);
+    Ok(clear_session_cookies(&mut HttpResponse::Ok())
+        .json(json!({"status": "success"})))
+}
+
+fn check_password(password: &str) -> Result<(), ServiceError> {
+    match password.chars().count() >= MIN_PASSWORD_LENGTH {
+        true => Ok(()),
+        false => Err(ServiceError::BadRequest(format!("The password must have at least {} characters", MIN_PASSWORD_LENGTH))),
+    }
+}
+
 ///
 /// Logout a user
 ///
@@ -155,15 +485,12 @@ async fn login_user_handler(
     )
 )]
 #[post("/logout")]
-async fn logout_handler(_: jwt_auth::JwtMiddleware) -> Result<HttpResponse, Error> {
-    let cookie = Cookie::build("token", "")
-        .path("/")
-        .max_age(ActixWebDuration::new(-1, 0))
-        .http_only(true)
-        .finish();
+async fn logout_handler(app: web::Data<AppState>,
+                        jwt: jwt_auth::JwtMiddleware) -> Result<HttpResponse, Error> {
+    let mut conn = app.get_connection().map_err(|err| ServiceError::InternalServerError(err.to_string()))?;
+    revoke_session(&mut conn, &jwt.session_id, jwt.user_id).map_err(|err| ServiceError::InternalServerError(err.to_string()))?;
 
-    Ok(HttpResponse::Ok()
-        .cookie(cookie)
+    Ok(clear_session_cookies(&mut HttpResponse::Ok())
         .json(json!({"status": "success"})))
 }
 
@@ -186,12 +513,51 @@ async fn user_handler(app: web::Data<AppState>,
     Ok(HttpResponse::Ok().json(user))
 }
 
+///
+/// Gets the storage used by the current user
+///
+/// Shows the bytes used against the user's quota and how they are spread over the folders
+///
+#[utoipa::path(
+    get,
+    tag = "Authentication",
+    path = "/api/auth/user/usage",
+    responses(
+        (status = 200, description = "Successfully retrieved the storage usage of the user", body = StorageUsageDto)
+    )
+)]
+#[get("/user/usage")]
+async fn user_usage_handler(app: web::Data<AppState>,
+                        user: jwt_auth::JwtMiddleware) -> Result<HttpResponse, Error> {
+    let mut conn = app.get_connection().map_err(|err| ServiceError::InternalServerError(err.to_string()))?;
+    let (used, quota) = get_storage_usage(&mut conn, user.user_id).map_err(|err| ServiceError::NotFound(err.to_string()))?;
+    let quota = quota.unwrap_or(app.get_config().default_storage_quota);
+    let trash = get_trash_usage(&mut conn, user.user_id).map_err(|err| ServiceError::InternalServerError(err.to_string()))?;
+    let versions = get_version_usage(&mut conn, user.user_id).map_err(|err| ServiceError::InternalServerError(err.to_string()))?;
+    let folders = get_folder_usage(&mut conn, user.user_id).map_err(|err| ServiceError::InternalServerError(err.to_string()))?;
+
+    Ok(HttpResponse::Ok().json(StorageUsageDto {
+        used,
+        quota,
+        available: (quota - used).max(0),
+        trash,
+        versions,
+        folders,
+    }))
+}
 
 pub fn config(conf: &mut web::ServiceConfig) {
     let scope = web::scope("/auth")
         .service(login_user_handler)
+        .service(refresh_handler)
         .service(logout_handler)
         .service(register_user_handler)
-        .service(user_handler);
+        .service(verify_email_handler)
+        .service(resend_verification_handler)
+        .service(forgot_password_handler)
+        .service(reset_password_handler)
+        .service(change_password_handler)
+        .service(user_handler)
+        .service(user_usage_handler);
     conf.service(scope);
 }
@@ -1,19 +1,27 @@
-use crate::file_store::FileStore;
-use crate::shared::common::{DbError,};
+use crate::shared::common::{DbError, StorageService};
 use crate::shared::dto::{NewUserDto, UserDto, CreateUser, User};
+use super::dto::{FolderUsageDto, PasswordResetDto};
 use argon2::{PasswordHash, PasswordVerifier};
+use super::tokens::{generate_token, hash_token};
+use crate::sessions::service::revoke_sessions;
+use diesel::dsl::sql;
 use diesel::insert_into;
+use diesel::sql_types::{BigInt, Bool, Text};
+use std::collections::HashMap;
 use diesel::{prelude::*};
 use uuid::Uuid;
 
 use crate::shared::common::Connection;
-use crate::schema::{users};
+use crate::schema::{file_folders, file_versions, files, password_resets, users};
 
 use argon2::{
     password_hash::{rand_core::OsRng, PasswordHasher, SaltString},
     Argon2,
 };
 
+// Email addresses are compared without their case, like the unique index on them
+define_sql_function!(fn lower(text: Text) -> Text);
+
 pub fn get_user(conn: &mut SqliteConnection, user_id: i32) -> Result<UserDto, DbError> {
     let user = users::dsl::users
         .filter(users::id.eq(user_id))
@@ -22,6 +30,15 @@ pub fn get_user(conn: &mut SqliteConnection, user_id: i32) -> Result<UserDto, Db
     Ok(user)
 }
 
+pub fn get_user_by_username(conn: &mut SqliteConnection, username: &str) -> Result<UserDto, DbError> {
+    let user = users::dsl::users
+        .filter(users::username.eq(username))
+        .select((users::id, users::username, users::email_address, users::folder_id,users::active))
+        .first::<UserDto>(conn)
+        .map_err(|err| format!("Error loading user username: {}, Error: {}", username, err))?;
+    Ok(user)
+}
+
 pub fn find_user_by_username_and_password(conn: &mut Connection, username: String, password: String) -> Result<UserDto, DbError> {
     let user = users::dsl::users
         .filter(users::username.eq(username))
@@ -56,16 +73,30 @@ pub fn is_exists(conn: &mut Connection, username: String) -> Result<bool, DbErro
     Ok(exists == 1)
 }
 
+pub fn is_email_taken(conn: &mut Connection, email_address: &str) -> Result<bool, DbError> {
+    let taken: i64 = users::dsl::users
+        .filter(lower(users::email_address).eq(email_address.to_lowercase()))
+        .count()
+        .get_result(conn)?;
+    Ok(taken > 0)
+}
+
+///
+/// Checks whether an insert failed because the username or email address is taken already
+///
+pub fn is_unique_violation(err: &DbError) -> bool {
+    matches!(
+        err.downcast_ref::<diesel::result::Error>(),
+        Some(diesel::result::Error::DatabaseError(diesel::result::DatabaseErrorKind::UniqueViolation, _))
+    )
+}
+
 pub fn create_user(
     conn: &mut Connection,
-    storage: &FileStore,
+    storage: &dyn StorageService,
     new_user : NewUserDto,
 ) -> Result<bool, DbError> {
-    let salt = SaltString::generate(&mut OsRng);
-    let hashed_password = Argon2::default()
-        .hash_password(new_user.password.as_bytes(), &salt)
-        .expect("Error while hashing password")
-        .to_string();
+    let hashed_password = hash_password(&new_user.password)?;
 
     let uuid = Uuid::new_v4().to_string();
 
@@ -81,7 +112,237 @@ pub fn create_user(
     // Create user object
     let num_records = insert_into(users::dsl::users).values(user).execute(conn)?;
 
-    storage.create_folder(uuid)?;
+    storage.create_folder(&uuid)?;
 
     Ok(num_records == 1)
 }
+
+///
+/// Bytes used by the user and their own quota, if they have one
+///
+pub fn get_storage_usage(conn: &mut SqliteConnection, user_id: i32) -> Result<(i64, Option<i64>), DbError> {
+    Ok(users::dsl::users
+        .filter(users::id.eq(user_id))
+        .select((users::storage_used, users::storage_quota))
+        .first::<(i64, Option<i64>)>(conn)?)
+}
+
+///
+/// Bytes the user can still store, their own quota takes precedence over `default_quota`
+///
+pub fn get_available_space(conn: &mut SqliteConnection, user_id: i32, default_quota: i64) -> Result<i64, DbError> {
+    let (used, quota) = get_storage_usage(conn, user_id)?;
+    Ok((quota.unwrap_or(default_quota) - used).max(0))
+}
+
+///
+/// Adds `size` bytes to the user's usage if they still fit their quota
+///
+/// Checks and adds in one statement, so parallel uploads can't take the user over their quota
+/// together. False when the bytes don't fit, nothing is added then
+///
+pub fn reserve_storage(conn: &mut SqliteConnection, user_id: i32, size: i64, default_quota: i64) -> Result<bool, DbError> {
+    // Giving space back always fits
+    if size <= 0 {
+        add_storage_used(conn, user_id, size)?;
+        return Ok(true);
+    }
+
+    let fits = sql::<Bool>("storage_used + ")
+        .bind::<BigInt, _>(size)
+        .sql(" <= COALESCE(storage_quota, ")
+        .bind::<BigInt, _>(default_quota)
+        .sql(")");
+    let reserved = diesel::update(users::dsl::users.filter(users::id.eq(user_id)).filter(fits))
+        .set(users::storage_used.eq(users::storage_used + size))
+        .execute(conn)?;
+    Ok(reserved == 1)
+}
+
+pub fn add_storage_used(conn: &mut SqliteConnection, user_id: i32, delta: i64) -> Result<usize, DbError> {
+    Ok(diesel::update(users::dsl::users.filter(users::id.eq(user_id)))
+        .set(users::storage_used.eq(users::storage_used + delta))
+        .execute(conn)?)
+}
+
+///
+/// Bytes used by the files directly in each of the user's folders, trashed files are not included
+///
+pub fn get_folder_usage(conn: &mut SqliteConnection, user_id: i32) -> Result<Vec<FolderUsageDto>, DbError> {
+    let usage = files::table
+        .filter(files::owner_id.eq(user_id).and(files::active.eq(true)))
+        .group_by(files::folder_id)
+        .select((files::folder_id, sql::<BigInt>("COALESCE(SUM(files.size), 0)"), diesel::dsl::count(files::id)))
+        .load::<(String, i64, i64)>(conn)?;
+
+    // The user's root folder has no record, so it has no title
+    let titles: HashMap<String, String> = file_folders::table
+        .filter(file_folders::id.eq_any(usage.iter().map(|(folder_id, _, _)| folder_id)))
+        .select((file_folders::id, file_folders::title))
+        .load::<(String, String)>(conn)?
+        .into_iter()
+        .collect();
+
+    Ok(usage
+        .into_iter()
+        .map(|(folder_id, used, file_count)| FolderUsageDto {
+            title: titles.get(&folder_id).cloned(),
+            folder_id,
+            used,
+            file_count,
+        })
+        .collect())
+}
+
+///
+/// Bytes used by the files in the trash, with all their versions
+///
+pub fn get_trash_usage(conn: &mut SqliteConnection, user_id: i32) -> Result<i64, DbError> {
+    Ok(file_versions::table
+        .inner_join(files::table.on(files::id.eq(file_versions::file_id)))
+        .filter(files::owner_id.eq(user_id).and(files::active.eq(false)))
+        .select(sql::<BigInt>("COALESCE(SUM(file_versions.size), 0)"))
+        .first::<i64>(conn)?)
+}
+
+///
+/// Bytes used by the versions of the user's files that are not their current contents
+///
+pub fn get_version_usage(conn: &mut SqliteConnection, user_id: i32) -> Result<i64, DbError> {
+    let versions: i64 = file_versions::table
+        .inner_join(files::table.on(files::id.eq(file_versions::file_id)))
+        .filter(files::owner_id.eq(user_id))
+        .select(sql::<BigInt>("COALESCE(SUM(file_versions.size), 0)"))
+        .first(conn)?;
+    let current: i64 = files::table
+        .filter(files::owner_id.eq(user_id))
+        .select(sql::<BigInt>("COALESCE(SUM(files.size), 0)"))
+        .first(conn)?;
+    Ok((versions - current).max(0))
+}
+
+///
+/// The user with the email address, the case of the address doesn't matter
+///
+pub fn get_user_by_email(conn: &mut SqliteConnection, email_address: &str) -> Result<UserDto, DbError> {
+    let user = users::dsl::users
+        .filter(lower(users::email_address).eq(email_address.to_lowercase()))
+        .select((users::id, users::username, users::email_address, users::folder_id, users::active))
+        .first::<UserDto>(conn)?;
+    Ok(user)
+}
+
+///
+/// Activates the user, as long as `email_address` is still their address
+///
+pub fn activate_user(conn: &mut SqliteConnection, user_id: i32, email_address: &str) -> Result<usize, DbError> {
+    Ok(diesel::update(users::dsl::users
+            .filter(users::id.eq(user_id))
+            .filter(users::email_address.eq(email_address)))
+        .set((
+            users::active.eq(true),
+            users::updated_by.eq(user_id),
+            users::updated_at.eq(chrono::Utc::now().naive_utc())))
+        .execute(conn)?)
+}
+
+pub fn hash_password(password: &str) -> Result<String, DbError> {
+    let salt = SaltString::generate(&mut OsRng);
+    Ok(Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .map_err(|err| format!("Error while hashing password: {}", err))?
+        .to_string())
+}
+
+pub fn verify_password(conn: &mut SqliteConnection, user_id: i32, password: &str) -> Result<bool, DbError> {
+    let hash = users::dsl::users
+        .filter(users::id.eq(user_id))
+        .select(users::password)
+        .first::<String>(conn)?;
+    Ok(PasswordHash::new(&hash)
+        .is_ok_and(|hash| Argon2::default().verify_password(password.as_bytes(), &hash).is_ok()))
+}
+
+///
+/// Replaces the password of the user and revokes all of their sessions
+///
+pub fn set_password(conn: &mut SqliteConnection, user_id: i32, password: &str) -> Result<usize, DbError> {
+    let hashed_password = hash_password(password)?;
+    let now = chrono::Utc::now().naive_utc();
+    let updated = diesel::update(users::dsl::users.filter(users::id.eq(user_id)))
+        .set((
+            users::password.eq(hashed_password),
+            users::updated_by.eq(user_id),
+            users::updated_at.eq(now)))
+        .execute(conn)?;
+    revoke_sessions(conn, user_id, None)?;
+    Ok(updated)
+}
+
+///
+/// Creates a password reset token for the user that expires after `minutes`
+///
+/// Only the hash of the token is stored, and the user's earlier tokens stop working
+///
+pub fn create_password_reset(conn: &mut SqliteConnection, user_id: i32, minutes: i64) -> Result<String, DbError> {
+    let token = generate_token();
+
+    let now = chrono::Utc::now().naive_utc();
+    let reset = PasswordResetDto {
+        id: Uuid::new_v4().to_string(),
+        user_id,
+        token_hash: hash_token(&token),
+        expires_at: now + chrono::Duration::minutes(minutes),
+        used_at: None,
+        created_at: None,
+        updated_at: None,
+        created_by: user_id,
+        updated_by: user_id,
+        active: true,
+    };
+
+    conn.immediate_transaction(|conn| {
+        diesel::update(password_resets::table
+                .filter(password_resets::user_id.eq(user_id))
+                .filter(password_resets::active.eq(true)))
+            .set((
+                password_resets::active.eq(false),
+                password_resets::updated_at.eq(now)))
+            .execute(conn)?;
+        diesel::insert_into(password_resets::table)
+            .values(&reset)
+            .execute(conn)?;
+        Ok::<_, DbError>(())
+    })?;
+
+    Ok(token)
+}
+
+///
+/// Sets a new password with a password reset token, None when the token is unknown, used or expired
+///
+pub fn reset_password(conn: &mut SqliteConnection, token: &str, password: &str) -> Result<Option<i32>, DbError> {
+    let now = chrono::Utc::now().naive_utc();
+    conn.immediate_transaction(|conn| {
+        let reset = password_resets::table
+            .filter(password_resets::token_hash.eq(hash_token(token)))
+            .filter(password_resets::active.eq(true))
+            .filter(password_resets::used_at.is_null())
+            .filter(password_resets::expires_at.gt(now))
+            .first::<PasswordResetDto>(conn)
+            .optional()?;
+        let Some(reset) = reset else {
+            return Ok(None);
+        };
+
+        diesel::update(password_resets::table.filter(password_resets::id.eq(&reset.id)))
+            .set((
+                password_resets::used_at.eq(now),
+                password_resets::active.eq(false),
+                password_resets::updated_at.eq(now)))
+            .execute(conn)?;
+        set_password(conn, reset.user_id, password)?;
+
+        Ok(Some(reset.user_id))
+    })
+}
@@ -0,0 +1,51 @@
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use base64::Engine;
+use chrono::{Duration, Utc};
+use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Header, Validation};
+use sha2::{Digest, Sha256};
+
+use crate::shared::common::DbError;
+use super::dto::MailTokenClaims;
+
+// Purposes of the mailed tokens
+pub const VERIFY_EMAIL: &str = "verify_email";
+
+// Every purpose signs with its own key, a token for one purpose is worthless for the others
+fn purpose_key(secret: &str, purpose: &str) -> Vec<u8> {
+    format!("{}:{}", secret, purpose).into_bytes()
+}
+
+///
+/// Creates a signed token for `purpose` that expires after `hours`
+///
+pub fn create_mail_token(secret: &str, purpose: &str, user_id: i32, email: &str, hours: i64) -> Result<String, DbError> {
+    let now = Utc::now();
+    let claims = MailTokenClaims {
+        sub: user_id.to_string(),
+        email: email.to_string(),
+        iat: now.timestamp() as usize,
+        exp: (now + Duration::hours(hours)).timestamp() as usize,
+    };
+    Ok(encode(&Header::default(), &claims, &EncodingKey::from_secret(&purpose_key(secret, purpose)))?)
+}
+
+///
+/// Checks the signature and expiry of a token created for `purpose`
+///
+pub fn decode_mail_token(secret: &str, purpose: &str, token: &str) -> Result<MailTokenClaims, DbError> {
+    let key = purpose_key(secret, purpose);
+    let data = decode::<MailTokenClaims>(token, &DecodingKey::from_secret(&key), &Validation::default())?;
+    Ok(data.claims)
+}
+
+// 256 random bits, URL safe so a token fits in a link or a path segment
+pub fn generate_token() -> String {
+    let mut bytes = [0u8; 32];
+    OsRng.fill_bytes(&mut bytes);
+    base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(bytes)
+}
+
+// Hex SHA-256 of a token, so a leaked table does not give away working tokens
+pub fn hash_token(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
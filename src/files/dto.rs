@@ -1,6 +1,6 @@
 use serde::{Deserialize, Serialize};
 use utoipa::ToSchema;
-use crate::schema::files;
+use crate::schema::{files, file_versions};
 
 #[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 #[derive(Queryable, Selectable, Insertable, AsChangeset)]
@@ -23,6 +23,15 @@ pub struct FileDto {
     pub created_by: i32,
     pub updated_by: i32,
     pub active: bool,
+    // When and by whom the item was moved to the trash
+    pub deleted_at: Option<chrono::NaiveDateTime>,
+    pub deleted_by: Option<i32>,
+    // SHA-256 of the current contents in the blob store, files uploaded before it existed have none
+    pub blob_hash: Option<String>,
+    // Size of the current contents in bytes
+    pub size: i64,
+    // The group owning the file, if it is not owned by `owner_id` alone
+    pub group_id: Option<String>,
 }
 
 #[derive(Debug, Clone, Deserialize, ToSchema)]
@@ -35,3 +44,40 @@ pub struct CreateFileDto {
     pub description: Option<String>,
 }
 
+#[derive(Debug, Clone, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct UpdateFileDto {
+    // New title, renames the file
+    pub title: Option<String>,
+    pub access_level: Option<i32>,
+    // Moves the file into another folder
+    pub folder_id: Option<String>,
+    pub description: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ArchiveFilesDto {
+    // The files to download, the user needs to be able to read all of them
+    pub file_ids: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+#[derive(Queryable, Selectable, Insertable)]
+#[diesel(table_name = file_versions)]
+#[serde(rename_all = "camelCase")]
+pub struct FileVersionDto {
+    pub id: String,
+    pub file_id: String,
+    // Starts at 1 and increases with every upload
+    pub version: i32,
+    // Size of the contents in bytes
+    pub size: i64,
+    // Hex encoded SHA-256 of the contents
+    pub checksum: String,
+    pub media_type: Option<String>,
+    pub orginal_filename: Option<String>,
+    pub created_at: Option<chrono::NaiveDateTime>,
+    // The ID of the user who uploaded the version
+    pub created_by: i32,
+}
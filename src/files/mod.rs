@@ -3,32 +3,65 @@ pub mod service;
 
 use actix_multipart::Multipart;
 use actix_web::{
-    get, post, web, Error, HttpResponse,
+    delete, get, patch, post, web, Error, HttpRequest, HttpResponse,
 };
-
+use actix_web::http::header::{
+    ContentRange, ContentRangeSpec, ETag, EntityTag, Header, HttpDate, IfModifiedSince, IfNoneMatch,
+    IfRange, LastModified, Range, ACCEPT_RANGES, IF_NONE_MATCH,
+};
+use actix_web::http::StatusCode;
+use std::cell::Cell;
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::rc::Rc;
+use std::time::{Duration, UNIX_EPOCH};
+
+use diesel::SqliteConnection;
 use futures_util::TryStreamExt;
 use log::info;
+use uuid::Uuid;
 
 use crate::auth::jwt_auth;
+use crate::auth::service::{add_storage_used, get_available_space, reserve_storage};
+use crate::folders::service::is_valid_parent;
+use crate::groups::dto::Owner;
+use crate::groups::service::get_owner_root;
 use crate::get_user;
+use crate::previews::dto::ThumbnailParams;
+use crate::previews::{generate_thumbnails, has_thumbnails, thumbnail_location, thumbnail_stream, DEFAULT_THUMBNAIL_SIZE, THUMBNAIL_SIZES};
+use crate::shared::archive::Archive;
 use crate::shared::common::ServiceError;
 use crate::shared::common::AppState;
-use crate::shared::common::build_full_path;
-use crate::shared::dto::{CreateResponseDto, QueryParams};
-use service::{get_file, get_file_contents, create_file, get_all_files, update_file};
-
-use dto::{FileDto, CreateFileDto};
+use crate::shared::common::{build_full_path, build_version_path, ByteStream};
+use crate::shared::dto::UserDto;
+use crate::shared::dto::{CreateResponseDto, PageDto, QueryParams};
+use crate::shared::media_type::sniff_media_type;
+use crate::jobs::dto::Job;
+use crate::jobs::service::enqueue_job;
+use crate::shared::quota::quota_stream;
+use crate::sharing::dto::Permission;
+use crate::sharing::service::get_folder_owner;
+use crate::storage::blob_store::BlobStore;
+use crate::storage::service::{get_blob, release_blob_reference};
+use service::{get_file, create_file, get_files_page, update_file, trash_file};
+use service::{get_file_versions, get_file_version, next_version_number, create_file_version};
+
+use dto::{FileDto, CreateFileDto, UpdateFileDto, FileVersionDto, ArchiveFilesDto};
 
 ///
 /// Gets all files for a user
 ///
+/// The files are returned a page at a time (`page`, `pageSize`) sorted by `title`, `created`,
+/// `updated` or `size` in `asc` or `desc` order. They can be filtered by `folderId`, `mediaType`
+/// (`image/*` for all images), `modifiedAfter`/`modifiedBefore` and `active`
+///
 #[utoipa::path(
     get,
     tag = "Files",
     path = "/api/files",
 
     responses(
-        (status = 200, description = "Successfully retrieved all files", body = [Vec<FileDto>])
+        (status = 200, description = "Successfully retrieved all files", body = [PageDto<FileDto>])
     )
 )]
 #[get("")]
@@ -43,7 +76,14 @@ pub async fn get_all_files_handler(
         .get_connection()
         .map_err(|err| ServiceError::NotFound(err.to_string()))?;
 
-    match get_all_files(&mut conn, user_id, query.into_inner()) {
+    // Files in a group's folder or a folder shared with the user belong to the folder's owner
+    let query = query.into_inner();
+    let owner = match &query.folder_id {
+        Some(folder_id) => get_folder_owner(&mut conn, folder_id, user_id, Permission::Read).map_err(|err| ServiceError::NotFound(err.to_string()))?,
+        None => Owner::User(user_id),
+    };
+
+    match get_files_page(&mut conn, &owner, &query) {
         Ok(files) => Ok(HttpResponse::Ok().json(files)),
         Err(err) => Err(ServiceError::NotFound(err.to_string()).into()),
     }
@@ -76,7 +116,7 @@ pub async fn get_file_handler(
         .get_connection()
         .map_err(|err| ServiceError::NotFound(err.to_string()))?;
 
-    match get_file(&mut conn, &file_id, user_id) {
+    match get_file(&mut conn, &file_id, user_id, Permission::Read) {
         Ok(file) => Ok(HttpResponse::Ok().json(file)),
         Err(err) => Err(ServiceError::NotFound(err.to_string()).into()),
     }
@@ -106,12 +146,121 @@ pub async fn create_file_handler(
         .get_connection()
         .map_err(|err| ServiceError::NotFound(err.to_string()))?;
 
-    match create_file(&mut conn, file, user_id) {
+    // Files belong to the owner of the folder, a group or the user that shared it with write access
+    let owner = get_folder_owner(&mut conn, &file.folder_id, user_id, Permission::Write).map_err(|err| ServiceError::NotFound(err.to_string()))?;
+
+    match create_file(&mut conn, file, &owner, user_id) {
         Ok(uuid) => Ok(HttpResponse::Created().json(CreateResponseDto::ok_with_id(uuid))),
         Err(err) => Err(ServiceError::NotFound(err.to_string()).into()),
     }
 }
 
+///
+/// Updates a file
+///
+/// Renames the file, changes its description or access level, or moves it into another folder.
+/// Only the fields present in the request are changed.
+///
+#[utoipa::path(
+    patch,
+    tag = "Files",
+    path = "/api/files/{file_id}",
+    request_body = UpdateFileDto,
+    responses(
+        (status = 200, description = "Successfully updated a file", body = [FileDto])
+    )
+)]
+#[patch("/{file_id}")]
+pub async fn update_file_handler(
+    app: web::Data<AppState>,
+    jwt: jwt_auth::JwtMiddleware,
+    path: web::Path<String>,
+    data: web::Json<UpdateFileDto>,
+) -> Result<HttpResponse, Error> {
+    let user_id = jwt.user_id;
+    let file_id: String = path.to_string();
+    let changes = data.into_inner();
+
+    let mut conn = app
+        .get_connection()
+        .map_err(|err| ServiceError::NotFound(err.to_string()))?;
+
+    let mut file = get_file(&mut conn, &file_id, user_id, Permission::Owner).map_err(|err| ServiceError::NotFound(err.to_string()))?;
+
+    if let Some(folder_id) = changes.folder_id.filter(|folder_id| *folder_id != file.folder_id) {
+        // Files stay with their owner, a group's files can only be moved between its folders
+        let owner = Owner::from(&file);
+        let root_folder_id = get_owner_root(&mut conn, &owner).map_err(|err| ServiceError::NotFound(err.to_string()))?;
+        if !is_valid_parent(&mut conn, &folder_id, &root_folder_id, &owner).map_err(|err| ServiceError::InternalServerError(err.to_string()))? {
+            return Err(ServiceError::BadRequest(format!("Invalid folder {}", folder_id)).into());
+        }
+
+        // Contents in the blob store do not depend on the folder, only older uploads have to be moved
+        if file.blob_hash.is_none() {
+            let from_path = build_full_path(&root_folder_id, &file.folder_id);
+            let to_path = build_full_path(&root_folder_id, &folder_id);
+            info!("Moving file: {file_id} from {from_path} to {to_path}");
+            // Files without contents have no blob to move
+            match app.get_storage_service().move_file(&from_path, &file.id, &to_path, &file.id).await {
+                Err(err) if err.kind() != std::io::ErrorKind::NotFound => {
+                    return Err(ServiceError::InternalServerError(err.to_string()).into());
+                }
+                _ => {}
+            }
+        }
+        file.folder_id = folder_id;
+    }
+    if let Some(title) = changes.title {
+        file.title = title;
+    }
+    if let Some(access_level) = changes.access_level {
+        file.access_level = access_level;
+    }
+    if let Some(description) = changes.description {
+        file.description = Some(description);
+    }
+
+    update_file(&mut conn, file, user_id).map_err(|err| ServiceError::InternalServerError(err.to_string()))?;
+
+    match get_file(&mut conn, &file_id, user_id, Permission::Read) {
+        Ok(file) => Ok(HttpResponse::Ok().json(file)),
+        Err(err) => Err(ServiceError::NotFound(err.to_string()).into()),
+    }
+}
+
+///
+/// Moves a file to the trash
+///
+/// The file can be restored from the trash until it is purged after the retention period
+///
+#[utoipa::path(
+    delete,
+    tag = "Files",
+    path = "/api/files/{file_id}",
+    responses(
+        (status = 204, description = "Successfully moved a file to the trash")
+    )
+)]
+#[delete("/{file_id}")]
+pub async fn delete_file_handler(
+    app: web::Data<AppState>,
+    jwt: jwt_auth::JwtMiddleware,
+    path: web::Path<String>,
+) -> Result<HttpResponse, Error> {
+    let user_id = jwt.user_id;
+    let file_id: String = path.to_string();
+
+    let mut conn = app
+        .get_connection()
+        .map_err(|err| ServiceError::NotFound(err.to_string()))?;
+
+    get_file(&mut conn, &file_id, user_id, Permission::Owner).map_err(|err| ServiceError::NotFound(err.to_string()))?;
+    trash_file(&mut conn, &file_id, user_id).map_err(|err| ServiceError::InternalServerError(err.to_string()))?;
+    info!("Moved file: {} to the trash for user: {}", file_id, user_id);
+
+    Ok(HttpResponse::NoContent().finish())
+}
+
 ///
 /// Uploads a file
 /// 
@@ -120,7 +269,8 @@ pub async fn create_file_handler(
     tag = "Files",
     path = "/api/files/{file_id}upload",
     responses(
-        (status = 201, description = "Successfully uploaded a file", body = [FileDto])
+        (status = 201, description = "Successfully uploaded a file", body = [FileDto]),
+        (status = 413, description = "The upload exceeds the storage quota")
     )
 )]
 #[post("/{file_id}/upload")]
@@ -135,33 +285,24 @@ pub async fn upload_file_handler(
         let file_id: String = path.to_string();
         let user_id = jwt.user_id;
         let org_filename = content_disposition.get_filename().unwrap_or("unknown").to_string();
-        let file_media_type : String= content_disposition
-            .get_filename()
-            .map(|_| "application/octet-stream".to_string())
-            .unwrap_or_else(|| "unknown".to_string());
+        let declared_media_type = field.content_type().map(|mime| mime.essence_str().to_string());
 
         let mut conn = app
             .get_connection()
             .map_err(|err| ServiceError::NotFound(err.to_string()))?;
 
-        let user = get_user(&mut conn, user_id).map_err(|err| ServiceError::NotFound(err.to_string()))?;
-        let mut file: FileDto = get_file(&mut conn, &file_id, user_id).map_err(|err| ServiceError::NotFound(err.to_string()))?;
-
-        let full_path = build_full_path(&user.folder_id, &file.folder_id,);
+        let file: FileDto = get_file(&mut conn, &file_id, user_id, Permission::Write).map_err(|err| ServiceError::NotFound(err.to_string()))?;
 
-        info!("Saving file: {file_id} to {full_path}");
+        info!("Saving file: {file_id} for user {user_id}");
 
-        let stream: &mut futures_util::stream::IntoStream<actix_multipart::Field> = &mut field.into_stream();
+        let stream: ByteStream = Box::pin(field.map_err(|err| std::io::Error::other(err.to_string())));
+        let (file_media_type, stream) = sniff_media_type(stream, declared_media_type.as_deref(), Some(&org_filename))
+            .await
+            .map_err(|err| ServiceError::BadRequest(err.to_string()))?;
 
         // upload_file(full_path, &mut stream).await.map_err(|err| ServiceError::BadRequest(err.to_string()))?;
 
-        let storage = app.get_storage_service();
-        storage.save_file(&user.folder_id, &file_id, stream).await.map_err(|err| ServiceError::InternalServerError(err.to_string()))?;
-        file.media_type = Some(file_media_type);
-        file.orginal_filename = Some(org_filename);
-
-        update_file(&mut conn, file, user_id).map_err(|err| ServiceError::InternalServerError(err.to_string()))?;
-
+        store_file_version(&app, &mut conn, file, stream, file_media_type, org_filename, user_id, 0).await?;
     }
 
     Ok(HttpResponse::Ok().body("File uploaded successfully"))
@@ -170,16 +311,179 @@ pub async fn upload_file_handler(
 ///
 /// Downloads a file
 ///
+/// Supports single `Range` requests (`206 Partial Content`) and conditional requests
+/// through `If-None-Match` / `If-Modified-Since` (`304 Not Modified`)
+///
 #[utoipa::path(
     get,
     tag = "Files",
     path = "/api/files/{file_id}/contents",
     responses(
-        (status = 200, description = "Successfully downloaded a file", body = [Vec<u8>])
+        (status = 200, description = "Successfully downloaded a file", body = [Vec<u8>]),
+        (status = 206, description = "Successfully downloaded part of a file", body = [Vec<u8>]),
+        (status = 304, description = "File has not been modified"),
+        (status = 416, description = "Requested range can not be satisfied")
     )
 )]
 #[get("/{file_id}/contents")]
 pub async fn get_file_contents_handler(
+    req: HttpRequest,
+    app: web::Data<AppState>,
+    jwt: jwt_auth::JwtMiddleware,
+    path: web::Path<String>,
+) -> Result<HttpResponse, Error> {
+    let file_id = path.to_string();
+    let user_id = jwt.user_id;
+
+    let mut conn = app
+        .get_connection()
+        .map_err(|err| ServiceError::NotFound(err.to_string()))?;
+
+    let file = get_file(&mut conn, &file_id, user_id, Permission::Read).map_err(|err| ServiceError::NotFound(err.to_string()))?;
+    let owner = get_user(&mut conn, file.owner_id).map_err(|err| ServiceError::NotFound(err.to_string()))?;
+
+    file_contents_response(&req, &app, &owner, file).await
+}
+
+///
+/// Downloads a selection of files as a ZIP archive
+///
+/// The archive is written while it is being downloaded, the files in it are named after the
+/// files they were uploaded as
+///
+#[utoipa::path(
+    post,
+    tag = "Files",
+    path = "/api/files/archive",
+    request_body = ArchiveFilesDto,
+    responses(
+        (status = 200, description = "Successfully downloaded the files", body = [Vec<u8>])
+    )
+)]
+#[post("/archive")]
+pub async fn archive_files_handler(
+    app: web::Data<AppState>,
+    jwt: jwt_auth::JwtMiddleware,
+    data: web::Json<ArchiveFilesDto>,
+) -> Result<HttpResponse, Error> {
+    let user_id = jwt.user_id;
+    if data.file_ids.is_empty() {
+        return Err(ServiceError::BadRequest("No files to download".to_string()).into());
+    }
+
+    let mut conn = app
+        .get_connection()
+        .map_err(|err| ServiceError::NotFound(err.to_string()))?;
+
+    let mut files = Vec::new();
+    for file_id in &data.file_ids {
+        let file = get_file(&mut conn, file_id, user_id, Permission::Read).map_err(|err| ServiceError::NotFound(err.to_string()))?;
+        files.push((String::new(), file));
+    }
+
+    let mut archive = Archive::default();
+    add_files_to_archive(&mut conn, &mut archive, files)?;
+    info!("Downloading {} files as an archive for user: {}", data.file_ids.len(), user_id);
+
+    Ok(archive_response(&app, archive, "Files.zip"))
+}
+
+///
+/// Downloads a thumbnail of an image
+///
+/// `size` is the longest side in pixels, one of 128, 256 (the default) or 512, `format` is
+/// either `jpeg` (the default) or `webp`. Thumbnails are made when an image is uploaded,
+/// missing ones are made when they are requested
+///
+#[utoipa::path(
+    get,
+    tag = "Files",
+    path = "/api/files/{file_id}/thumbnail",
+    responses(
+        (status = 200, description = "Successfully downloaded a thumbnail", body = [Vec<u8>]),
+        (status = 304, description = "The thumbnail has not changed since it was last downloaded"),
+        (status = 404, description = "The file has no thumbnail, it is not an image or too large")
+    )
+)]
+#[get("/{file_id}/thumbnail")]
+pub async fn get_file_thumbnail_handler(
+    req: HttpRequest,
+    app: web::Data<AppState>,
+    jwt: jwt_auth::JwtMiddleware,
+    path: web::Path<String>,
+    query: web::Query<ThumbnailParams>,
+) -> Result<HttpResponse, Error> {
+    let file_id = path.to_string();
+    let user_id = jwt.user_id;
+    let size = query.size.unwrap_or(DEFAULT_THUMBNAIL_SIZE);
+    let format = query.format.unwrap_or_default();
+    if !THUMBNAIL_SIZES.contains(&size) {
+        return Err(ServiceError::BadRequest(format!("Invalid thumbnail size {}, use one of {:?}", size, THUMBNAIL_SIZES)).into());
+    }
+
+    let mut conn = app
+        .get_connection()
+        .map_err(|err| ServiceError::NotFound(err.to_string()))?;
+
+    let file = get_file(&mut conn, &file_id, user_id, Permission::Read).map_err(|err| ServiceError::NotFound(err.to_string()))?;
+    if !file.media_type.as_deref().is_some_and(has_thumbnails) {
+        return Err(ServiceError::NotFound(format!("File {} has no thumbnail", file_id)).into());
+    }
+
+    // A thumbnail only changes with the file's contents, so its tag follows the file's
+    let etag = EntityTag::new_strong(format!("{}-{}-{}", file_etag(&file).tag(), size, format.extension()));
+    let last_modified = file_last_modified(&file);
+    let mut response = HttpResponse::Ok();
+    response.insert_header(ETag(etag.clone()));
+    if let Some(last_modified) = last_modified {
+        response.insert_header(LastModified(last_modified));
+    }
+
+    if is_not_modified(&req, &etag, last_modified) {
+        return Ok(response.status(StatusCode::NOT_MODIFIED).finish());
+    }
+
+    let owner = get_user(&mut conn, file.owner_id).map_err(|err| ServiceError::NotFound(err.to_string()))?;
+    let storage = app.get_storage_service();
+    let (path, name) = file_location(&owner, &file);
+    let (thumbnail_path, thumbnail_name) = thumbnail_location(&path, &name, size, format);
+    let stream = match storage.retrieve_file(&thumbnail_path, &thumbnail_name).await {
+        Ok(stream) => stream,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+            // Images too large or broken to decode simply have no thumbnail
+            let thumbnails = generate_thumbnails(storage.as_ref(), &path, &name)
+                .await
+                .map_err(|err| match err.kind() {
+                    std::io::ErrorKind::FileTooLarge | std::io::ErrorKind::InvalidData => ServiceError::NotFound(format!("File {} has no thumbnail", file_id)),
+                    _ => storage_error(err, &file_id),
+                })?;
+            let thumbnail = thumbnails
+                .iter()
+                .find(|thumbnail| thumbnail.size == size && thumbnail.format == format)
+                .ok_or_else(|| ServiceError::NotFound(format!("File {} has no thumbnail", file_id)))?;
+            thumbnail_stream(thumbnail)
+        }
+        Err(err) => return Err(storage_error(err, &file_id).into()),
+    };
+
+    Ok(response
+        .content_type(format.media_type())
+        .streaming(stream))
+}
+
+///
+/// Lists the versions of a file, newest first
+///
+#[utoipa::path(
+    get,
+    tag = "Files",
+    path = "/api/files/{file_id}/versions",
+    responses(
+        (status = 200, description = "Successfully retrieved the versions of a file", body = [Vec<FileVersionDto>])
+    )
+)]
+#[get("/{file_id}/versions")]
+pub async fn get_file_versions_handler(
     app: web::Data<AppState>,
     jwt: jwt_auth::JwtMiddleware,
     path: web::Path<String>,
@@ -191,19 +495,420 @@ pub async fn get_file_contents_handler(
         .get_connection()
         .map_err(|err| ServiceError::NotFound(err.to_string()))?;
 
-    let user = get_user(&mut conn, user_id).map_err(|err| ServiceError::NotFound(err.to_string()))?;
-    let file = get_file(&mut conn, &file_id, user_id).map_err(|err| ServiceError::NotFound(err.to_string()))?;
-    if file.owner_id != user_id {
-        return Err(ServiceError::Unauthorized.into());
+    get_file(&mut conn, &file_id, user_id, Permission::Read).map_err(|err| ServiceError::NotFound(err.to_string()))?;
+
+    match get_file_versions(&mut conn, &file_id) {
+        Ok(versions) => Ok(HttpResponse::Ok().json(versions)),
+        Err(err) => Err(ServiceError::NotFound(err.to_string()).into()),
     }
+}
+
+///
+/// Downloads a specific version of a file
+///
+#[utoipa::path(
+    get,
+    tag = "Files",
+    path = "/api/files/{file_id}/versions/{version}/contents",
+    responses(
+        (status = 200, description = "Successfully downloaded a version of a file", body = [Vec<u8>])
+    )
+)]
+#[get("/{file_id}/versions/{version}/contents")]
+pub async fn get_file_version_contents_handler(
+    app: web::Data<AppState>,
+    jwt: jwt_auth::JwtMiddleware,
+    path: web::Path<(String, i32)>,
+) -> Result<HttpResponse, Error> {
+    let (file_id, version) = path.into_inner();
+    let user_id = jwt.user_id;
+
+    let mut conn = app
+        .get_connection()
+        .map_err(|err| ServiceError::NotFound(err.to_string()))?;
+
+    let file = get_file(&mut conn, &file_id, user_id, Permission::Read).map_err(|err| ServiceError::NotFound(err.to_string()))?;
+    let owner = get_user(&mut conn, file.owner_id).map_err(|err| ServiceError::NotFound(err.to_string()))?;
+    let file_version = get_file_version(&mut conn, &file_id, version).map_err(|err| ServiceError::NotFound(err.to_string()))?;
+
+    let (path, name) = version_location(&mut conn, &owner, &file_version);
+    let stream = app
+        .get_storage_service()
+        .retrieve_file(&path, &name)
+        .await
+        .map_err(|err| storage_error(err, &file_id))?;
+
     Ok(HttpResponse::Ok()
-        .content_type(file.media_type.unwrap_or("application/octet-stream".to_string()))
-        .insert_header(("FileName", file.orginal_filename.unwrap_or("Unknown".to_string())))
-        .body(get_file_contents(build_full_path(&user.folder_id, &file.folder_id), &file.id)
-        .map_err(|err| ServiceError::BadRequest(err.to_string()))?)
+        .content_type(file_version.media_type.unwrap_or("application/octet-stream".to_string()))
+        .insert_header(("FileName", file_version.orginal_filename.unwrap_or("Unknown".to_string())))
+        .insert_header(ETag(EntityTag::new_strong(file_version.checksum)))
+        .no_chunking(file_version.size as u64)
+        .streaming(stream))
+}
+
+///
+/// Makes an old version the current contents of a file
+///
+/// The old version is copied into a new version, so the history itself is never rewritten
+///
+#[utoipa::path(
+    post,
+    tag = "Files",
+    path = "/api/files/{file_id}/versions/{version}/promote",
+    responses(
+        (status = 200, description = "Successfully promoted a version", body = [FileVersionDto])
     )
+)]
+#[post("/{file_id}/versions/{version}/promote")]
+pub async fn promote_file_version_handler(
+    app: web::Data<AppState>,
+    jwt: jwt_auth::JwtMiddleware,
+    path: web::Path<(String, i32)>,
+) -> Result<HttpResponse, Error> {
+    let (file_id, version) = path.into_inner();
+    let user_id = jwt.user_id;
+
+    let mut conn = app
+        .get_connection()
+        .map_err(|err| ServiceError::NotFound(err.to_string()))?;
+
+    let mut file = get_file(&mut conn, &file_id, user_id, Permission::Write).map_err(|err| ServiceError::NotFound(err.to_string()))?;
+    let owner = get_user(&mut conn, file.owner_id).map_err(|err| ServiceError::NotFound(err.to_string()))?;
+    let old_version = get_file_version(&mut conn, &file_id, version).map_err(|err| ServiceError::NotFound(err.to_string()))?;
+    let new_version = next_version_number(&mut conn, &file_id).map_err(|err| ServiceError::InternalServerError(err.to_string()))?;
+
+    // Every version counts against the quota, so the new one takes as much space as the old one
+    let reserved = reserve_storage(&mut conn, file.owner_id, old_version.size, app.get_config().default_storage_quota)
+        .map_err(|err| ServiceError::InternalServerError(err.to_string()))?;
+    if !reserved {
+        return Err(ServiceError::PayloadTooLarge(format!("Version {} exceeds the storage quota", version)).into());
+    }
+    let unreserve = |conn: &mut SqliteConnection| {
+        let _ = add_storage_used(conn, file.owner_id, -old_version.size);
+    };
+
+    // The new version shares the blob of the old one, versions from before the blob store are moved into it
+    let blobs = app.get_blob_store();
+    let blob = match get_blob(&mut conn, &old_version.checksum) {
+        Ok(blob) => blobs.add_reference(&mut conn, &blob.hash).await,
+        Err(_) => {
+            let (path, name) = version_location(&mut conn, &owner, &old_version);
+            match app.get_storage_service().retrieve_file(&path, &name).await {
+                Ok(stream) => blobs.put(&mut conn, stream).await,
+                Err(err) => {
+                    unreserve(&mut conn);
+                    return Err(storage_error(err, &file_id).into());
+                }
+            }
+        }
+    };
+    let blob = match blob {
+        Ok(blob) => blob,
+        Err(err) => {
+            unreserve(&mut conn);
+            return Err(ServiceError::InternalServerError(err.to_string()).into());
+        }
+    };
+
+    let file_version = FileVersionDto {
+        id: Uuid::new_v4().to_string(),
+        version: new_version,
+        size: blob.size,
+        checksum: blob.hash.clone(),
+        created_at: None,
+        created_by: user_id,
+        ..old_version
+    };
+    if let Err(err) = create_file_version(&mut conn, &file_version) {
+        let _ = release_blob_reference(&mut conn, &blob.hash);
+        unreserve(&mut conn);
+        return Err(ServiceError::InternalServerError(err.to_string()).into());
+    }
+    let file_version = get_file_version(&mut conn, &file_id, new_version).map_err(|err| ServiceError::InternalServerError(err.to_string()))?;
 
+    file.media_type = file_version.media_type.clone();
+    file.orginal_filename = file_version.orginal_filename.clone();
+    file.blob_hash = Some(blob.hash.clone());
+    file.size = blob.size;
+    update_file(&mut conn, file, user_id).map_err(|err| ServiceError::InternalServerError(err.to_string()))?;
+    queue_content_jobs(&mut conn, &file_id, &blob.hash, file_version.media_type.as_deref().unwrap_or_default(), user_id)?;
+    info!("Promoted version {} of file: {} to version {}", version, file_id, new_version);
 
+    Ok(HttpResponse::Ok().json(file_version))
+}
+
+///
+/// Streams the current contents of `file`, answering conditional and range requests
+///
+/// `user` is the owner of the file, older uploads are stored under their folder
+///
+pub async fn file_contents_response(req: &HttpRequest, app: &AppState, user: &UserDto, file: FileDto) -> Result<HttpResponse, Error> {
+    let etag = file_etag(&file);
+    let last_modified = file_last_modified(&file);
+
+    let mut response = HttpResponse::Ok();
+    response
+        .insert_header(ETag(etag.clone()))
+        .insert_header((ACCEPT_RANGES, "bytes"));
+    if let Some(last_modified) = last_modified {
+        response.insert_header(LastModified(last_modified));
+    }
+
+    if is_not_modified(req, &etag, last_modified) {
+        return Ok(response.status(StatusCode::NOT_MODIFIED).finish());
+    }
+
+    let storage = app.get_storage_service();
+    let (path, name) = file_location(user, &file);
+    let size = storage
+        .file_size(&path, &name)
+        .await
+        .map_err(|err| storage_error(err, &file.id))?;
+
+    response
+        .content_type(file.media_type.unwrap_or("application/octet-stream".to_string()))
+        .insert_header(("FileName", file.orginal_filename.unwrap_or("Unknown".to_string())));
+
+    match requested_range(req, &etag, last_modified, size) {
+        RequestedRange::Full => {
+            let stream = storage
+                .retrieve_file(&path, &name)
+                .await
+                .map_err(|err| storage_error(err, &file.id))?;
+            Ok(response.no_chunking(size).streaming(stream))
+        }
+        RequestedRange::Partial(start, end) => {
+            let length = end - start + 1;
+            let stream = storage
+                .retrieve_file_range(&path, &name, start, length)
+                .await
+                .map_err(|err| storage_error(err, &file.id))?;
+            Ok(response
+                .status(StatusCode::PARTIAL_CONTENT)
+                .insert_header(ContentRange(ContentRangeSpec::Bytes {
+                    range: Some((start, end)),
+                    instance_length: Some(size),
+                }))
+                .no_chunking(length)
+                .streaming(stream))
+        }
+        RequestedRange::Unsatisfiable => Ok(response
+            .status(StatusCode::RANGE_NOT_SATISFIABLE)
+            .insert_header(ContentRange(ContentRangeSpec::Bytes {
+                range: None,
+                instance_length: Some(size),
+            }))
+            .finish()),
+    }
+}
+
+///
+/// Stores `input` as a new version of `file` and makes it the file's current contents
+///
+/// The contents go into the blob store, each version references its blob and the file points
+/// at the blob of its latest version. Every version counts against the owner's quota, uploads
+/// that would take them over it are cut off as soon as the quota is exceeded. `reserved` bytes
+/// were already added to the owner's usage for this version, like the length of a tus upload
+///
+#[allow(clippy::too_many_arguments)]
+pub async fn store_file_version(
+    app: &AppState,
+    conn: &mut SqliteConnection,
+    mut file: FileDto,
+    input: ByteStream,
+    media_type: String,
+    filename: String,
+    user_id: i32,
+    reserved: i64,
+) -> Result<FileVersionDto, ServiceError> {
+    let version = next_version_number(conn, &file.id).map_err(|err| ServiceError::InternalServerError(err.to_string()))?;
+
+    let default_quota = app.get_config().default_storage_quota;
+    let available = get_available_space(conn, file.owner_id, default_quota)
+        .map_err(|err| ServiceError::InternalServerError(err.to_string()))?;
+    let exceeded = Rc::new(Cell::new(false));
+    let input = quota_stream(input, (available + reserved) as u64, exceeded.clone());
+
+    let blob = app
+        .get_blob_store()
+        .put(conn, input)
+        .await
+        .map_err(|err| match exceeded.get() {
+            true => ServiceError::PayloadTooLarge(format!("Upload exceeds the storage quota of {} bytes available", available + reserved)),
+            false => ServiceError::InternalServerError(err.to_string()),
+        })?;
+
+    // Parallel uploads all saw the same free space, only those that still fit are kept
+    match reserve_storage(conn, file.owner_id, blob.size - reserved, default_quota) {
+        Ok(true) => {}
+        Ok(false) => {
+            let _ = release_blob_reference(conn, &blob.hash);
+            return Err(ServiceError::PayloadTooLarge("Upload exceeds the storage quota".to_string()));
+        }
+        Err(err) => {
+            let _ = release_blob_reference(conn, &blob.hash);
+            return Err(ServiceError::InternalServerError(err.to_string()));
+        }
+    }
+
+    let file_version = FileVersionDto {
+        id: Uuid::new_v4().to_string(),
+        file_id: file.id.clone(),
+        version,
+        size: blob.size,
+        checksum: blob.hash.clone(),
+        media_type: Some(media_type.clone()),
+        orginal_filename: Some(filename.clone()),
+        created_at: None,
+        created_by: user_id,
+    };
+    if let Err(err) = create_file_version(conn, &file_version) {
+        let _ = release_blob_reference(conn, &blob.hash);
+        let _ = add_storage_used(conn, file.owner_id, -blob.size);
+        return Err(ServiceError::InternalServerError(err.to_string()));
+    }
+    let file_version = get_file_version(conn, &file.id, version).map_err(|err| ServiceError::InternalServerError(err.to_string()))?;
+
+    file.media_type = Some(media_type.clone());
+    file.orginal_filename = Some(filename);
+    file.blob_hash = Some(blob.hash.clone());
+    file.size = blob.size;
+    let file_id = file.id.clone();
+    update_file(conn, file, user_id).map_err(|err| ServiceError::InternalServerError(err.to_string()))?;
+    queue_content_jobs(conn, &file_id, &blob.hash, &media_type, user_id)?;
+
+    Ok(file_version)
+}
+
+///
+/// Adds files to an archive, each into the folder path it comes with
+///
+pub fn add_files_to_archive(conn: &mut SqliteConnection, archive: &mut Archive, files: Vec<(String, FileDto)>) -> Result<(), ServiceError> {
+    // Files stored before the blob store are found through their owner's folder
+    let mut owners: HashMap<i32, UserDto> = HashMap::new();
+    for (folder, file) in files {
+        let owner = match owners.entry(file.owner_id) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert(get_user(conn, file.owner_id).map_err(|err| ServiceError::NotFound(err.to_string()))?),
+        };
+        let location = file_location(owner, &file);
+        let name = file.orginal_filename.as_deref().unwrap_or(&file.title);
+        archive.add_file(&folder, name, location, file.media_type.clone(), file.updated_at);
+    }
+    Ok(())
+}
+
+///
+/// Streams an archive as a ZIP download
+///
+pub fn archive_response(app: &AppState, archive: Archive, filename: &str) -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type("application/zip")
+        .insert_header(("FileName", filename))
+        .streaming(archive.into_stream(app.get_storage_service().clone()))
+}
+
+// Path and name of the current contents of a file in storage
+fn file_location(user: &UserDto, file: &FileDto) -> (String, String) {
+    match &file.blob_hash {
+        Some(hash) => BlobStore::location(hash),
+        // Uploaded before the blob store existed
+        None => (build_full_path(&user.folder_id, &file.folder_id), file.id.clone()),
+    }
+}
+
+// Indexes new contents and makes their thumbnails in the background
+fn queue_content_jobs(conn: &mut SqliteConnection, file_id: &str, blob_hash: &str, media_type: &str, user_id: i32) -> Result<(), ServiceError> {
+    let mut jobs = vec![Job::IndexContents {
+        file_id: file_id.to_string(),
+        blob_hash: blob_hash.to_string(),
+        media_type: media_type.to_string(),
+    }];
+    if has_thumbnails(media_type) {
+        let (path, name) = BlobStore::location(blob_hash);
+        jobs.push(Job::CreateThumbnails { path, name });
+    }
+
+    for job in jobs {
+        enqueue_job(conn, &job, user_id).map_err(|err| ServiceError::InternalServerError(err.to_string()))?;
+    }
+    Ok(())
+}
+
+// Path and name of the contents of a file version in storage
+fn version_location(conn: &mut SqliteConnection, user: &UserDto, file_version: &FileVersionDto) -> (String, String) {
+    match get_blob(conn, &file_version.checksum) {
+        Ok(blob) => BlobStore::location(&blob.hash),
+        // Uploaded before the blob store existed
+        Err(_) => (build_version_path(&user.folder_id, &file_version.file_id), file_version.version.to_string()),
+    }
+}
+
+enum RequestedRange {
+    Full,
+    Partial(u64, u64),
+    Unsatisfiable,
+}
+
+fn storage_error(err: std::io::Error, file_id: &str) -> ServiceError {
+    match err.kind() {
+        std::io::ErrorKind::NotFound => ServiceError::NotFound(file_id.to_string()),
+        _ => ServiceError::InternalServerError(err.to_string()),
+    }
+}
+
+// The file record changes whenever new contents are uploaded, so it identifies the current version
+fn file_etag(file: &FileDto) -> EntityTag {
+    let version = file.updated_at.or(file.created_at).map(|at| at.and_utc().timestamp_millis()).unwrap_or_default();
+    EntityTag::new_strong(format!("{}-{:x}", file.id, version))
+}
+
+// HTTP dates only carry whole seconds, truncate so the value round trips through If-Modified-Since
+fn file_last_modified(file: &FileDto) -> Option<HttpDate> {
+    file.updated_at.or(file.created_at).map(|at| {
+        let seconds = at.and_utc().timestamp().max(0) as u64;
+        HttpDate::from(UNIX_EPOCH + Duration::from_secs(seconds))
+    })
+}
+
+fn is_not_modified(req: &HttpRequest, etag: &EntityTag, last_modified: Option<HttpDate>) -> bool {
+    // If-None-Match takes precedence over If-Modified-Since (RFC 7232 section 6)
+    if req.headers().contains_key(IF_NONE_MATCH) {
+        return match IfNoneMatch::parse(req) {
+            Ok(IfNoneMatch::Any) => true,
+            Ok(IfNoneMatch::Items(tags)) => tags.iter().any(|tag| tag.weak_eq(etag)),
+            Err(_) => false,
+        };
+    }
+
+    match (IfModifiedSince::parse(req), last_modified) {
+        (Ok(IfModifiedSince(since)), Some(last_modified)) => last_modified <= since,
+        _ => false,
+    }
+}
+
+fn requested_range(req: &HttpRequest, etag: &EntityTag, last_modified: Option<HttpDate>, size: u64) -> RequestedRange {
+    let specs = match Range::parse(req) {
+        Ok(Range::Bytes(specs)) => specs,
+        _ => return RequestedRange::Full,
+    };
+
+    // A stale If-Range means the client's partial copy is outdated, send the whole file instead
+    let if_range_matches = match IfRange::parse(req) {
+        Ok(IfRange::EntityTag(tag)) => tag.strong_eq(etag),
+        Ok(IfRange::Date(date)) => last_modified == Some(date),
+        Err(_) => true,
+    };
+
+    // Multipart/byteranges responses are not supported, multiple ranges are answered with the full file
+    if !if_range_matches || specs.len() != 1 {
+        return RequestedRange::Full;
+    }
+
+    match specs[0].to_satisfiable_range(size) {
+        Some((start, end)) => RequestedRange::Partial(start, end),
+        None => RequestedRange::Unsatisfiable,
+    }
 }
 
 pub fn config(conf: &mut web::ServiceConfig) {
@@ -211,8 +916,15 @@ pub fn config(conf: &mut web::ServiceConfig) {
             .service(get_all_files_handler)
             .service(get_file_handler)
             .service(get_file_contents_handler)
+            .service(get_file_thumbnail_handler)
+            .service(archive_files_handler)
             .service(create_file_handler)
             .service(upload_file_handler)
+            .service(update_file_handler)
+            .service(delete_file_handler)
+            .service(get_file_versions_handler)
+            .service(get_file_version_contents_handler)
+            .service(promote_file_version_handler)
             ;
 
     conf.service(scope);
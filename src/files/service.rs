@@ -1,18 +1,31 @@
 use std::fs::File;
 use std::io::Write;
 
-use crate::shared::{common::{DbError, ServiceError}, dto::QueryParams};
-use super::dto::{FileDto, CreateFileDto};
+use crate::shared::{common::{DbError, ServiceError}, dto::{PageDto, QueryParams, SortField, SortOrder}};
+use super::dto::{FileDto, CreateFileDto, FileVersionDto};
+use crate::groups::dto::Owner;
+use crate::sharing::dto::Permission;
+use crate::sharing::service::get_file_permission;
 use actix_multipart::Field;
 use diesel::prelude::*;
+use diesel::sqlite::Sqlite;
 use uuid::Uuid;
 
 use futures_util::TryStreamExt;
 
+use crate::schema::files;
 use crate::schema::files::dsl;
+use crate::schema::file_versions;
 
 
-pub fn create_file(conn: &mut SqliteConnection, file: CreateFileDto, owner_id: i32) -> Result<String, DbError> {
+///
+/// Creates a file for `owner`, a group's files are owned by the member that created them
+///
+pub fn create_file(conn: &mut SqliteConnection, file: CreateFileDto, owner: &Owner, user_id: i32) -> Result<String, DbError> {
+    let (owner_id, group_id) = match owner {
+        Owner::User(owner_id) => (*owner_id, None),
+        Owner::Group(group_id) => (user_id, Some(group_id.clone())),
+    };
     let uuid = Uuid::new_v4().to_string();
     let file = FileDto {
         id: uuid,
@@ -23,11 +36,16 @@ pub fn create_file(conn: &mut SqliteConnection, file: CreateFileDto, owner_id: i
         description: file.description,
         folder_id: file.folder_id,
         orginal_filename: None,
-        created_by: owner_id,
-        updated_by: owner_id,
+        created_by: user_id,
+        updated_by: user_id,
         created_at: None,
         updated_at: None,
         active: true,
+        deleted_at: None,
+        deleted_by: None,
+        blob_hash: None,
+        size: 0,
+        group_id,
     };
 
     diesel::insert_into(dsl::files)
@@ -37,7 +55,10 @@ pub fn create_file(conn: &mut SqliteConnection, file: CreateFileDto, owner_id: i
 }
 
 
-pub fn update_file(conn: &mut SqliteConnection, file: FileDto, owner_id: i32) -> Result<usize, DbError> {
+///
+/// Saves the changes to a file, the caller must have checked the user may change it
+///
+pub fn update_file(conn: &mut SqliteConnection, file: FileDto, user_id: i32) -> Result<usize, DbError> {
     // let file = FileDto {
     //     id: uuid,
     //     title: file.title,
@@ -54,16 +75,18 @@ pub fn update_file(conn: &mut SqliteConnection, file: FileDto, owner_id: i32) ->
     //     active: true,
     // };
 
-    Ok(diesel::update(dsl::files.filter(dsl::id.eq(file.id).and(dsl::owner_id.eq(owner_id))))
+    Ok(diesel::update(dsl::files.filter(dsl::id.eq(file.id)))
         .set((
             dsl::title.eq(file.title),
             dsl::access_level.eq(file.access_level),
             dsl::media_type.eq(file.media_type),
             dsl::description.eq(file.description),
             dsl::folder_id.eq(file.folder_id),
-            dsl::updated_by.eq(owner_id),
-            dsl::updated_at.eq(chrono::Local::now().naive_local()),
+            dsl::updated_by.eq(user_id),
+            dsl::updated_at.eq(chrono::Utc::now().naive_utc()),
             dsl::orginal_filename.eq(file.orginal_filename),
+            dsl::blob_hash.eq(file.blob_hash),
+            dsl::size.eq(file.size),
             dsl::active.eq(file.active)))
         .execute(conn)?)
 }
@@ -80,24 +103,93 @@ pub async fn upload_file(full_path: String, f: &mut Field) -> Result<(), Service
     Ok(())
 }
 
-pub fn get_all_files(conn: &mut SqliteConnection, user_id: i32, params: QueryParams) -> Result<Vec<FileDto>, DbError> {
-    let mut query = crate::schema::files::table.into_boxed::<>();
-    query = query.filter(dsl::owner_id.eq(user_id));
-    
-    if let Some(q_folder_id) = params.folder_id {
+// Files of the owner matching the filters of `params`
+fn filtered_files<'a>(owner: &'a Owner, params: &'a QueryParams) -> files::BoxedQuery<'a, Sqlite> {
+    let mut query = files::table.into_boxed::<Sqlite>();
+    query = match owner {
+        // A user's own files, without the group files they created
+        Owner::User(owner_id) => query.filter(dsl::owner_id.eq(*owner_id)).filter(dsl::group_id.is_null()),
+        Owner::Group(group_id) => query.filter(dsl::group_id.eq(group_id)),
+    };
+    query = query.filter(dsl::active.eq(params.active.unwrap_or(true)));
+
+    if let Some(q_folder_id) = &params.folder_id {
         log::info!("Filtering for folder {}",q_folder_id);
         query = query.filter(dsl::folder_id.eq(q_folder_id));
     }
+    if let Some(media_type) = &params.media_type {
+        query = match media_type.strip_suffix("/*") {
+            Some(kind) => query.filter(dsl::media_type.like(format!("{}/%", kind))),
+            None => query.filter(dsl::media_type.eq(media_type)),
+        };
+    }
+    if let Some(modified_after) = params.modified_after {
+        query = query.filter(dsl::updated_at.ge(modified_after));
+    }
+    if let Some(modified_before) = params.modified_before {
+        query = query.filter(dsl::updated_at.lt(modified_before));
+    }
 
-    Ok(query.get_results::<FileDto>(conn)?)
+    let descending = params.order.unwrap_or_default() == SortOrder::Desc;
+    query = match (params.sort.unwrap_or_default(), descending) {
+        (SortField::Title, false) => query.order(dsl::title.asc()),
+        (SortField::Title, true) => query.order(dsl::title.desc()),
+        (SortField::Created, false) => query.order(dsl::created_at.asc()),
+        (SortField::Created, true) => query.order(dsl::created_at.desc()),
+        (SortField::Updated, false) => query.order(dsl::updated_at.asc()),
+        (SortField::Updated, true) => query.order(dsl::updated_at.desc()),
+        (SortField::Size, false) => query.order(dsl::size.asc()),
+        (SortField::Size, true) => query.order(dsl::size.desc()),
+    };
+    // Keeps the order stable between pages
+    query.then_order_by(dsl::id.asc())
+}
+
+///
+/// All files of the owner matching `params`, in the requested order
+///
+pub fn get_all_files(conn: &mut SqliteConnection, owner: &Owner, params: &QueryParams) -> Result<Vec<FileDto>, DbError> {
+    Ok(filtered_files(owner, params).get_results::<FileDto>(conn)?)
 }
 
+///
+/// The owner's active files in any of the folders, sorted by title
+///
+pub fn get_files_in_folders(conn: &mut SqliteConnection, owner: &Owner, folder_ids: &[String]) -> Result<Vec<FileDto>, DbError> {
+    let params = QueryParams::default();
+    Ok(filtered_files(owner, &params)
+        .filter(dsl::folder_id.eq_any(folder_ids))
+        .get_results::<FileDto>(conn)?)
+}
+
+///
+/// The page of the owner's files requested in `params`
+///
+pub fn get_files_page(conn: &mut SqliteConnection, owner: &Owner, params: &QueryParams) -> Result<PageDto<FileDto>, DbError> {
+    let total: i64 = filtered_files(owner, params)
+        .count()
+        .get_result(conn)?;
+    let files = filtered_files(owner, params)
+        .limit(params.page_size())
+        .offset(params.offset())
+        .get_results::<FileDto>(conn)?;
+    Ok(PageDto::new(files, params, total))
+}
 
-pub fn get_file(conn: &mut SqliteConnection, file_id: &String,  user_id: i32) -> Result<FileDto, DbError> {
+
+///
+/// Loads a file the user has at least `permission` on, as its owner or through a share
+///
+pub fn get_file(conn: &mut SqliteConnection, file_id: &String,  user_id: i32, permission: Permission) -> Result<FileDto, DbError> {
     let file = dsl::files
         .filter(dsl::id.eq(&file_id))
-        .filter(dsl::owner_id.eq(user_id))
-        .first::<FileDto>(conn);
+        .filter(dsl::active.eq(true))
+        .first::<FileDto>(conn)
+        .map_err(DbError::from)
+        .and_then(|file| match get_file_permission(conn, &file, user_id)? {
+            Some(granted) if granted >= permission => Ok(file),
+            _ => Err(format!("No {:?} access", permission).into()),
+        });
     log::debug!("Loaded file_id: {} for user_id: {}: {}", file_id, user_id, match &file {
         Ok(_) => "Ok",
         Err(_) => "Error",
@@ -112,14 +204,49 @@ pub fn get_file(conn: &mut SqliteConnection, file_id: &String,  user_id: i32) ->
     }
 }
 
-pub fn get_file_contents(path: String, file_id: &str) -> Result<String, DbError> {
-    use std::fs::File;
-    use std::io::Read;
+///
+/// Moves a file to the trash, its contents stay in storage until the trash is purged
+///
+/// The caller must have checked the user may delete the file
+///
+pub fn trash_file(conn: &mut SqliteConnection, file_id: &str, user_id: i32) -> Result<usize, DbError> {
+    let now = chrono::Utc::now().naive_utc();
+    Ok(diesel::update(dsl::files.filter(dsl::id.eq(file_id)))
+        .set((
+            dsl::active.eq(false),
+            dsl::deleted_at.eq(now),
+            dsl::deleted_by.eq(user_id),
+            dsl::updated_by.eq(user_id),
+            dsl::updated_at.eq(now)))
+        .execute(conn)?)
+}
 
-    let full_path = format!("{}/{}", path, file_id);
-    let mut file = File::open(full_path).map_err(|e| format!("Failed to open file: {}", e))?;
-    let mut contents = String::new();
-    file.read_to_string(&mut contents).map_err(|e| format!("Failed to read file: {}", e))?;
+pub fn get_file_versions(conn: &mut SqliteConnection, file_id: &str) -> Result<Vec<FileVersionDto>, DbError> {
+    Ok(file_versions::table
+        .filter(file_versions::file_id.eq(file_id))
+        .order(file_versions::version.desc())
+        .load::<FileVersionDto>(conn)?)
+}
 
-    Ok(contents)
-}
\ No newline at end of file
+pub fn get_file_version(conn: &mut SqliteConnection, file_id: &str, version: i32) -> Result<FileVersionDto, DbError> {
+    let file_version = file_versions::table
+        .filter(file_versions::file_id.eq(file_id))
+        .filter(file_versions::version.eq(version))
+        .first::<FileVersionDto>(conn)
+        .map_err(|err| format!("Error loading version {} of file_id: {}, Error: {}", version, file_id, err))?;
+    Ok(file_version)
+}
+
+pub fn next_version_number(conn: &mut SqliteConnection, file_id: &str) -> Result<i32, DbError> {
+    let latest: Option<i32> = file_versions::table
+        .filter(file_versions::file_id.eq(file_id))
+        .select(diesel::dsl::max(file_versions::version))
+        .first(conn)?;
+    Ok(latest.unwrap_or_default() + 1)
+}
+
+pub fn create_file_version(conn: &mut SqliteConnection, file_version: &FileVersionDto) -> Result<usize, DbError> {
+    Ok(diesel::insert_into(file_versions::table)
+        .values(file_version)
+        .execute(conn)?)
+}
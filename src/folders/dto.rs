@@ -1,9 +1,10 @@
 use serde::{Deserialize, Serialize};
 use utoipa::ToSchema;
+use crate::files::dto::FileDto;
 use crate::schema::{file_folders};
 
 #[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
-#[derive(Queryable, Selectable, Insertable, AsChangeset)]
+#[derive(Queryable, QueryableByName, Selectable, Insertable, AsChangeset)]
 #[diesel(table_name = file_folders)]
 #[serde(rename_all = "camelCase")]
 pub struct FolderDto {
@@ -20,6 +21,11 @@ pub struct FolderDto {
     pub created_by: i32,
     pub updated_by: i32,
     pub active: bool,
+    // When and by whom the item was moved to the trash
+    pub deleted_at: Option<chrono::NaiveDateTime>,
+    pub deleted_by: Option<i32>,
+    // The group owning the folder, if it is not owned by `owner_id` alone
+    pub group_id: Option<String>,
 }
 
 #[derive(Debug, Clone, Deserialize, ToSchema)]
@@ -30,3 +36,33 @@ pub struct CreateFolderDto {
     pub description: Option<String>,
 }
 
+#[derive(Debug, Clone, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct UpdateFolderDto {
+    pub title: String,
+    pub parent_folder_id: String,
+    pub description: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TreeParams {
+    // Levels of sub folders to include, 1 only includes the folder's direct sub folders
+    pub depth: Option<i32>,
+    // Includes the files of every folder in the tree
+    pub files: Option<bool>,
+}
+
+///
+/// A folder with its sub folders and, if requested, its files
+///
+#[derive(Debug, Clone, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct FolderTreeDto {
+    pub id: String,
+    // None for root folders, which only exist in storage
+    pub folder: Option<FolderDto>,
+    #[schema(no_recursion)]
+    pub folders: Vec<FolderTreeDto>,
+    pub files: Vec<FileDto>,
+}
@@ -3,28 +3,43 @@ pub mod service;
 
 
 use actix_web::{
-    get, web, Error, HttpResponse,
+    delete, get, post, put, web, Error, HttpResponse,
 };
 
 use log::info;
 
 use crate::auth::jwt_auth;
+use crate::files::dto::FileDto;
+use crate::files::{add_files_to_archive, archive_response};
+use crate::groups::dto::Owner;
+use crate::groups::service::get_owner_root;
 use crate::shared::common::ServiceError;
 use crate::shared::common::AppState;
-use service::{get_all_folders_in_folder};
+use crate::shared::archive::Archive;
+use crate::shared::common::build_full_path;
+use crate::shared::dto::{CreateResponseDto, PageDto, QueryParams};
+use crate::sharing::dto::Permission;
+use crate::sharing::service::{get_accessible_folder, get_folder_owner, get_shared_folder_ids};
+use service::{get_folders_page, get_folder_tree, get_ancestor_folders, create_folder, update_folder, trash_folder, is_folder_empty, is_valid_parent, is_descendant_of};
 
-use dto::{FolderDto};
+use dto::{FolderDto, CreateFolderDto, UpdateFolderDto, FolderTreeDto, TreeParams};
+
+const DEFAULT_TREE_DEPTH: i32 = 5;
+const MAX_TREE_DEPTH: i32 = 32;
 
 ///
 /// Gets all folders in a user's folder
 ///
+/// Takes the same paging, sorting and filtering parameters as the file listing, folders
+/// have no size and are sorted by title instead
+///
 #[utoipa::path(
     get,
     tag = "Folders",
     path = "/api/folders/{folder_id}/contents",
 
     responses(
-        (status = 200, description = "Successfully retrieved all folders in folder", body = [Vec<FolderDto>])
+        (status = 200, description = "Successfully retrieved all folders in folder", body = [PageDto<FolderDto>])
     )
 )]
 #[get("/{folder_id}/contents")]
@@ -32,6 +47,7 @@ pub async fn get_all_folders_handler(
     app: web::Data<AppState>,
     jwt: jwt_auth::JwtMiddleware,
     path: web::Path<String>,
+    query: web::Query<QueryParams>,
 ) -> Result<HttpResponse, Error> {
     let user_id = jwt.user_id;
     let folder_id: String = path.to_string();
@@ -42,15 +58,326 @@ pub async fn get_all_folders_handler(
         .get_connection()
         .map_err(|err| ServiceError::NotFound(err.to_string()))?;
 
-    match get_all_folders_in_folder(&mut conn, user_id, folder_id) {
+    // Folders inside a group's folder or a folder shared with the user belong to the folder's owner
+    let owner = get_folder_owner(&mut conn, &folder_id, user_id, Permission::Read).map_err(|err| ServiceError::NotFound(err.to_string()))?;
+
+    match get_folders_page(&mut conn, &owner, &folder_id, &query) {
         Ok(folders) => Ok(HttpResponse::Ok().json(folders)),
         Err(err) => Err(ServiceError::NotFound(err.to_string()).into()),
     }
 }
 
+///
+/// Gets a folder by it's folder id
+///
+#[utoipa::path(
+    get,
+    tag = "Folders",
+    path = "/api/folders/{folder_id}",
+
+    responses(
+        (status = 200, description = "Successfully retrieved a folder", body = [FolderDto])
+    )
+)]
+#[get("/{folder_id}")]
+pub async fn get_folder_handler(
+    app: web::Data<AppState>,
+    jwt: jwt_auth::JwtMiddleware,
+    path: web::Path<String>,
+) -> Result<HttpResponse, Error> {
+    let user_id = jwt.user_id;
+    let folder_id: String = path.to_string();
+
+    let mut conn = app
+        .get_connection()
+        .map_err(|err| ServiceError::NotFound(err.to_string()))?;
+
+    match get_accessible_folder(&mut conn, &folder_id, user_id, Permission::Read) {
+        Ok(folder) => Ok(HttpResponse::Ok().json(folder)),
+        Err(err) => Err(ServiceError::NotFound(err.to_string()).into()),
+    }
+}
+
+///
+/// Gets the tree of folders below a folder
+///
+/// `depth` limits the levels of sub folders, 5 by default and at most 32. With `files=true`
+/// every folder in the tree also lists its files
+///
+#[utoipa::path(
+    get,
+    tag = "Folders",
+    path = "/api/folders/{folder_id}/tree",
+
+    responses(
+        (status = 200, description = "Successfully retrieved the folder tree", body = [FolderTreeDto])
+    )
+)]
+#[get("/{folder_id}/tree")]
+pub async fn get_folder_tree_handler(
+    app: web::Data<AppState>,
+    jwt: jwt_auth::JwtMiddleware,
+    path: web::Path<String>,
+    query: web::Query<TreeParams>,
+) -> Result<HttpResponse, Error> {
+    let user_id = jwt.user_id;
+    let folder_id: String = path.to_string();
+    let depth = query.depth.unwrap_or(DEFAULT_TREE_DEPTH).clamp(1, MAX_TREE_DEPTH);
+
+    let mut conn = app
+        .get_connection()
+        .map_err(|err| ServiceError::NotFound(err.to_string()))?;
+
+    let owner = get_folder_owner(&mut conn, &folder_id, user_id, Permission::Read).map_err(|err| ServiceError::NotFound(err.to_string()))?;
+    // Root folders have no folder of their own
+    let folder = get_accessible_folder(&mut conn, &folder_id, user_id, Permission::Read).ok();
+
+    match get_folder_tree(&mut conn, &folder_id, folder, &owner, Some(depth), query.files.unwrap_or(false)) {
+        Ok(tree) => Ok(HttpResponse::Ok().json(tree)),
+        Err(err) => Err(ServiceError::InternalServerError(err.to_string()).into()),
+    }
+}
+
+///
+/// Downloads a folder with all its sub folders and files as a ZIP archive
+///
+/// The archive is written while it is being downloaded, the files in it are named after the
+/// files they were uploaded as
+///
+#[utoipa::path(
+    get,
+    tag = "Folders",
+    path = "/api/folders/{folder_id}/archive",
+
+    responses(
+        (status = 200, description = "Successfully downloaded the folder", body = [Vec<u8>])
+    )
+)]
+#[get("/{folder_id}/archive")]
+pub async fn archive_folder_handler(
+    app: web::Data<AppState>,
+    jwt: jwt_auth::JwtMiddleware,
+    path: web::Path<String>,
+) -> Result<HttpResponse, Error> {
+    let user_id = jwt.user_id;
+    let folder_id: String = path.to_string();
+
+    let mut conn = app
+        .get_connection()
+        .map_err(|err| ServiceError::NotFound(err.to_string()))?;
+
+    let owner = get_folder_owner(&mut conn, &folder_id, user_id, Permission::Read).map_err(|err| ServiceError::NotFound(err.to_string()))?;
+    let folder = get_accessible_folder(&mut conn, &folder_id, user_id, Permission::Read).ok();
+    let filename = format!("{}.zip", folder.as_ref().map_or("Files", |folder| folder.title.as_str()));
+
+    let tree = get_folder_tree(&mut conn, &folder_id, folder, &owner, None, true).map_err(|err| ServiceError::InternalServerError(err.to_string()))?;
+    let mut archive = Archive::default();
+    let mut files = Vec::new();
+    add_tree_to_archive(&mut archive, "", tree, &mut files);
+    add_files_to_archive(&mut conn, &mut archive, files)?;
+    info!("Downloading folder: {} as an archive for user: {}", folder_id, user_id);
+
+    Ok(archive_response(&app, archive, &filename))
+}
+
+// Adds the sub folders of a tree below `parent`, the files are collected with the path of their folder
+fn add_tree_to_archive(archive: &mut Archive, parent: &str, tree: FolderTreeDto, files: &mut Vec<(String, FileDto)>) {
+    files.extend(tree.files.into_iter().map(|file| (parent.to_string(), file)));
+    for child in tree.folders {
+        let (title, modified) = child.folder.as_ref().map_or((child.id.as_str(), None), |folder| (folder.title.as_str(), folder.updated_at));
+        let path = archive.add_folder(parent, title, modified);
+        add_tree_to_archive(archive, &path, child, files);
+    }
+}
+
+///
+/// Gets the folders above a folder
+///
+/// Lists the folders from the top down to the folder itself, for breadcrumbs. The root
+/// folder is not included, the list for a root folder is empty. For a folder shared with the
+/// user the list starts at the shared folder
+///
+#[utoipa::path(
+    get,
+    tag = "Folders",
+    path = "/api/folders/{folder_id}/ancestors",
+
+    responses(
+        (status = 200, description = "Successfully retrieved the folder's ancestors", body = [Vec<FolderDto>])
+    )
+)]
+#[get("/{folder_id}/ancestors")]
+pub async fn get_folder_ancestors_handler(
+    app: web::Data<AppState>,
+    jwt: jwt_auth::JwtMiddleware,
+    path: web::Path<String>,
+) -> Result<HttpResponse, Error> {
+    let user_id = jwt.user_id;
+    let folder_id: String = path.to_string();
+
+    let mut conn = app
+        .get_connection()
+        .map_err(|err| ServiceError::NotFound(err.to_string()))?;
+
+    get_folder_owner(&mut conn, &folder_id, user_id, Permission::Read).map_err(|err| ServiceError::NotFound(err.to_string()))?;
+    let Ok(folder) = get_accessible_folder(&mut conn, &folder_id, user_id, Permission::Read) else {
+        return Ok(HttpResponse::Ok().json(Vec::<FolderDto>::new()));
+    };
+
+    let owner = Owner::from(&folder);
+    let mut ancestors = get_ancestor_folders(&mut conn, &folder_id, &owner).map_err(|err| ServiceError::InternalServerError(err.to_string()))?;
+
+    // The folders above the highest one shared with the user stay hidden
+    if let Owner::User(owner_id) = owner && owner_id != user_id {
+        let shared = get_shared_folder_ids(&mut conn, user_id, owner_id).map_err(|err| ServiceError::InternalServerError(err.to_string()))?;
+        if let Some(highest) = ancestors.iter().rposition(|ancestor| shared.contains(&ancestor.id)) {
+            ancestors.truncate(highest + 1);
+        }
+    }
+    ancestors.reverse();
+
+    Ok(HttpResponse::Ok().json(ancestors))
+}
+
+///
+/// Creates a folder
+///
+/// The parent folder must be the user's root folder or a folder the user can write to. Folders
+/// belong to the owner of the parent, a group or the user that shared it with write access
+///
+#[utoipa::path(
+    post,
+    tag = "Folders",
+    path = "/api/folders",
+    request_body = CreateFolderDto,
+    responses(
+        (status = 201, description = "Successfully created a folder", body = [CreateResponseDto])
+    )
+)]
+#[post("")]
+pub async fn create_folder_handler(
+    app: web::Data<AppState>,
+    jwt: jwt_auth::JwtMiddleware,
+    data: web::Json<CreateFolderDto>,
+) -> Result<HttpResponse, Error> {
+    let user_id = jwt.user_id;
+    let folder = data.into_inner();
+
+    let mut conn = app
+        .get_connection()
+        .map_err(|err| ServiceError::NotFound(err.to_string()))?;
+
+    let owner = get_folder_owner(&mut conn, &folder.parent_folder_id, user_id, Permission::Write).map_err(|err| ServiceError::NotFound(err.to_string()))?;
+    let root_folder_id = get_owner_root(&mut conn, &owner).map_err(|err| ServiceError::NotFound(err.to_string()))?;
+    if !is_valid_parent(&mut conn, &folder.parent_folder_id, &root_folder_id, &owner).map_err(|err| ServiceError::InternalServerError(err.to_string()))? {
+        return Err(ServiceError::BadRequest(format!("Invalid parent folder {}", folder.parent_folder_id)).into());
+    }
+
+    let folder_id = create_folder(&mut conn, folder, &owner, user_id).map_err(|err| ServiceError::InternalServerError(err.to_string()))?;
+    info!("Created folder: {} for user: {}", folder_id, user_id);
+
+    app.get_storage_service()
+        .create_folder(&build_full_path(&root_folder_id, &folder_id))
+        .map_err(|err| ServiceError::InternalServerError(err.to_string()))?;
+
+    Ok(HttpResponse::Created().json(CreateResponseDto::ok_with_id(folder_id)))
+}
+
+///
+/// Updates a folder
+///
+/// Renames the folder, changes its description or moves it to another parent folder.
+/// A folder can not be moved into itself or one of its sub folders.
+///
+#[utoipa::path(
+    put,
+    tag = "Folders",
+    path = "/api/folders/{folder_id}",
+    request_body = UpdateFolderDto,
+    responses(
+        (status = 200, description = "Successfully updated a folder", body = [FolderDto])
+    )
+)]
+#[put("/{folder_id}")]
+pub async fn update_folder_handler(
+    app: web::Data<AppState>,
+    jwt: jwt_auth::JwtMiddleware,
+    path: web::Path<String>,
+    data: web::Json<UpdateFolderDto>,
+) -> Result<HttpResponse, Error> {
+    let user_id = jwt.user_id;
+    let folder_id: String = path.to_string();
+    let folder = data.into_inner();
+
+    let mut conn = app
+        .get_connection()
+        .map_err(|err| ServiceError::NotFound(err.to_string()))?;
+
+    let existing = get_accessible_folder(&mut conn, &folder_id, user_id, Permission::Owner).map_err(|err| ServiceError::NotFound(err.to_string()))?;
+
+    // Folders stay with their owner, a group's folders can only be moved between its folders
+    let owner = Owner::from(&existing);
+    let root_folder_id = get_owner_root(&mut conn, &owner).map_err(|err| ServiceError::NotFound(err.to_string()))?;
+    if !is_valid_parent(&mut conn, &folder.parent_folder_id, &root_folder_id, &owner).map_err(|err| ServiceError::InternalServerError(err.to_string()))? {
+        return Err(ServiceError::BadRequest(format!("Invalid parent folder {}", folder.parent_folder_id)).into());
+    }
+    if is_descendant_of(&mut conn, &folder.parent_folder_id, &folder_id, &owner).map_err(|err| ServiceError::InternalServerError(err.to_string()))? {
+        return Err(ServiceError::BadRequest("A folder can not be moved into itself or one of its sub folders".to_string()).into());
+    }
+
+    update_folder(&mut conn, &folder_id, folder, user_id).map_err(|err| ServiceError::InternalServerError(err.to_string()))?;
+
+    match get_accessible_folder(&mut conn, &folder_id, user_id, Permission::Read) {
+        Ok(folder) => Ok(HttpResponse::Ok().json(folder)),
+        Err(err) => Err(ServiceError::NotFound(err.to_string()).into()),
+    }
+}
+
+///
+/// Moves an empty folder to the trash
+///
+#[utoipa::path(
+    delete,
+    tag = "Folders",
+    path = "/api/folders/{folder_id}",
+    responses(
+        (status = 204, description = "Successfully moved a folder to the trash")
+    )
+)]
+#[delete("/{folder_id}")]
+pub async fn delete_folder_handler(
+    app: web::Data<AppState>,
+    jwt: jwt_auth::JwtMiddleware,
+    path: web::Path<String>,
+) -> Result<HttpResponse, Error> {
+    let user_id = jwt.user_id;
+    let folder_id: String = path.to_string();
+
+    let mut conn = app
+        .get_connection()
+        .map_err(|err| ServiceError::NotFound(err.to_string()))?;
+
+    let folder = get_accessible_folder(&mut conn, &folder_id, user_id, Permission::Owner).map_err(|err| ServiceError::NotFound(err.to_string()))?;
+    if !is_folder_empty(&mut conn, &folder_id, &Owner::from(&folder)).map_err(|err| ServiceError::InternalServerError(err.to_string()))? {
+        return Err(ServiceError::BadRequest(format!("Folder {} is not empty", folder_id)).into());
+    }
+
+    trash_folder(&mut conn, &folder_id, user_id).map_err(|err| ServiceError::InternalServerError(err.to_string()))?;
+    info!("Moved folder: {} to the trash for user: {}", folder_id, user_id);
+
+    Ok(HttpResponse::NoContent().finish())
+}
+
 pub fn config(conf: &mut web::ServiceConfig) {
     let scope = web::scope("/folders")
             .service(get_all_folders_handler)
+            .service(get_folder_handler)
+            .service(get_folder_tree_handler)
+            .service(get_folder_ancestors_handler)
+            .service(archive_folder_handler)
+            .service(create_folder_handler)
+            .service(update_folder_handler)
+            .service(delete_folder_handler)
             // .service(add_move_to_folder_handler)
             ;
 
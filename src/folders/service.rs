@@ -1,17 +1,326 @@
+use std::collections::HashMap;
+
 use crate::shared::common::{DbError};
-use super::dto::{FolderDto};
+use crate::shared::dto::{PageDto, QueryParams, SortField, SortOrder};
+use super::dto::{FolderDto, CreateFolderDto, UpdateFolderDto, FolderTreeDto};
+use crate::files::dto::FileDto;
+use crate::files::service::get_files_in_folders;
+use crate::groups::dto::Owner;
 use diesel::prelude::*;
+use diesel::query_builder::{BoxedSqlQuery, SqlQuery};
+use diesel::sql_types::{Integer, Text};
+use diesel::sqlite::Sqlite;
+use uuid::Uuid;
+
+use crate::schema::file_folders;
+use crate::schema::file_folders::dsl;
+
+// Deepest nesting followed when walking the folders, guards against cycles
+const MAX_FOLDER_DEPTH: i32 = 256;
 
-pub fn get_all_folders_in_folder(conn: &mut SqliteConnection, user_id: i32, folder_id: String) -> Result<Vec<FolderDto>, DbError> {
-    use crate::schema::file_folders::dsl::*;
+// Folders of a user, without the group folders they created, or of a group
+fn owned_folders(owner: &Owner) -> file_folders::BoxedQuery<'_, Sqlite> {
+    match owner {
+        Owner::User(owner_id) => dsl::file_folders
+            .filter(dsl::owner_id.eq(*owner_id))
+            .filter(dsl::group_id.is_null())
+            .into_boxed(),
+        Owner::Group(group_id) => dsl::file_folders
+            .filter(dsl::group_id.eq(group_id))
+            .into_boxed(),
+    }
+}
 
-    let results = file_folders
-        .filter(owner_id.eq(user_id))
-        .filter(parent_folder_id.eq(folder_id))
+pub fn get_all_folders_in_folder(conn: &mut SqliteConnection, owner: &Owner, folder_id: String) -> Result<Vec<FolderDto>, DbError> {
+    let results = owned_folders(owner)
+        .filter(dsl::parent_folder_id.eq(folder_id))
+        .filter(dsl::active.eq(true))
         .load::<FolderDto>(conn)?;
 
     Ok(results)
-}   
+}
+
+// Folders of the owner in `folder_id` matching the filters of `params`, folders have no size
+// and are sorted by title instead
+fn filtered_folders<'a>(owner: &'a Owner, folder_id: &'a str, params: &'a QueryParams) -> file_folders::BoxedQuery<'a, Sqlite> {
+    let mut query = owned_folders(owner)
+        .filter(dsl::parent_folder_id.eq(folder_id))
+        .filter(dsl::active.eq(params.active.unwrap_or(true)));
+
+    if let Some(modified_after) = params.modified_after {
+        query = query.filter(dsl::updated_at.ge(modified_after));
+    }
+    if let Some(modified_before) = params.modified_before {
+        query = query.filter(dsl::updated_at.lt(modified_before));
+    }
+
+    let descending = params.order.unwrap_or_default() == SortOrder::Desc;
+    query = match (params.sort.unwrap_or_default(), descending) {
+        (SortField::Title | SortField::Size, false) => query.order(dsl::title.asc()),
+        (SortField::Title | SortField::Size, true) => query.order(dsl::title.desc()),
+        (SortField::Created, false) => query.order(dsl::created_at.asc()),
+        (SortField::Created, true) => query.order(dsl::created_at.desc()),
+        (SortField::Updated, false) => query.order(dsl::updated_at.asc()),
+        (SortField::Updated, true) => query.order(dsl::updated_at.desc()),
+    };
+    // Keeps the order stable between pages
+    query.then_order_by(dsl::id.asc())
+}
+
+///
+/// The page of the owner's folders in `folder_id` requested in `params`
+///
+pub fn get_folders_page(conn: &mut SqliteConnection, owner: &Owner, folder_id: &str, params: &QueryParams) -> Result<PageDto<FolderDto>, DbError> {
+    let total: i64 = filtered_folders(owner, folder_id, params)
+        .count()
+        .get_result(conn)?;
+    let folders = filtered_folders(owner, folder_id, params)
+        .limit(params.page_size())
+        .offset(params.offset())
+        .load::<FolderDto>(conn)?;
+    Ok(PageDto::new(folders, params, total))
+}
+
+///
+/// Loads one of the user's own folders, group folders are loaded through their group
+///
+pub fn get_folder(conn: &mut SqliteConnection, folder_id: &str, user_id: i32) -> Result<FolderDto, DbError> {
+    let folder = dsl::file_folders
+        .filter(dsl::id.eq(folder_id))
+        .filter(dsl::owner_id.eq(user_id))
+        .filter(dsl::group_id.is_null())
+        .filter(dsl::active.eq(true))
+        .first::<FolderDto>(conn)
+        .map_err(|err| format!("Error loading folder folder_id: {}, user_id: {}, Error: {}", folder_id, user_id, err))?;
+    Ok(folder)
+}
+
+///
+/// Creates a folder for `owner`, a group's folders are owned by the member that created them
+///
+pub fn create_folder(conn: &mut SqliteConnection, folder: CreateFolderDto, owner: &Owner, user_id: i32) -> Result<String, DbError> {
+    let (owner_id, group_id) = match owner {
+        Owner::User(owner_id) => (*owner_id, None),
+        Owner::Group(group_id) => (user_id, Some(group_id.clone())),
+    };
+    let folder = FolderDto {
+        id: Uuid::new_v4().to_string(),
+        owner_id,
+        parent_folder_id: folder.parent_folder_id,
+        title: folder.title,
+        description: folder.description,
+        created_at: None,
+        updated_at: None,
+        created_by: user_id,
+        updated_by: user_id,
+        active: true,
+        deleted_at: None,
+        deleted_by: None,
+        group_id,
+    };
+
+    diesel::insert_into(dsl::file_folders)
+        .values(&folder)
+        .execute(conn)?;
+    Ok(folder.id)
+}
+
+///
+/// Saves the changes to a folder, the caller must have checked the user may change it
+///
+pub fn update_folder(conn: &mut SqliteConnection, folder_id: &str, folder: UpdateFolderDto, user_id: i32) -> Result<usize, DbError> {
+    Ok(diesel::update(dsl::file_folders.filter(dsl::id.eq(folder_id)))
+        .set((
+            dsl::title.eq(folder.title),
+            dsl::parent_folder_id.eq(folder.parent_folder_id),
+            dsl::description.eq(folder.description),
+            dsl::updated_by.eq(user_id),
+            dsl::updated_at.eq(chrono::Utc::now().naive_utc())))
+        .execute(conn)?)
+}
+
+///
+/// Moves a folder to the trash, the caller must have checked the user may delete it
+///
+pub fn trash_folder(conn: &mut SqliteConnection, folder_id: &str, user_id: i32) -> Result<usize, DbError> {
+    let now = chrono::Utc::now().naive_utc();
+    Ok(diesel::update(dsl::file_folders.filter(dsl::id.eq(folder_id)))
+        .set((
+            dsl::active.eq(false),
+            dsl::deleted_at.eq(now),
+            dsl::deleted_by.eq(user_id),
+            dsl::updated_by.eq(user_id),
+            dsl::updated_at.eq(now)))
+        .execute(conn)?)
+}
+
+///
+/// Checks whether a folder has any active sub folders or files
+///
+pub fn is_folder_empty(conn: &mut SqliteConnection, folder_id: &str, owner: &Owner) -> Result<bool, DbError> {
+    use crate::schema::files;
+
+    let folders: i64 = owned_folders(owner)
+        .filter(dsl::parent_folder_id.eq(folder_id))
+        .filter(dsl::active.eq(true))
+        .count()
+        .get_result(conn)?;
+    let mut files = files::table
+        .filter(files::folder_id.eq(folder_id))
+        .filter(files::active.eq(true))
+        .into_boxed();
+    files = match owner {
+        Owner::User(owner_id) => files.filter(files::owner_id.eq(*owner_id)).filter(files::group_id.is_null()),
+        Owner::Group(group_id) => files.filter(files::group_id.eq(group_id)),
+    };
+    let files: i64 = files.count().get_result(conn)?;
+    Ok(folders == 0 && files == 0)
+}
+
+///
+/// Checks that `folder_id` can hold content of the owner, that is either the owner's root folder
+/// or one of the owner's active folders
+///
+pub fn is_valid_parent(conn: &mut SqliteConnection, folder_id: &str, root_folder_id: &str, owner: &Owner) -> Result<bool, DbError> {
+    if folder_id == root_folder_id {
+        return Ok(true);
+    }
+    let folders: i64 = owned_folders(owner)
+        .filter(dsl::id.eq(folder_id))
+        .filter(dsl::active.eq(true))
+        .count()
+        .get_result(conn)?;
+    Ok(folders > 0)
+}
+
+///
+/// Checks whether `folder_id` is `ancestor_id` or lies somewhere below it
+///
+/// Fails when the folders above `folder_id` go deeper than `MAX_FOLDER_DEPTH`, which only
+/// happens if they loop
+///
+pub fn is_descendant_of(conn: &mut SqliteConnection, folder_id: &str, ancestor_id: &str, owner: &Owner) -> Result<bool, DbError> {
+    if folder_id == ancestor_id {
+        return Ok(true);
+    }
+    let ancestors = get_ancestor_folders(conn, folder_id, owner)?;
+    if ancestors.len() > MAX_FOLDER_DEPTH as usize {
+        return Err(format!("Folder {} is nested deeper than {} levels", folder_id, MAX_FOLDER_DEPTH).into());
+    }
+    // The owner's root folder has no row, it is only found as the parent of the top folder
+    Ok(ancestors.iter().any(|folder| folder.id == ancestor_id || folder.parent_folder_id == ancestor_id))
+}
+
+// Appends the condition restricting the `file_folders` rows named `alias` to the owner's
+fn bind_owner<'a>(query: BoxedSqlQuery<'a, Sqlite, SqlQuery>, alias: &str, owner: &'a Owner) -> BoxedSqlQuery<'a, Sqlite, SqlQuery> {
+    match owner {
+        Owner::User(owner_id) => query
+            .sql(format!(" AND {alias}.owner_id = ? AND {alias}.group_id IS NULL"))
+            .bind::<Integer, _>(*owner_id),
+        Owner::Group(group_id) => query
+            .sql(format!(" AND {alias}.group_id = ?"))
+            .bind::<Text, _>(group_id),
+    }
+}
+
+///
+/// The folder and every folder above it, nearest first, the owner's root folder has no
+/// file_folders row and is not included
+///
+/// Stops after `MAX_FOLDER_DEPTH` levels above the folder, so looping parents end the walk
+///
+pub fn get_ancestor_folders(conn: &mut SqliteConnection, folder_id: &str, owner: &Owner) -> Result<Vec<FolderDto>, DbError> {
+    let mut query = diesel::sql_query(
+        "WITH RECURSIVE ancestors(id, parent_folder_id, depth) AS (\
+         SELECT id, parent_folder_id, 0 FROM file_folders WHERE id = ?")
+        .into_boxed::<Sqlite>()
+        .bind::<Text, _>(folder_id);
+    query = bind_owner(query, "file_folders", owner);
+    query = query.sql(
+        " UNION ALL SELECT parent.id, parent.parent_folder_id, ancestors.depth + 1 \
+         FROM file_folders parent JOIN ancestors ON parent.id = ancestors.parent_folder_id \
+         WHERE ancestors.depth < ?")
+        .bind::<Integer, _>(MAX_FOLDER_DEPTH);
+    query = bind_owner(query, "parent", owner);
+
+    Ok(query
+        .sql(") SELECT file_folders.* FROM file_folders JOIN ancestors ON file_folders.id = ancestors.id ORDER BY ancestors.depth")
+        .load::<FolderDto>(conn)?)
+}
+
+///
+/// The active folders below `folder_id`, down to `depth` levels or all of them for None
+///
+pub fn get_descendant_folders(conn: &mut SqliteConnection, folder_id: &str, owner: &Owner, depth: Option<i32>) -> Result<Vec<FolderDto>, DbError> {
+    let mut query = diesel::sql_query(
+        "WITH RECURSIVE tree(id, depth) AS (\
+         SELECT id, 1 FROM file_folders WHERE parent_folder_id = ? AND active = 1")
+        .into_boxed::<Sqlite>()
+        .bind::<Text, _>(folder_id);
+    query = bind_owner(query, "file_folders", owner);
+    query = match depth {
+        Some(depth) => query.sql(
+            " UNION ALL SELECT child.id, tree.depth + 1 \
+             FROM file_folders child JOIN tree ON child.parent_folder_id = tree.id \
+             WHERE child.active = 1 AND tree.depth < ?")
+            .bind::<Integer, _>(depth),
+        // Levels are not counted, so UNION drops the folders found before and looping parents end the walk
+        None => query.sql(
+            " UNION SELECT child.id, 0 \
+             FROM file_folders child JOIN tree ON child.parent_folder_id = tree.id \
+             WHERE child.active = 1"),
+    };
+    query = bind_owner(query, "child", owner);
+
+    Ok(query
+        .sql(") SELECT file_folders.* FROM file_folders JOIN tree ON file_folders.id = tree.id ORDER BY file_folders.title")
+        .load::<FolderDto>(conn)?)
+}
+
+///
+/// The tree of active folders below `folder_id` down to `depth` levels or all of them for None,
+/// with the files of every folder if `with_files` is set
+///
+pub fn get_folder_tree(conn: &mut SqliteConnection, folder_id: &str, folder: Option<FolderDto>, owner: &Owner, depth: Option<i32>, with_files: bool) -> Result<FolderTreeDto, DbError> {
+    let mut children: HashMap<String, Vec<FolderDto>> = HashMap::new();
+    for child in get_descendant_folders(conn, folder_id, owner, depth)? {
+        children.entry(child.parent_folder_id.clone()).or_default().push(child);
+    }
+
+    let mut files: HashMap<String, Vec<FileDto>> = HashMap::new();
+    if with_files {
+        let mut folder_ids: Vec<String> = children.values().flatten().map(|child| child.id.clone()).collect();
+        folder_ids.push(folder_id.to_string());
+        for file in get_files_in_folders(conn, owner, &folder_ids)? {
+            files.entry(file.folder_id.clone()).or_default().push(file);
+        }
+    }
 
+    Ok(build_tree(folder_id.to_string(), folder, &mut children, &mut files))
+}
 
+fn build_tree(id: String, folder: Option<FolderDto>, children: &mut HashMap<String, Vec<FolderDto>>, files: &mut HashMap<String, Vec<FileDto>>) -> FolderTreeDto {
+    let folders = children
+        .remove(&id)
+        .unwrap_or_default()
+        .into_iter()
+        .map(|child| build_tree(child.id.clone(), Some(child), children, files))
+        .collect();
+    let files = files.remove(&id).unwrap_or_default();
+    FolderTreeDto { id, folder, folders, files }
+}
 
+///
+/// The folder itself followed by every folder above it, up to the owner's root folder
+///
+pub fn get_folder_ancestors(conn: &mut SqliteConnection, folder_id: &str, owner_id: i32) -> Result<Vec<String>, DbError> {
+    let folders = get_ancestor_folders(conn, folder_id, &Owner::User(owner_id))?;
+    let mut ancestors: Vec<String> = folders.iter().map(|folder| folder.id.clone()).collect();
+    if ancestors.is_empty() {
+        ancestors.push(folder_id.to_string());
+    }
+    if let Some(top) = folders.last() {
+        ancestors.push(top.parent_folder_id.clone());
+    }
+    Ok(ancestors)
+}
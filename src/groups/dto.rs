@@ -0,0 +1,101 @@
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use crate::files::dto::FileDto;
+use crate::folders::dto::FolderDto;
+use crate::schema::{groups, group_members};
+
+// Values of `group_members.role`
+pub const ROLE_VIEWER: i32 = 1;
+pub const ROLE_EDITOR: i32 = 2;
+pub const ROLE_ADMIN: i32 = 3;
+
+///
+/// Who files and folders belong to, a single user or a group
+///
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Owner {
+    User(i32),
+    Group(String),
+}
+
+impl From<&FileDto> for Owner {
+    fn from(file: &FileDto) -> Owner {
+        match &file.group_id {
+            Some(group_id) => Owner::Group(group_id.clone()),
+            None => Owner::User(file.owner_id),
+        }
+    }
+}
+
+impl From<&FolderDto> for Owner {
+    fn from(folder: &FolderDto) -> Owner {
+        match &folder.group_id {
+            Some(group_id) => Owner::Group(group_id.clone()),
+            None => Owner::User(folder.owner_id),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+#[derive(Queryable, Selectable, Insertable)]
+#[diesel(table_name = groups)]
+#[serde(rename_all = "camelCase")]
+pub struct GroupDto {
+    // The unique identifier for the group
+    pub id: String,
+    pub name: String,
+    // Additional description of the group
+    pub description: Option<String>,
+    // The group's root folder
+    pub folder_id: String,
+    pub created_at: Option<chrono::NaiveDateTime>,
+    pub updated_at: Option<chrono::NaiveDateTime>,
+    pub created_by: i32,
+    pub updated_by: i32,
+    pub active: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+#[derive(Queryable, Selectable, Insertable)]
+#[diesel(table_name = group_members)]
+#[serde(rename_all = "camelCase")]
+pub struct GroupMemberDto {
+    // The unique identifier for the membership
+    pub id: String,
+    pub group_id: String,
+    pub user_id: i32,
+    // 1: viewer, 2: editor, 3: admin
+    pub role: i32,
+    pub created_at: Option<chrono::NaiveDateTime>,
+    pub updated_at: Option<chrono::NaiveDateTime>,
+    pub created_by: i32,
+    pub updated_by: i32,
+    pub active: bool,
+}
+
+#[derive(Debug, Clone, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct CreateGroupDto {
+    pub name: String,
+    pub description: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct AddGroupMemberDto {
+    // The user to add
+    pub username: String,
+    // 1: viewer, 2: editor, 3: admin
+    pub role: i32,
+}
+
+///
+/// A member of a group as listed to the other members
+///
+#[derive(Debug, Clone, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct MemberDto {
+    pub user_id: i32,
+    pub username: String,
+    pub role: i32,
+}
@@ -0,0 +1,243 @@
+pub mod dto;
+pub mod service;
+
+use actix_web::{
+    delete, get, post, web, Error, HttpResponse,
+};
+
+use log::info;
+
+use crate::auth::jwt_auth;
+use crate::auth::service::get_user_by_username;
+use crate::shared::common::ServiceError;
+use crate::shared::common::AppState;
+use crate::shared::dto::CreateResponseDto;
+use crate::sharing::dto::Permission;
+use service::{create_group, get_user_groups, get_member_group, get_group_members, add_group_member, remove_group_member, is_last_admin};
+
+use dto::{GroupDto, CreateGroupDto, AddGroupMemberDto, MemberDto, ROLE_ADMIN};
+
+///
+/// Gets all groups the user is a member of
+///
+#[utoipa::path(
+    get,
+    tag = "Groups",
+    path = "/api/groups",
+    responses(
+        (status = 200, description = "Successfully retrieved the user's groups", body = [Vec<GroupDto>])
+    )
+)]
+#[get("")]
+pub async fn get_groups_handler(
+    app: web::Data<AppState>,
+    jwt: jwt_auth::JwtMiddleware,
+) -> Result<HttpResponse, Error> {
+    let mut conn = app
+        .get_connection()
+        .map_err(|err| ServiceError::NotFound(err.to_string()))?;
+
+    match get_user_groups(&mut conn, jwt.user_id) {
+        Ok(groups) => Ok(HttpResponse::Ok().json(groups)),
+        Err(err) => Err(ServiceError::NotFound(err.to_string()).into()),
+    }
+}
+
+///
+/// Gets a group by it's group id
+///
+#[utoipa::path(
+    get,
+    tag = "Groups",
+    path = "/api/groups/{group_id}",
+    responses(
+        (status = 200, description = "Successfully retrieved a group", body = [GroupDto])
+    )
+)]
+#[get("/{group_id}")]
+pub async fn get_group_handler(
+    app: web::Data<AppState>,
+    jwt: jwt_auth::JwtMiddleware,
+    path: web::Path<String>,
+) -> Result<HttpResponse, Error> {
+    let group_id = path.to_string();
+
+    let mut conn = app
+        .get_connection()
+        .map_err(|err| ServiceError::NotFound(err.to_string()))?;
+
+    match get_member_group(&mut conn, &group_id, jwt.user_id, Permission::Read) {
+        Ok(group) => Ok(HttpResponse::Ok().json(group)),
+        Err(err) => Err(ServiceError::NotFound(err.to_string()).into()),
+    }
+}
+
+///
+/// Creates a group
+///
+/// The group gets its own root folder, everything created below it is owned by the group.
+/// The user creating the group becomes its admin
+///
+#[utoipa::path(
+    post,
+    tag = "Groups",
+    path = "/api/groups",
+    request_body = CreateGroupDto,
+    responses(
+        (status = 201, description = "Successfully created a group", body = [CreateResponseDto])
+    )
+)]
+#[post("")]
+pub async fn create_group_handler(
+    app: web::Data<AppState>,
+    jwt: jwt_auth::JwtMiddleware,
+    data: web::Json<CreateGroupDto>,
+) -> Result<HttpResponse, Error> {
+    let user_id = jwt.user_id;
+
+    let mut conn = app
+        .get_connection()
+        .map_err(|err| ServiceError::NotFound(err.to_string()))?;
+
+    let group_id = create_group(&mut conn, app.get_storage_service().as_ref(), data.into_inner(), user_id)
+        .map_err(|err| ServiceError::InternalServerError(err.to_string()))?;
+    info!("Created group: {} for user: {}", group_id, user_id);
+
+    Ok(HttpResponse::Created().json(CreateResponseDto::ok_with_id(group_id)))
+}
+
+///
+/// Gets the members of a group
+///
+#[utoipa::path(
+    get,
+    tag = "Groups",
+    path = "/api/groups/{group_id}/members",
+    responses(
+        (status = 200, description = "Successfully retrieved the group's members", body = [Vec<MemberDto>])
+    )
+)]
+#[get("/{group_id}/members")]
+pub async fn get_group_members_handler(
+    app: web::Data<AppState>,
+    jwt: jwt_auth::JwtMiddleware,
+    path: web::Path<String>,
+) -> Result<HttpResponse, Error> {
+    let group_id = path.to_string();
+
+    let mut conn = app
+        .get_connection()
+        .map_err(|err| ServiceError::NotFound(err.to_string()))?;
+
+    get_member_group(&mut conn, &group_id, jwt.user_id, Permission::Read).map_err(|err| ServiceError::NotFound(err.to_string()))?;
+
+    match get_group_members(&mut conn, &group_id) {
+        Ok(members) => Ok(HttpResponse::Ok().json(members)),
+        Err(err) => Err(ServiceError::NotFound(err.to_string()).into()),
+    }
+}
+
+///
+/// Adds a user to a group
+///
+/// 1 makes the user a viewer, 2 an editor and 3 an admin of the group. Adding a member
+/// again changes their role. Only admins can manage the members
+///
+#[utoipa::path(
+    post,
+    tag = "Groups",
+    path = "/api/groups/{group_id}/members",
+    request_body = AddGroupMemberDto,
+    responses(
+        (status = 201, description = "Successfully added a member", body = [CreateResponseDto])
+    )
+)]
+#[post("/{group_id}/members")]
+pub async fn add_group_member_handler(
+    app: web::Data<AppState>,
+    jwt: jwt_auth::JwtMiddleware,
+    path: web::Path<String>,
+    data: web::Json<AddGroupMemberDto>,
+) -> Result<HttpResponse, Error> {
+    let user_id = jwt.user_id;
+    let group_id = path.to_string();
+    let member = data.into_inner();
+
+    let mut conn = app
+        .get_connection()
+        .map_err(|err| ServiceError::NotFound(err.to_string()))?;
+
+    get_member_group(&mut conn, &group_id, user_id, Permission::Owner).map_err(|err| ServiceError::NotFound(err.to_string()))?;
+    if Permission::from_role(member.role).is_none() {
+        return Err(ServiceError::BadRequest(format!("Invalid role {}", member.role)).into());
+    }
+
+    let new_member = get_user_by_username(&mut conn, &member.username).map_err(|err| ServiceError::NotFound(err.to_string()))?;
+    let new_member_id = new_member.id.unwrap_or_default();
+    if member.role != ROLE_ADMIN && is_last_admin(&mut conn, &group_id, new_member_id).map_err(|err| ServiceError::InternalServerError(err.to_string()))? {
+        return Err(ServiceError::BadRequest("A group needs at least one admin".to_string()).into());
+    }
+
+    match add_group_member(&mut conn, &group_id, new_member_id, member.role, user_id) {
+        Ok(member_id) => {
+            info!("Added user: {} to group: {} as role: {}", new_member_id, group_id, member.role);
+            Ok(HttpResponse::Created().json(CreateResponseDto::ok_with_id(member_id)))
+        }
+        Err(err) => Err(ServiceError::InternalServerError(err.to_string()).into()),
+    }
+}
+
+///
+/// Removes a user from a group
+///
+/// Admins can remove any member, other members can only leave the group themselves
+///
+#[utoipa::path(
+    delete,
+    tag = "Groups",
+    path = "/api/groups/{group_id}/members/{user_id}",
+    responses(
+        (status = 204, description = "Successfully removed a member")
+    )
+)]
+#[delete("/{group_id}/members/{user_id}")]
+pub async fn remove_group_member_handler(
+    app: web::Data<AppState>,
+    jwt: jwt_auth::JwtMiddleware,
+    path: web::Path<(String, i32)>,
+) -> Result<HttpResponse, Error> {
+    let user_id = jwt.user_id;
+    let (group_id, member_id) = path.into_inner();
+
+    let mut conn = app
+        .get_connection()
+        .map_err(|err| ServiceError::NotFound(err.to_string()))?;
+
+    let permission = if member_id == user_id { Permission::Read } else { Permission::Owner };
+    get_member_group(&mut conn, &group_id, user_id, permission).map_err(|err| ServiceError::NotFound(err.to_string()))?;
+    if is_last_admin(&mut conn, &group_id, member_id).map_err(|err| ServiceError::InternalServerError(err.to_string()))? {
+        return Err(ServiceError::BadRequest("A group needs at least one admin".to_string()).into());
+    }
+
+    match remove_group_member(&mut conn, &group_id, member_id, user_id) {
+        Ok(0) => Err(ServiceError::NotFound(format!("User {} is not a member of group {}", member_id, group_id)).into()),
+        Ok(_) => {
+            info!("Removed user: {} from group: {}", member_id, group_id);
+            Ok(HttpResponse::NoContent().finish())
+        }
+        Err(err) => Err(ServiceError::InternalServerError(err.to_string()).into()),
+    }
+}
+
+pub fn config(conf: &mut web::ServiceConfig) {
+    let scope = web::scope("/groups")
+            .service(get_groups_handler)
+            .service(get_group_handler)
+            .service(create_group_handler)
+            .service(get_group_members_handler)
+            .service(add_group_member_handler)
+            .service(remove_group_member_handler)
+            ;
+
+    conf.service(scope);
+}
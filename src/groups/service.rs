@@ -0,0 +1,184 @@
+use crate::shared::common::{DbError, StorageService};
+use super::dto::{GroupDto, GroupMemberDto, CreateGroupDto, MemberDto, Owner, ROLE_ADMIN};
+use crate::sharing::dto::Permission;
+use diesel::prelude::*;
+use uuid::Uuid;
+
+use crate::schema::{groups, group_members, users};
+
+///
+/// Creates a group with its root folder in storage, the creating user becomes its first admin
+///
+pub fn create_group(conn: &mut SqliteConnection, storage: &dyn StorageService, group: CreateGroupDto, user_id: i32) -> Result<String, DbError> {
+    let group = GroupDto {
+        id: Uuid::new_v4().to_string(),
+        name: group.name,
+        description: group.description,
+        folder_id: Uuid::new_v4().to_string(),
+        created_at: None,
+        updated_at: None,
+        created_by: user_id,
+        updated_by: user_id,
+        active: true,
+    };
+
+    conn.transaction(|conn| {
+        diesel::insert_into(groups::table)
+            .values(&group)
+            .execute(conn)?;
+        add_group_member(conn, &group.id, user_id, ROLE_ADMIN, user_id)
+    })?;
+
+    storage.create_folder(&group.folder_id)?;
+
+    Ok(group.id)
+}
+
+///
+/// Groups the user is an active member of
+///
+pub fn get_user_groups(conn: &mut SqliteConnection, user_id: i32) -> Result<Vec<GroupDto>, DbError> {
+    Ok(groups::table
+        .inner_join(group_members::table.on(group_members::group_id.eq(groups::id)))
+        .filter(group_members::user_id.eq(user_id))
+        .filter(group_members::active.eq(true))
+        .filter(groups::active.eq(true))
+        .select(GroupDto::as_select())
+        .load::<GroupDto>(conn)?)
+}
+
+///
+/// The group whose root folder is `folder_id`, if there is one
+///
+pub fn get_group_by_folder(conn: &mut SqliteConnection, folder_id: &str) -> Result<Option<GroupDto>, DbError> {
+    Ok(groups::table
+        .filter(groups::folder_id.eq(folder_id))
+        .filter(groups::active.eq(true))
+        .first::<GroupDto>(conn)
+        .optional()?)
+}
+
+///
+/// The access the user has to everything owned by a group, given by their role in it
+///
+pub fn get_group_permission(conn: &mut SqliteConnection, group_id: &str, user_id: i32) -> Result<Option<Permission>, DbError> {
+    let role = group_members::table
+        .filter(group_members::group_id.eq(group_id))
+        .filter(group_members::user_id.eq(user_id))
+        .filter(group_members::active.eq(true))
+        .select(group_members::role)
+        .first::<i32>(conn)
+        .optional()?;
+    Ok(role.and_then(Permission::from_role))
+}
+
+///
+/// Loads a group the user has at least `permission` on through their role
+///
+pub fn get_member_group(conn: &mut SqliteConnection, group_id: &str, user_id: i32, permission: Permission) -> Result<GroupDto, DbError> {
+    let group = groups::table
+        .filter(groups::id.eq(group_id))
+        .filter(groups::active.eq(true))
+        .first::<GroupDto>(conn)
+        .map_err(|err| format!("Error loading group group_id: {}, user_id: {}, Error: {}", group_id, user_id, err))?;
+
+    match get_group_permission(conn, group_id, user_id)? {
+        Some(granted) if granted >= permission => Ok(group),
+        _ => Err(format!("User {} has no {:?} access to group_id: {}", user_id, permission, group_id).into()),
+    }
+}
+
+pub fn get_group_members(conn: &mut SqliteConnection, group_id: &str) -> Result<Vec<MemberDto>, DbError> {
+    Ok(group_members::table
+        .inner_join(users::table.on(users::id.eq(group_members::user_id.nullable())))
+        .filter(group_members::group_id.eq(group_id))
+        .filter(group_members::active.eq(true))
+        .select((group_members::user_id, users::username, group_members::role))
+        .load::<(i32, String, i32)>(conn)?
+        .into_iter()
+        .map(|(user_id, username, role)| MemberDto { user_id, username, role })
+        .collect())
+}
+
+///
+/// Adds a user to a group, adding a member again changes their role
+///
+pub fn add_group_member(conn: &mut SqliteConnection, group_id: &str, user_id: i32, role: i32, added_by: i32) -> Result<String, DbError> {
+    let member_id = group_members::table
+        .filter(group_members::group_id.eq(group_id))
+        .filter(group_members::user_id.eq(user_id))
+        .filter(group_members::active.eq(true))
+        .select(group_members::id)
+        .first::<String>(conn)
+        .optional()?;
+
+    match member_id {
+        Some(member_id) => {
+            diesel::update(group_members::table.filter(group_members::id.eq(&member_id)))
+                .set((
+                    group_members::role.eq(role),
+                    group_members::updated_by.eq(added_by),
+                    group_members::updated_at.eq(chrono::Utc::now().naive_utc())))
+                .execute(conn)?;
+            Ok(member_id)
+        }
+        None => {
+            let member = GroupMemberDto {
+                id: Uuid::new_v4().to_string(),
+                group_id: group_id.to_string(),
+                user_id,
+                role,
+                created_at: None,
+                updated_at: None,
+                created_by: added_by,
+                updated_by: added_by,
+                active: true,
+            };
+            diesel::insert_into(group_members::table)
+                .values(&member)
+                .execute(conn)?;
+            Ok(member.id)
+        }
+    }
+}
+
+///
+/// Checks whether the user is the only admin left in a group
+///
+pub fn is_last_admin(conn: &mut SqliteConnection, group_id: &str, user_id: i32) -> Result<bool, DbError> {
+    let admins: Vec<i32> = group_members::table
+        .filter(group_members::group_id.eq(group_id))
+        .filter(group_members::role.eq(ROLE_ADMIN))
+        .filter(group_members::active.eq(true))
+        .select(group_members::user_id)
+        .load(conn)?;
+    Ok(admins == [user_id])
+}
+
+pub fn remove_group_member(conn: &mut SqliteConnection, group_id: &str, user_id: i32, removed_by: i32) -> Result<usize, DbError> {
+    Ok(diesel::update(group_members::table
+            .filter(group_members::group_id.eq(group_id))
+            .filter(group_members::user_id.eq(user_id))
+            .filter(group_members::active.eq(true)))
+        .set((
+            group_members::active.eq(false),
+            group_members::updated_by.eq(removed_by),
+            group_members::updated_at.eq(chrono::Utc::now().naive_utc())))
+        .execute(conn)?)
+}
+
+///
+/// Root folder of a user or a group, the base of their folders in storage
+///
+pub fn get_owner_root(conn: &mut SqliteConnection, owner: &Owner) -> Result<String, DbError> {
+    Ok(match owner {
+        Owner::User(user_id) => users::table
+            .filter(users::id.eq(user_id))
+            .select(users::folder_id)
+            .first::<String>(conn)?,
+        Owner::Group(group_id) => groups::table
+            .filter(groups::id.eq(group_id))
+            .select(groups::folder_id)
+            .first::<String>(conn)?,
+    })
+}
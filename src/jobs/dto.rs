@@ -0,0 +1,118 @@
+use std::fmt;
+use std::io::ErrorKind;
+
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use crate::schema::jobs;
+use crate::shared::common::DbError;
+
+// Values of `jobs.status`
+pub const JOB_QUEUED: i32 = 0;
+pub const JOB_RUNNING: i32 = 1;
+pub const JOB_DONE: i32 = 2;
+// Gave up after `max_attempts`, the job stays until an admin retries it
+pub const JOB_FAILED: i32 = 3;
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+#[derive(Queryable, Selectable, Insertable)]
+#[diesel(table_name = jobs)]
+#[serde(rename_all = "camelCase")]
+pub struct JobDto {
+    pub id: String,
+    pub kind: String,
+    // The job as JSON
+    pub payload: String,
+    // 0: queued, 1: running, 2: done, 3: failed
+    pub status: i32,
+    pub attempts: i32,
+    pub max_attempts: i32,
+    // The job is not run before this time
+    pub run_at: chrono::NaiveDateTime,
+    // Why the last attempt failed
+    pub last_error: Option<String>,
+    pub created_at: Option<chrono::NaiveDateTime>,
+    pub updated_at: Option<chrono::NaiveDateTime>,
+    pub created_by: i32,
+    pub updated_by: i32,
+    pub active: bool,
+}
+
+///
+/// Work done in the background after it was queued
+///
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Job {
+    // Indexes the text of a file's contents for search
+    IndexContents { file_id: String, blob_hash: String, media_type: String },
+    // Makes the thumbnails of the image stored at `path` and `name`
+    CreateThumbnails { path: String, name: String },
+}
+
+impl Job {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Job::IndexContents { .. } => "index_contents",
+            Job::CreateThumbnails { .. } => "create_thumbnails",
+        }
+    }
+}
+
+///
+/// Why a job failed, decides whether it is attempted again
+///
+#[derive(Debug)]
+pub enum JobError {
+    // May work on a later attempt, like a storage or database outage
+    Retry(DbError),
+    // Would fail the same way every time, like missing contents or an unsupported image
+    Permanent(String),
+}
+
+impl fmt::Display for JobError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            JobError::Retry(err) => write!(f, "{}", err),
+            JobError::Permanent(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl From<std::io::Error> for JobError {
+    fn from(err: std::io::Error) -> JobError {
+        match err.kind() {
+            ErrorKind::NotFound | ErrorKind::InvalidData | ErrorKind::FileTooLarge | ErrorKind::Unsupported => JobError::Permanent(err.to_string()),
+            _ => JobError::Retry(err.into()),
+        }
+    }
+}
+
+impl From<DbError> for JobError {
+    fn from(err: DbError) -> JobError {
+        match err.downcast::<std::io::Error>() {
+            Ok(err) => JobError::from(*err),
+            Err(err) => JobError::Retry(err),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct JobParams {
+    pub status: Option<i32>,
+    pub kind: Option<String>,
+    // Most jobs returned, newest first
+    pub limit: Option<i64>,
+}
+
+///
+/// Number of jobs in each status
+///
+#[derive(Debug, Clone, Default, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct JobStatsDto {
+    pub queued: i64,
+    pub running: i64,
+    pub done: i64,
+    pub failed: i64,
+}
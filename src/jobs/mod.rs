@@ -0,0 +1,169 @@
+pub mod dto;
+pub mod service;
+pub mod worker;
+
+use actix_web::{
+    get, post, web, Error, HttpResponse,
+};
+
+use log::info;
+
+use crate::auth::jwt_auth;
+use crate::shared::common::ServiceError;
+use crate::shared::common::AppState;
+use service::{get_jobs, get_job, get_job_stats, retry_job};
+
+use dto::{JobDto, JobParams, JobStatsDto};
+
+const DEFAULT_LIMIT: i64 = 100;
+const MAX_LIMIT: i64 = 1000;
+
+///
+/// Lists the background jobs, newest first
+///
+/// The jobs can be filtered by `status` (0: queued, 1: running, 2: done, 3: failed) and `kind`,
+/// at most `limit` jobs are returned, 100 by default. Only admins can see the jobs
+///
+#[utoipa::path(
+    get,
+    tag = "Jobs",
+    path = "/api/jobs",
+    responses(
+        (status = 200, description = "Successfully retrieved the jobs", body = [Vec<JobDto>])
+    )
+)]
+#[get("")]
+pub async fn get_jobs_handler(
+    app: web::Data<AppState>,
+    jwt: jwt_auth::JwtMiddleware,
+    query: web::Query<JobParams>,
+) -> Result<HttpResponse, Error> {
+    let mut conn = app
+        .get_connection()
+        .map_err(|err| ServiceError::NotFound(err.to_string()))?;
+
+    require_admin(&app, jwt.user_id)?;
+
+    let limit = query.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+    match get_jobs(&mut conn, &query, limit) {
+        Ok(jobs) => Ok(HttpResponse::Ok().json(jobs)),
+        Err(err) => Err(ServiceError::InternalServerError(err.to_string()).into()),
+    }
+}
+
+///
+/// Counts the background jobs in each status
+///
+#[utoipa::path(
+    get,
+    tag = "Jobs",
+    path = "/api/jobs/stats",
+    responses(
+        (status = 200, description = "Successfully counted the jobs", body = [JobStatsDto])
+    )
+)]
+#[get("/stats")]
+pub async fn get_job_stats_handler(
+    app: web::Data<AppState>,
+    jwt: jwt_auth::JwtMiddleware,
+) -> Result<HttpResponse, Error> {
+    let mut conn = app
+        .get_connection()
+        .map_err(|err| ServiceError::NotFound(err.to_string()))?;
+
+    require_admin(&app, jwt.user_id)?;
+
+    match get_job_stats(&mut conn) {
+        Ok(stats) => Ok(HttpResponse::Ok().json(stats)),
+        Err(err) => Err(ServiceError::InternalServerError(err.to_string()).into()),
+    }
+}
+
+///
+/// Gets a background job by it's job id
+///
+#[utoipa::path(
+    get,
+    tag = "Jobs",
+    path = "/api/jobs/{job_id}",
+    responses(
+        (status = 200, description = "Successfully retrieved a job", body = [JobDto])
+    )
+)]
+#[get("/{job_id}")]
+pub async fn get_job_handler(
+    app: web::Data<AppState>,
+    jwt: jwt_auth::JwtMiddleware,
+    path: web::Path<String>,
+) -> Result<HttpResponse, Error> {
+    let job_id = path.to_string();
+
+    let mut conn = app
+        .get_connection()
+        .map_err(|err| ServiceError::NotFound(err.to_string()))?;
+
+    require_admin(&app, jwt.user_id)?;
+
+    match get_job(&mut conn, &job_id) {
+        Ok(job) => Ok(HttpResponse::Ok().json(job)),
+        Err(err) => Err(ServiceError::NotFound(err.to_string()).into()),
+    }
+}
+
+///
+/// Queues a failed job again
+///
+/// The job gets all its attempts back and runs as soon as a worker is free
+///
+#[utoipa::path(
+    post,
+    tag = "Jobs",
+    path = "/api/jobs/{job_id}/retry",
+    responses(
+        (status = 200, description = "Successfully queued a job again", body = [JobDto])
+    )
+)]
+#[post("/{job_id}/retry")]
+pub async fn retry_job_handler(
+    app: web::Data<AppState>,
+    jwt: jwt_auth::JwtMiddleware,
+    path: web::Path<String>,
+) -> Result<HttpResponse, Error> {
+    let user_id = jwt.user_id;
+    let job_id = path.to_string();
+
+    let mut conn = app
+        .get_connection()
+        .map_err(|err| ServiceError::NotFound(err.to_string()))?;
+
+    require_admin(&app, user_id)?;
+
+    match retry_job(&mut conn, &job_id, user_id) {
+        Ok(0) => Err(ServiceError::BadRequest(format!("Job {} has not failed", job_id)).into()),
+        Ok(_) => {
+            info!("Queued job: {} again for user: {}", job_id, user_id);
+            let job = get_job(&mut conn, &job_id).map_err(|err| ServiceError::NotFound(err.to_string()))?;
+            Ok(HttpResponse::Ok().json(job))
+        }
+        Err(err) => Err(ServiceError::InternalServerError(err.to_string()).into()),
+    }
+}
+
+// Admins are the users whose ids are listed in `ADMIN_USER_IDS`, ids stay the same when a user is renamed
+fn require_admin(app: &AppState, user_id: i32) -> Result<(), ServiceError> {
+    match app.get_config().admin_user_ids.contains(&user_id) {
+        true => Ok(()),
+        false => Err(ServiceError::Unauthorized),
+    }
+}
+
+pub fn config(conf: &mut web::ServiceConfig) {
+    let scope = web::scope("/jobs")
+            .service(get_jobs_handler)
+            .service(get_job_stats_handler)
+            .service(get_job_handler)
+            .service(retry_job_handler)
+            ;
+
+    conf.service(scope);
+}
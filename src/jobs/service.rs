@@ -0,0 +1,199 @@
+use crate::shared::common::DbError;
+use super::dto::{Job, JobDto, JobParams, JobStatsDto, JOB_DONE, JOB_FAILED, JOB_QUEUED, JOB_RUNNING};
+use diesel::prelude::*;
+use uuid::Uuid;
+
+use crate::schema::jobs;
+
+const MAX_ATTEMPTS: i32 = 5;
+// Failed attempts are retried after 30 seconds, doubling every time up to an hour
+const RETRY_DELAY_SECONDS: i64 = 30;
+const MAX_RETRY_DELAY_SECONDS: i64 = 60 * 60;
+
+///
+/// Queues a job to be run by the next free worker
+///
+pub fn enqueue_job(conn: &mut SqliteConnection, job: &Job, user_id: i32) -> Result<String, DbError> {
+    let job = JobDto {
+        id: Uuid::new_v4().to_string(),
+        kind: job.kind().to_string(),
+        payload: serde_json::to_string(job)?,
+        status: JOB_QUEUED,
+        attempts: 0,
+        max_attempts: MAX_ATTEMPTS,
+        run_at: chrono::Utc::now().naive_utc(),
+        last_error: None,
+        created_at: None,
+        updated_at: None,
+        created_by: user_id,
+        updated_by: user_id,
+        active: true,
+    };
+
+    diesel::insert_into(jobs::table)
+        .values(&job)
+        .execute(conn)?;
+
+    Ok(job.id)
+}
+
+///
+/// Takes the queued job that is due longest and marks it running, None if no job is due
+///
+pub fn claim_next_job(conn: &mut SqliteConnection) -> Result<Option<JobDto>, DbError> {
+    conn.immediate_transaction(|conn| {
+        let now = chrono::Utc::now().naive_utc();
+        let job = jobs::table
+            .filter(jobs::status.eq(JOB_QUEUED))
+            .filter(jobs::run_at.le(now))
+            .filter(jobs::active.eq(true))
+            .order(jobs::run_at.asc())
+            .first::<JobDto>(conn)
+            .optional()?;
+        let Some(job) = job else {
+            return Ok(None);
+        };
+
+        diesel::update(jobs::table.filter(jobs::id.eq(&job.id)))
+            .set((
+                jobs::status.eq(JOB_RUNNING),
+                jobs::attempts.eq(job.attempts + 1),
+                jobs::updated_at.eq(now)))
+            .execute(conn)?;
+
+        Ok(Some(JobDto { status: JOB_RUNNING, attempts: job.attempts + 1, updated_at: Some(now), ..job }))
+    })
+}
+
+pub fn complete_job(conn: &mut SqliteConnection, job_id: &str) -> Result<usize, DbError> {
+    Ok(diesel::update(jobs::table.filter(jobs::id.eq(job_id)))
+        .set((
+            jobs::status.eq(JOB_DONE),
+            jobs::updated_at.eq(chrono::Utc::now().naive_utc())))
+        .execute(conn)?)
+}
+
+///
+/// Records a failed attempt, the job is queued again later or fails for good after its last attempt
+///
+/// A `permanent` failure would happen again, so the job fails for good right away
+///
+pub fn fail_job(conn: &mut SqliteConnection, job: &JobDto, error: &str, permanent: bool) -> Result<usize, DbError> {
+    let now = chrono::Utc::now().naive_utc();
+    let (status, run_at) = match permanent || job.attempts >= job.max_attempts {
+        true => (JOB_FAILED, job.run_at),
+        false => (JOB_QUEUED, now + chrono::Duration::seconds(retry_delay(job.attempts))),
+    };
+
+    Ok(diesel::update(jobs::table.filter(jobs::id.eq(&job.id)))
+        .set((
+            jobs::status.eq(status),
+            jobs::run_at.eq(run_at),
+            jobs::last_error.eq(error),
+            jobs::updated_at.eq(now)))
+        .execute(conn)?)
+}
+
+fn retry_delay(attempts: i32) -> i64 {
+    let doublings = (attempts - 1).clamp(0, 20);
+    (RETRY_DELAY_SECONDS << doublings).min(MAX_RETRY_DELAY_SECONDS)
+}
+
+///
+/// Queues the jobs again that were running when the service stopped
+///
+pub fn reset_running_jobs(conn: &mut SqliteConnection) -> Result<usize, DbError> {
+    Ok(diesel::update(jobs::table.filter(jobs::status.eq(JOB_RUNNING)))
+        .set((
+            jobs::status.eq(JOB_QUEUED),
+            jobs::updated_at.eq(chrono::Utc::now().naive_utc())))
+        .execute(conn)?)
+}
+
+///
+/// Deletes the jobs that were done before `cutoff`, failed jobs are kept
+///
+pub fn delete_done_jobs(conn: &mut SqliteConnection, cutoff: chrono::NaiveDateTime) -> Result<usize, DbError> {
+    Ok(diesel::delete(jobs::table
+            .filter(jobs::status.eq(JOB_DONE))
+            .filter(jobs::updated_at.lt(cutoff)))
+        .execute(conn)?)
+}
+
+pub fn get_jobs(conn: &mut SqliteConnection, params: &JobParams, limit: i64) -> Result<Vec<JobDto>, DbError> {
+    let mut query = jobs::table
+        .filter(jobs::active.eq(true))
+        .into_boxed();
+    if let Some(status) = params.status {
+        query = query.filter(jobs::status.eq(status));
+    }
+    if let Some(kind) = &params.kind {
+        query = query.filter(jobs::kind.eq(kind));
+    }
+
+    Ok(query
+        .order(jobs::created_at.desc())
+        .then_order_by(jobs::id)
+        .limit(limit)
+        .load::<JobDto>(conn)?)
+}
+
+pub fn get_job(conn: &mut SqliteConnection, job_id: &str) -> Result<JobDto, DbError> {
+    Ok(jobs::table
+        .filter(jobs::id.eq(job_id))
+        .filter(jobs::active.eq(true))
+        .first::<JobDto>(conn)?)
+}
+
+pub fn get_job_stats(conn: &mut SqliteConnection) -> Result<JobStatsDto, DbError> {
+    let counts = jobs::table
+        .filter(jobs::active.eq(true))
+        .group_by(jobs::status)
+        .select((jobs::status, diesel::dsl::count_star()))
+        .load::<(i32, i64)>(conn)?;
+
+    let mut stats = JobStatsDto::default();
+    for (status, count) in counts {
+        match status {
+            JOB_QUEUED => stats.queued = count,
+            JOB_RUNNING => stats.running = count,
+            JOB_DONE => stats.done = count,
+            JOB_FAILED => stats.failed = count,
+            _ => {}
+        }
+    }
+    Ok(stats)
+}
+
+///
+/// Queues a failed job again with all its attempts
+///
+pub fn retry_job(conn: &mut SqliteConnection, job_id: &str, user_id: i32) -> Result<usize, DbError> {
+    let now = chrono::Utc::now().naive_utc();
+    Ok(diesel::update(jobs::table
+            .filter(jobs::id.eq(job_id))
+            .filter(jobs::status.eq(JOB_FAILED)))
+        .set((
+            jobs::status.eq(JOB_QUEUED),
+            jobs::attempts.eq(0),
+            jobs::run_at.eq(now),
+            jobs::updated_by.eq(user_id),
+            jobs::updated_at.eq(now)))
+        .execute(conn)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn retries_back_off_exponentially_up_to_an_hour() {
+        assert_eq!(retry_delay(0), 30);
+        assert_eq!(retry_delay(1), 30);
+        assert_eq!(retry_delay(2), 60);
+        assert_eq!(retry_delay(4), 240);
+        assert_eq!(retry_delay(7), 1920);
+        assert_eq!(retry_delay(8), MAX_RETRY_DELAY_SECONDS);
+        assert_eq!(retry_delay(i32::MAX), MAX_RETRY_DELAY_SECONDS);
+    }
+}
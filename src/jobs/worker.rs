@@ -0,0 +1,97 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::previews::generate_thumbnails;
+use crate::search::index_file_contents;
+use crate::shared::common::AppState;
+use super::dto::{Job, JobDto, JobError};
+use super::service::{claim_next_job, complete_job, delete_done_jobs, fail_job, reset_running_jobs};
+
+// How often idle workers look for due jobs
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+// How often finished jobs are cleaned up, they are kept for a week
+const CLEANUP_INTERVAL: Duration = Duration::from_secs(60 * 60);
+const DONE_RETENTION_DAYS: i64 = 7;
+
+///
+/// Starts `workers` workers running the queued jobs, and the cleanup of finished ones
+///
+/// Jobs that were running when the service stopped are queued again first
+///
+pub fn start_workers(app: Arc<AppState>, workers: usize) {
+    match app.get_connection().and_then(|mut conn| reset_running_jobs(&mut conn)) {
+        Ok(0) => {}
+        Ok(reset) => log::info!("Queued {} interrupted jobs again", reset),
+        Err(err) => log::error!("Failed to queue interrupted jobs again: {}", err),
+    }
+
+    for worker in 0..workers {
+        actix_web::rt::spawn(run_worker(app.clone(), worker));
+    }
+    actix_web::rt::spawn(run_cleanup(app));
+}
+
+async fn run_worker(app: Arc<AppState>, worker: usize) {
+    let mut interval = actix_web::rt::time::interval(POLL_INTERVAL);
+    loop {
+        interval.tick().await;
+        // Work through everything that is due before waiting again
+        loop {
+            let job = match app.get_connection().and_then(|mut conn| claim_next_job(&mut conn)) {
+                Ok(Some(job)) => job,
+                Ok(None) => break,
+                Err(err) => {
+                    log::error!("Worker {} failed to claim a job: {}", worker, err);
+                    break;
+                }
+            };
+            run_job(&app, job).await;
+        }
+    }
+}
+
+async fn run_job(app: &AppState, job: JobDto) {
+    let result = match serde_json::from_str::<Job>(&job.payload) {
+        Ok(payload) => perform(app, payload).await,
+        Err(err) => Err(JobError::Permanent(format!("Invalid payload: {}", err))),
+    };
+
+    let recorded = app.get_connection().and_then(|mut conn| match &result {
+        Ok(()) => complete_job(&mut conn, &job.id),
+        Err(err) => {
+            let permanent = matches!(err, JobError::Permanent(_));
+            log::warn!("Attempt {} of job: {} ({}) failed{}: {}", job.attempts, job.id, job.kind,
+                if permanent { " for good" } else { "" }, err);
+            fail_job(&mut conn, &job, &err.to_string(), permanent)
+        }
+    });
+    if let Err(err) = recorded {
+        log::error!("Failed to record the result of job: {}: {}", job.id, err);
+    }
+}
+
+async fn perform(app: &AppState, job: Job) -> Result<(), JobError> {
+    match job {
+        Job::IndexContents { file_id, blob_hash, media_type } => {
+            let mut conn = app.get_connection()?;
+            Ok(index_file_contents(app, &mut conn, &file_id, &blob_hash, &media_type).await?)
+        }
+        Job::CreateThumbnails { path, name } => {
+            generate_thumbnails(app.get_storage_service().as_ref(), &path, &name).await?;
+            Ok(())
+        }
+    }
+}
+
+async fn run_cleanup(app: Arc<AppState>) {
+    let mut interval = actix_web::rt::time::interval(CLEANUP_INTERVAL);
+    loop {
+        interval.tick().await;
+        let cutoff = chrono::Utc::now().naive_utc() - chrono::Duration::days(DONE_RETENTION_DAYS);
+        match app.get_connection().and_then(|mut conn| delete_done_jobs(&mut conn, cutoff)) {
+            Ok(0) => {}
+            Ok(deleted) => log::info!("Deleted {} finished jobs", deleted),
+            Err(err) => log::error!("Failed to delete finished jobs: {}", err),
+        }
+    }
+}
@@ -0,0 +1,7 @@
+// A plain text mail to a single recipient
+#[derive(Debug, Clone)]
+pub struct Mail {
+    pub to: String,
+    pub subject: String,
+    pub body: String,
+}
@@ -0,0 +1,35 @@
+use std::io::Error;
+
+use async_trait::async_trait;
+use uuid::Uuid;
+
+use super::dto::Mail;
+use super::MailService;
+
+// Writes every mail as an .eml file into `base_path` instead of sending it
+pub struct FileMailer {
+    base_path: String,
+    from: String,
+}
+
+impl FileMailer {
+    pub fn new(base_path: String, from: String) -> Result<FileMailer, Error> {
+        std::fs::create_dir_all(&base_path)?;
+        Ok(FileMailer {base_path, from})
+    }
+}
+
+#[async_trait(?Send)]
+impl MailService for FileMailer {
+    async fn send_mail(&self, mail: &Mail) -> Result<(), Error> {
+        let now = chrono::Utc::now();
+        // Named after the time it was sent so the files list in order
+        let name = format!("{}-{}.eml", now.format("%Y%m%d%H%M%S%3f"), Uuid::new_v4());
+        let contents = format!(
+            "From: {}\r\nTo: {}\r\nSubject: {}\r\nDate: {}\r\nContent-Type: text/plain; charset=utf-8\r\n\r\n{}\r\n",
+            self.from, mail.to, mail.subject, now.to_rfc2822(), mail.body);
+        tokio::fs::write(format!("{}/{}", self.base_path, name), contents).await?;
+        log::info!("Wrote mail to: {} into {}", mail.to, name);
+        Ok(())
+    }
+}
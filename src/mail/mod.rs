@@ -0,0 +1,63 @@
+pub mod dto;
+pub mod file_mailer;
+pub mod log_mailer;
+pub mod smtp_mailer;
+
+use std::io::{Error, ErrorKind};
+use std::sync::Arc;
+
+use async_trait::async_trait;
+
+use crate::shared::common::Config;
+use dto::Mail;
+use file_mailer::FileMailer;
+use log_mailer::LogMailer;
+use smtp_mailer::SmtpMailer;
+
+#[async_trait(?Send)]
+pub trait MailService: Send + Sync {
+    async fn send_mail(&self, mail: &Mail) -> Result<(), Error>;
+}
+
+///
+/// Creates the mail service selected by `Config::mail_service`
+///
+/// Supported values:
+///  * `log` - writes who the mails go to and their subject to the log, for local testing
+///  * `file` - writes every mail as an .eml file under `MAIL_FILE_PATH`, for local testing
+///  * `smtp` - sends the mails through `SMTP_HOST`, with `SMTP_USERNAME` and `SMTP_PASSWORD` if set
+///
+/// In production mode the mails have to be sent, `log` and `file` are refused
+///
+pub fn create_mail_service(config: &Config, prod_mode: bool) -> Result<Arc<dyn MailService>, Error> {
+    let from = config.mail_from.clone();
+    match config.mail_service.as_str() {
+        service @ ("log" | "file") if prod_mode => Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!("Mail service {} is for local testing, set MAIL_SERVICE to smtp in production mode", service),
+        )),
+        "log" => Ok(Arc::new(LogMailer::new(from))),
+        "file" => {
+            let base_path = std::env::var("MAIL_FILE_PATH").expect("MAIL_FILE_PATH must be set");
+            Ok(Arc::new(FileMailer::new(base_path, from)?))
+        }
+        "smtp" => {
+            let host = std::env::var("SMTP_HOST").expect("SMTP_HOST must be set");
+            let port = std::env::var("SMTP_PORT").unwrap_or("587".to_string());
+            let tls = std::env::var("SMTP_TLS").unwrap_or("true".to_string());
+            let credentials = std::env::var("SMTP_USERNAME").ok()
+                .map(|username| (username, std::env::var("SMTP_PASSWORD").unwrap_or_default()));
+            Ok(Arc::new(SmtpMailer::new(
+                &host,
+                port.parse::<u16>().map_err(|err| Error::new(ErrorKind::InvalidInput, err))?,
+                tls.parse::<bool>().unwrap_or(true),
+                credentials,
+                &from,
+            )?))
+        }
+        other => Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!("Unknown mail service: {}", other),
+        )),
+    }
+}
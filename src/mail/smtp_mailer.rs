@@ -0,0 +1,55 @@
+use std::io::{Error, ErrorKind};
+
+use async_trait::async_trait;
+use lettre::message::{header::ContentType, Mailbox};
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+
+use super::dto::Mail;
+use super::MailService;
+
+pub struct SmtpMailer {
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+    from: Mailbox,
+}
+
+impl SmtpMailer {
+    ///
+    /// Connects with STARTTLS, or plain text when `tls` is false for local relays like MailHog
+    ///
+    pub fn new(host: &str, port: u16, tls: bool, credentials: Option<(String, String)>, from: &str) -> Result<SmtpMailer, Error> {
+        let mut builder = match tls {
+            true => AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(host)
+                .map_err(|err| Error::new(ErrorKind::InvalidInput, err))?,
+            false => AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(host),
+        };
+        builder = builder.port(port);
+        if let Some((username, password)) = credentials {
+            builder = builder.credentials(Credentials::new(username, password));
+        }
+
+        let from = from.parse::<Mailbox>()
+            .map_err(|err| Error::new(ErrorKind::InvalidInput, format!("Invalid sender address: {}, Error: {}", from, err)))?;
+
+        Ok(SmtpMailer {transport: builder.build(), from})
+    }
+}
+
+#[async_trait(?Send)]
+impl MailService for SmtpMailer {
+    async fn send_mail(&self, mail: &Mail) -> Result<(), Error> {
+        let to = mail.to.parse::<Mailbox>()
+            .map_err(|err| Error::new(ErrorKind::InvalidInput, format!("Invalid address: {}, Error: {}", mail.to, err)))?;
+        let message = Message::builder()
+            .from(self.from.clone())
+            .to(to)
+            .subject(&mail.subject)
+            .header(ContentType::TEXT_PLAIN)
+            .body(mail.body.clone())
+            .map_err(|err| Error::new(ErrorKind::InvalidInput, err))?;
+
+        self.transport.send(message).await.map_err(Error::other)?;
+        log::info!("Sent mail to: {}", mail.to);
+        Ok(())
+    }
+}
@@ -5,7 +5,19 @@ mod shared;
 mod swagger;
 pub mod files;
 pub mod folders;
-mod file_store;
+pub mod groups;
+pub mod jobs;
+pub mod previews;
+pub mod search;
+pub mod sessions;
+pub mod shares;
+pub mod sharing;
+pub mod trash;
+pub mod uploads;
+mod storage;
+mod mail;
+#[cfg(test)]
+mod tests;
 
 pub use auth::service::get_user;
 
@@ -25,11 +37,13 @@ use diesel::{
 use utoipa::OpenApi;
 use utoipa_swagger_ui::SwaggerUi;
 
-use shared::common::{AppState, Config};
+use std::sync::Arc;
+
+use shared::common::{AppState, Config, ConnectionOptions};
+use storage::blob_store::BlobStore;
 
 use diesel_migrations::{embed_migrations, EmbeddedMigrations, MigrationHarness};
 
-use crate::file_store::FileStore;
 pub const MIGRATIONS: EmbeddedMigrations = embed_migrations!();
 
 #[get("/healthcheck")]
@@ -37,6 +51,26 @@ async fn health_check(_name: web::Path<String>) -> impl Responder {
     format!("WebServer Status: {}\nDatabase Status {}\n", "Ok", "Ok")
 }
 
+///
+/// Registers the routes of every module below `/api`
+///
+fn api_config(conf: &mut web::ServiceConfig) {
+    conf.configure(auth::config)
+        .configure(files::config)
+        .configure(folders::config)
+        .configure(uploads::config)
+        .configure(trash::config)
+        .configure(shares::config)
+        .configure(sharing::config)
+        .configure(sessions::config)
+        .configure(groups::config)
+        .configure(search::config)
+        .configure(jobs::config);
+        // .configure(users::config)
+        // .configure(blocks::config)
+        // .configure(pages::config)
+}
+
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
     dotenv::dotenv().ok();
@@ -49,6 +83,7 @@ async fn main() -> std::io::Result<()> {
     // set up database connection pool
     let manager = ConnectionManager::<diesel::SqliteConnection>::new(&config.database_url);
     let pool = r2d2::Pool::builder()
+        .connection_customizer(Box::new(ConnectionOptions))
         .build(manager)
         .expect("Failed to create pool.");
 
@@ -56,9 +91,23 @@ async fn main() -> std::io::Result<()> {
 
     connection.run_pending_migrations(MIGRATIONS).expect("Failed to run migrations");
 
+    // Uploads held by PATCH requests that were cut off when the service stopped
+    uploads::service::release_uploads(&mut connection).expect("Failed to release interrupted uploads");
+
     
-    //TODO: Support multiple Storage services
-    let storage = FileStore::new(std::env::var("FILE_STORE_BASE_PATH").expect("FILE_STORE_BASE_PATH must be set"));
+    let storage = storage::create_storage_service(&config).expect("Failed to create storage service");
+
+    let blobs = Arc::new(BlobStore::new(storage.clone()));
+
+    let prod_mode = std::env::var("PROD_MODE").unwrap_or("false".to_string()).parse::<bool>().unwrap_or(false);
+
+    let mailer = mail::create_mail_service(&config, prod_mode).expect("Failed to create mail service");
+
+    actix_web::rt::spawn(trash::purge::run_purge(pool.clone(), storage.clone(), blobs.clone(), config.trash_retention_days));
+    actix_web::rt::spawn(sessions::cleanup::run_cleanup(pool.clone()));
+
+    let jobs_state = Arc::new(AppState::new(pool.clone(), config.clone(), storage.clone(), blobs.clone(), mailer.clone(), prod_mode));
+    jobs::worker::start_workers(jobs_state, config.job_workers);
     
     // let result: CountResult = sql_query("SELECT COUNT(*) AS count FROM information_schema.tables WHERE table_name = 'users'")
     //     .get_result::<CountResult>(&mut conn)
@@ -76,31 +125,38 @@ async fn main() -> std::io::Result<()> {
         let cors = Cors::default()
             .allowed_origin("http://localhost:8090")
             .allowed_origin("ws://localhost:8090")
-            .allowed_methods(vec!["GET", "PUT", "POST"])
+            .allowed_methods(vec!["GET", "PUT", "POST", "PATCH", "HEAD", "DELETE", "OPTIONS"])
             .allowed_headers(vec![
                 header::CONTENT_TYPE,
                 header::AUTHORIZATION,
                 header::ACCEPT,
+                header::HeaderName::from_static("tus-resumable"),
+                header::HeaderName::from_static("upload-length"),
+                header::HeaderName::from_static("upload-offset"),
+                header::HeaderName::from_static("upload-metadata"),
+                header::HeaderName::from_static("x-share-password"),
+            ])
+            .expose_headers(vec![
+                header::LOCATION,
+                header::HeaderName::from_static("tus-resumable"),
+                header::HeaderName::from_static("upload-length"),
+                header::HeaderName::from_static("upload-offset"),
+                header::HeaderName::from_static("upload-expires"),
             ])
             .supports_credentials();
         App::new()
             .app_data(web::Data::new(AppState::new(
                 pool.clone(),
                 config.clone(),
-                // Box::new(storage.clone()),
                 storage.clone(),
-                std::env::var("PROD_MODE").unwrap_or("false".to_string()).parse::<bool>().unwrap_or(false)
+                blobs.clone(),
+                mailer.clone(),
+                prod_mode
             )))
             .wrap(cors)
             .wrap(Logger::default())
             .service(
-                web::scope("/api")
-                    .configure(auth::config)
-                    .configure(files::config)
-                    .configure(folders::config)
-                    // .configure(users::config)
-                    // .configure(blocks::config)
-                    // .configure(pages::config),
+                web::scope("/api").configure(api_config)
             )
             .service(health_check)
             // .service(web::resource("/ws").to(web_socket))
@@ -0,0 +1,42 @@
+use actix_web::web::Bytes;
+use serde::Deserialize;
+use utoipa::ToSchema;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ThumbnailFormat {
+    #[default]
+    Jpeg,
+    Webp,
+}
+
+impl ThumbnailFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ThumbnailFormat::Jpeg => "jpg",
+            ThumbnailFormat::Webp => "webp",
+        }
+    }
+
+    pub fn media_type(&self) -> &'static str {
+        match self {
+            ThumbnailFormat::Jpeg => "image/jpeg",
+            ThumbnailFormat::Webp => "image/webp",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ThumbnailParams {
+    // Longest side in pixels, one of 128, 256 or 512
+    pub size: Option<u32>,
+    pub format: Option<ThumbnailFormat>,
+}
+
+// A rendered thumbnail of an image
+pub struct Thumbnail {
+    pub size: u32,
+    pub format: ThumbnailFormat,
+    pub data: Bytes,
+}
@@ -0,0 +1,179 @@
+pub mod dto;
+
+use std::io::{Cursor, Error, ErrorKind};
+
+use actix_web::web::{self, Bytes};
+use futures_util::{stream, TryStreamExt};
+use image::codecs::jpeg::JpegEncoder;
+use image::codecs::webp::WebPEncoder;
+use image::DynamicImage;
+
+use crate::shared::common::{ByteStream, StorageService};
+
+use dto::{Thumbnail, ThumbnailFormat};
+
+// Longest side of the thumbnails in pixels, every image gets one of each size and format
+pub const THUMBNAIL_SIZES: [u32; 3] = [128, 256, 512];
+pub const DEFAULT_THUMBNAIL_SIZE: u32 = 256;
+const THUMBNAIL_FORMATS: [ThumbnailFormat; 2] = [ThumbnailFormat::Jpeg, ThumbnailFormat::Webp];
+const JPEG_QUALITY: u8 = 80;
+// Larger images are not decoded, they get no thumbnails
+const MAX_SOURCE_BYTES: usize = 50 * 1024 * 1024;
+// Thumbnails live in this folder next to the contents they are made from
+const THUMBNAIL_PATH: &str = ".thumbnails";
+
+const IMAGE_MEDIA_TYPES: [&str; 4] = ["image/jpeg", "image/png", "image/gif", "image/webp"];
+
+///
+/// Checks whether thumbnails can be made of contents of this media type
+///
+pub fn has_thumbnails(media_type: &str) -> bool {
+    IMAGE_MEDIA_TYPES.contains(&media_type)
+}
+
+///
+/// Path and name of a thumbnail of the contents stored at `path` and `name`
+///
+pub fn thumbnail_location(path: &str, name: &str, size: u32, format: ThumbnailFormat) -> (String, String) {
+    (format!("{}/{}", path, THUMBNAIL_PATH), format!("{}-{}.{}", name, size, format.extension()))
+}
+
+///
+/// Makes every thumbnail of the image stored at `path` and `name` and stores them next to it
+///
+/// The thumbnails are returned as well, so a missing one can be served right away
+///
+pub async fn generate_thumbnails(storage: &dyn StorageService, path: &str, name: &str) -> Result<Vec<Thumbnail>, Error> {
+    let mut input = storage.retrieve_file(path, name).await?;
+    let mut source = Vec::new();
+    while let Some(chunk) = input.try_next().await? {
+        source.extend_from_slice(&chunk);
+        if source.len() > MAX_SOURCE_BYTES {
+            return Err(Error::new(ErrorKind::FileTooLarge, format!("Image {} is too large for thumbnails", name)));
+        }
+    }
+
+    // Decoding and resizing would hold up the other requests on this worker
+    let thumbnails = web::block(move || render_thumbnails(&source))
+        .await
+        .map_err(Error::other)??;
+
+    storage.create_folder(&format!("{}/{}", path, THUMBNAIL_PATH))?;
+    for thumbnail in &thumbnails {
+        let (thumbnail_path, thumbnail_name) = thumbnail_location(path, name, thumbnail.size, thumbnail.format);
+        storage.save_file(&thumbnail_path, &thumbnail_name, thumbnail_stream(thumbnail)).await?;
+    }
+
+    Ok(thumbnails)
+}
+
+///
+/// Deletes the thumbnails of the contents stored at `path` and `name`, missing ones are ignored
+///
+pub async fn delete_thumbnails(storage: &dyn StorageService, path: &str, name: &str) -> Result<(), Error> {
+    for size in THUMBNAIL_SIZES {
+        for format in THUMBNAIL_FORMATS {
+            let (thumbnail_path, thumbnail_name) = thumbnail_location(path, name, size, format);
+            match storage.delete_file(&thumbnail_path, &thumbnail_name).await {
+                Err(err) if err.kind() != ErrorKind::NotFound => return Err(err),
+                _ => {}
+            }
+        }
+    }
+    Ok(())
+}
+
+///
+/// The contents of a thumbnail as a stream
+///
+pub fn thumbnail_stream(thumbnail: &Thumbnail) -> ByteStream {
+    let data = thumbnail.data.clone();
+    Box::pin(stream::once(async move { Ok(data) }))
+}
+
+fn render_thumbnails(source: &[u8]) -> Result<Vec<Thumbnail>, Error> {
+    let image = image::load_from_memory(source).map_err(|err| Error::new(ErrorKind::InvalidData, err))?;
+
+    let mut thumbnails = Vec::new();
+    for size in THUMBNAIL_SIZES {
+        // Small images are not scaled up
+        let resized = match image.width() > size || image.height() > size {
+            true => image.thumbnail(size, size),
+            false => image.clone(),
+        };
+        for format in THUMBNAIL_FORMATS {
+            thumbnails.push(Thumbnail { size, format, data: encode(&resized, format)? });
+        }
+    }
+    Ok(thumbnails)
+}
+
+fn encode(image: &DynamicImage, format: ThumbnailFormat) -> Result<Bytes, Error> {
+    let mut data = Cursor::new(Vec::new());
+    let result = match format {
+        // JPEG has no transparency
+        ThumbnailFormat::Jpeg => JpegEncoder::new_with_quality(&mut data, JPEG_QUALITY).encode_image(&DynamicImage::ImageRgb8(image.to_rgb8())),
+        ThumbnailFormat::Webp => WebPEncoder::new_lossless(&mut data).encode(
+            image.to_rgba8().as_raw(),
+            image.width(),
+            image.height(),
+            image::ExtendedColorType::Rgba8,
+        ),
+    };
+    result.map_err(Error::other)?;
+    Ok(Bytes::from(data.into_inner()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::memory_store::MemoryStore;
+
+    fn png(width: u32, height: u32) -> Bytes {
+        let mut data = Cursor::new(Vec::new());
+        DynamicImage::new_rgb8(width, height).write_to(&mut data, image::ImageFormat::Png).unwrap();
+        Bytes::from(data.into_inner())
+    }
+
+    fn dimensions(thumbnail: &Thumbnail) -> (u32, u32) {
+        let image = image::load_from_memory(&thumbnail.data).unwrap();
+        (image.width(), image.height())
+    }
+
+    #[test]
+    fn thumbnails_keep_the_aspect_ratio_and_are_not_scaled_up() {
+        let thumbnails = render_thumbnails(&png(1024, 512)).unwrap();
+        assert_eq!(thumbnails.len(), THUMBNAIL_SIZES.len() * THUMBNAIL_FORMATS.len());
+        let sizes: Vec<(u32, u32)> = thumbnails.iter().filter(|thumbnail| thumbnail.format == ThumbnailFormat::Jpeg).map(dimensions).collect();
+        assert_eq!(sizes, [(128, 64), (256, 128), (512, 256)]);
+
+        let thumbnails = render_thumbnails(&png(200, 100)).unwrap();
+        let sizes: Vec<(u32, u32)> = thumbnails.iter().map(dimensions).collect();
+        assert_eq!(sizes, [(128, 64), (128, 64), (200, 100), (200, 100), (200, 100), (200, 100)]);
+        assert_eq!(image::guess_format(&thumbnails[1].data).unwrap(), image::ImageFormat::WebP);
+    }
+
+    #[test]
+    fn broken_images_have_no_thumbnails() {
+        let err = render_thumbnails(b"\x89PNG\r\n\x1a\nbroken").err().unwrap();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+    }
+
+    #[actix_web::test]
+    async fn thumbnails_are_stored_next_to_the_image_and_deleted_with_it() {
+        let store = MemoryStore::new();
+        store.create_folder("root").unwrap();
+        let image = png(300, 300);
+        store.save_file("root", "image", Box::pin(stream::once(async move { Ok(image) }))).await.unwrap();
+
+        generate_thumbnails(&store, "root", "image").await.unwrap();
+        let (thumbnail_path, thumbnail_name) = thumbnail_location("root", "image", 128, ThumbnailFormat::Webp);
+        assert_eq!((thumbnail_path.as_str(), thumbnail_name.as_str()), ("root/.thumbnails", "image-128.webp"));
+        assert_eq!(store.list_file_names(&thumbnail_path).await.unwrap().len(), 6);
+
+        delete_thumbnails(&store, "root", "image").await.unwrap();
+        assert!(store.list_file_names(&thumbnail_path).await.unwrap().is_empty());
+        // Deleting again finds nothing to delete, which is fine
+        delete_thumbnails(&store, "root", "image").await.unwrap();
+    }
+}
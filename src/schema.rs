@@ -1,5 +1,14 @@
 // @generated automatically by Diesel CLI.
 
+diesel::table! {
+    blobs (hash) {
+        hash -> Text,
+        size -> BigInt,
+        ref_count -> Integer,
+        created_at -> Nullable<Timestamp>,
+    }
+}
+
 diesel::table! {
     file_folders (id) {
         id -> Text,
@@ -12,6 +21,23 @@ diesel::table! {
         created_by -> Integer,
         updated_by -> Integer,
         active -> Bool,
+        deleted_at -> Nullable<Timestamp>,
+        deleted_by -> Nullable<Integer>,
+        group_id -> Nullable<Text>,
+    }
+}
+
+diesel::table! {
+    file_versions (id) {
+        id -> Text,
+        file_id -> Text,
+        version -> Integer,
+        size -> BigInt,
+        checksum -> Text,
+        media_type -> Nullable<Text>,
+        orginal_filename -> Nullable<Text>,
+        created_at -> Nullable<Timestamp>,
+        created_by -> Integer,
     }
 }
 
@@ -30,6 +56,162 @@ diesel::table! {
         created_by -> Integer,
         updated_by -> Integer,
         active -> Bool,
+        deleted_at -> Nullable<Timestamp>,
+        deleted_by -> Nullable<Integer>,
+        blob_hash -> Nullable<Text>,
+        size -> BigInt,
+        group_id -> Nullable<Text>,
+    }
+}
+
+diesel::table! {
+    files_search_rowids (file_id) {
+        file_id -> Text,
+        search_rowid -> Integer,
+    }
+}
+
+diesel::table! {
+    group_members (id) {
+        id -> Text,
+        group_id -> Text,
+        user_id -> Integer,
+        role -> Integer,
+        created_at -> Nullable<Timestamp>,
+        updated_at -> Nullable<Timestamp>,
+        created_by -> Integer,
+        updated_by -> Integer,
+        active -> Bool,
+    }
+}
+
+diesel::table! {
+    groups (id) {
+        id -> Text,
+        name -> Text,
+        description -> Nullable<Text>,
+        folder_id -> Text,
+        created_at -> Nullable<Timestamp>,
+        updated_at -> Nullable<Timestamp>,
+        created_by -> Integer,
+        updated_by -> Integer,
+        active -> Bool,
+    }
+}
+
+diesel::table! {
+    jobs (id) {
+        id -> Text,
+        kind -> Text,
+        payload -> Text,
+        status -> Integer,
+        attempts -> Integer,
+        max_attempts -> Integer,
+        run_at -> Timestamp,
+        last_error -> Nullable<Text>,
+        created_at -> Nullable<Timestamp>,
+        updated_at -> Nullable<Timestamp>,
+        created_by -> Integer,
+        updated_by -> Integer,
+        active -> Bool,
+    }
+}
+
+diesel::table! {
+    password_resets (id) {
+        id -> Text,
+        user_id -> Integer,
+        token_hash -> Text,
+        expires_at -> Timestamp,
+        used_at -> Nullable<Timestamp>,
+        created_at -> Nullable<Timestamp>,
+        updated_at -> Nullable<Timestamp>,
+        created_by -> Integer,
+        updated_by -> Integer,
+        active -> Bool,
+    }
+}
+
+diesel::table! {
+    session_tokens (token_hash) {
+        token_hash -> Text,
+        session_id -> Text,
+        created_at -> Nullable<Timestamp>,
+    }
+}
+
+diesel::table! {
+    sessions (id) {
+        id -> Text,
+        user_id -> Integer,
+        refresh_token_hash -> Text,
+        user_agent -> Nullable<Text>,
+        ip_address -> Nullable<Text>,
+        last_used_at -> Nullable<Timestamp>,
+        expires_at -> Timestamp,
+        revoked_at -> Nullable<Timestamp>,
+        created_at -> Nullable<Timestamp>,
+        updated_at -> Nullable<Timestamp>,
+        created_by -> Integer,
+        updated_by -> Integer,
+        active -> Bool,
+    }
+}
+
+diesel::table! {
+    share_links (id) {
+        id -> Text,
+        token -> Text,
+        owner_id -> Integer,
+        file_id -> Nullable<Text>,
+        folder_id -> Nullable<Text>,
+        password -> Nullable<Text>,
+        expires_at -> Nullable<Timestamp>,
+        max_downloads -> Nullable<Integer>,
+        download_count -> Integer,
+        failed_attempts -> Integer,
+        locked_until -> Nullable<Timestamp>,
+        created_at -> Nullable<Timestamp>,
+        updated_at -> Nullable<Timestamp>,
+        created_by -> Integer,
+        updated_by -> Integer,
+        active -> Bool,
+    }
+}
+
+diesel::table! {
+    uploads (id) {
+        id -> Text,
+        owner_id -> Integer,
+        file_id -> Text,
+        upload_length -> BigInt,
+        upload_offset -> BigInt,
+        filename -> Nullable<Text>,
+        media_type -> Nullable<Text>,
+        patching -> Bool,
+        quota_user_id -> Integer,
+        expires_at -> Timestamp,
+        created_at -> Nullable<Timestamp>,
+        updated_at -> Nullable<Timestamp>,
+        created_by -> Integer,
+        updated_by -> Integer,
+        active -> Bool,
+    }
+}
+
+diesel::table! {
+    user_shares (id) {
+        id -> Text,
+        owner_id -> Integer,
+        user_id -> Integer,
+        file_id -> Nullable<Text>,
+        folder_id -> Nullable<Text>,
+        permission -> Integer,
+        created_at -> Nullable<Timestamp>,
+        updated_at -> Nullable<Timestamp>,
+        created_by -> Integer,
+        updated_by -> Integer,
+        active -> Bool,
     }
 }
 
@@ -45,11 +227,25 @@ diesel::table! {
         created_by -> Integer,
         updated_by -> Integer,
         active -> Bool,
+        storage_used -> BigInt,
+        storage_quota -> Nullable<BigInt>,
     }
 }
 
 diesel::allow_tables_to_appear_in_same_query!(
+    blobs,
     file_folders,
+    file_versions,
     files,
+    files_search_rowids,
+    group_members,
+    groups,
+    jobs,
+    password_resets,
+    session_tokens,
+    sessions,
+    share_links,
+    uploads,
+    user_shares,
     users,
 );
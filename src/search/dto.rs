@@ -0,0 +1,40 @@
+use diesel::sql_types::{Nullable, Text};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use crate::files::dto::FileDto;
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SearchParams {
+    // Words match as prefixes when they end in `*`, text in double quotes as a phrase
+    pub q: String,
+    // Either a full media type or a type like `image/*`
+    pub media_type: Option<String>,
+    // Limits the results to the folder and everything below it
+    pub folder_id: Option<String>,
+    pub modified_after: Option<chrono::NaiveDateTime>,
+    pub modified_before: Option<chrono::NaiveDateTime>,
+    pub limit: Option<i64>,
+    // Skips this many of the best matches, for the following pages
+    pub offset: Option<i64>,
+}
+
+///
+/// A file matching a search, best matches come first
+///
+#[derive(Debug, Clone, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct SearchResultDto {
+    pub file: FileDto,
+    // The matching part of the file's metadata or contents, with the matches in `<b>` tags
+    pub snippet: Option<String>,
+}
+
+// A row of the full-text index matching a search
+#[derive(Debug, Clone, QueryableByName)]
+pub struct SearchHit {
+    #[diesel(sql_type = Text)]
+    pub file_id: String,
+    #[diesel(sql_type = Nullable<Text>)]
+    pub snippet: Option<String>,
+}
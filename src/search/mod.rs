@@ -0,0 +1,101 @@
+pub mod dto;
+pub mod service;
+
+use actix_web::{
+    get, web, Error, HttpResponse,
+};
+
+use diesel::SqliteConnection;
+use futures_util::TryStreamExt;
+
+use crate::auth::jwt_auth;
+use crate::shared::common::{DbError, ServiceError};
+use crate::shared::common::AppState;
+use crate::shared::media_type::is_text_media_type;
+use crate::storage::blob_store::BlobStore;
+use service::{build_match_query, search_files, set_indexed_content, is_current_blob};
+
+use dto::{SearchParams, SearchResultDto};
+
+// Only the start of large text files is indexed
+const MAX_INDEXED_BYTES: usize = 1024 * 1024;
+const DEFAULT_LIMIT: i64 = 50;
+const MAX_LIMIT: i64 = 200;
+
+///
+/// Searches the titles, descriptions, filenames and text contents of the files the user may see
+///
+/// Words in `q` ending in `*` match as prefixes and text in double quotes as a phrase. The results
+/// can be narrowed down by `mediaType` (`image/*` for all images), `modifiedAfter`/`modifiedBefore`
+/// and `folderId`, which includes its sub folders. At most `limit` results are returned, 50 by default,
+/// `offset` skips the first results to page through the rest
+///
+#[utoipa::path(
+    get,
+    tag = "Search",
+    path = "/api/search",
+    responses(
+        (status = 200, description = "Successfully searched the files", body = [Vec<SearchResultDto>])
+    )
+)]
+#[get("")]
+pub async fn search_handler(
+    app: web::Data<AppState>,
+    jwt: jwt_auth::JwtMiddleware,
+    query: web::Query<SearchParams>,
+) -> Result<HttpResponse, Error> {
+    let params = query.into_inner();
+    let match_query = build_match_query(&params.q).ok_or_else(|| ServiceError::BadRequest("The search query is empty".to_string()))?;
+    let limit = params.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+    let offset = params.offset.unwrap_or(0).max(0);
+
+    let mut conn = app
+        .get_connection()
+        .map_err(|err| ServiceError::NotFound(err.to_string()))?;
+
+    match search_files(&mut conn, jwt.user_id, &match_query, &params, limit, offset) {
+        Ok(results) => Ok(HttpResponse::Ok().json(results)),
+        Err(err) => Err(ServiceError::InternalServerError(err.to_string()).into()),
+    }
+}
+
+///
+/// Indexes the text of a file's contents, contents that are not text are only found by the
+/// file's metadata
+///
+/// Contents that have been replaced since are skipped, the new contents are indexed on their own
+///
+pub async fn index_file_contents(app: &AppState, conn: &mut SqliteConnection, file_id: &str, blob_hash: &str, media_type: &str) -> Result<(), DbError> {
+    if !is_current_blob(conn, file_id, blob_hash)? {
+        return Ok(());
+    }
+    let content = match is_text_media_type(media_type) {
+        true => Some(read_text(app, blob_hash).await?),
+        false => None,
+    };
+    set_indexed_content(conn, file_id, content)?;
+    Ok(())
+}
+
+// The start of a blob as text, invalid UTF-8 is replaced
+async fn read_text(app: &AppState, blob_hash: &str) -> Result<String, std::io::Error> {
+    let (path, name) = BlobStore::location(blob_hash);
+    let mut input = app.get_storage_service().retrieve_file(&path, &name).await?;
+    let mut content = Vec::new();
+    while let Some(chunk) = input.try_next().await? {
+        content.extend_from_slice(&chunk);
+        if content.len() >= MAX_INDEXED_BYTES {
+            content.truncate(MAX_INDEXED_BYTES);
+            break;
+        }
+    }
+    Ok(String::from_utf8_lossy(&content).into_owned())
+}
+
+pub fn config(conf: &mut web::ServiceConfig) {
+    let scope = web::scope("/search")
+            .service(search_handler)
+            ;
+
+    conf.service(scope);
+}
@@ -0,0 +1,164 @@
+use std::collections::HashMap;
+
+use crate::shared::common::DbError;
+use super::dto::{SearchParams, SearchHit, SearchResultDto};
+use crate::files::dto::FileDto;
+use diesel::prelude::*;
+use diesel::sql_types::{BigInt, Integer, Nullable, Text, Timestamp};
+use diesel::sqlite::Sqlite;
+
+use crate::schema::files;
+
+///
+/// Turns the user's query into an FTS5 query
+///
+/// Words ending in `*` match as prefixes and text in double quotes as a phrase, all of them
+/// have to match. Everything is quoted so the query can't be read as FTS5 syntax
+///
+pub fn build_match_query(query: &str) -> Option<String> {
+    let mut terms = Vec::new();
+    // Every other part is inside double quotes, an unbalanced quote runs to the end
+    for (index, part) in query.split('"').enumerate() {
+        if index % 2 == 1 {
+            let phrase = part.split_whitespace().collect::<Vec<_>>().join(" ");
+            if !phrase.is_empty() {
+                terms.push(format!("\"{}\"", phrase));
+            }
+            continue;
+        }
+        for word in part.split_whitespace() {
+            let prefix = word.ends_with('*');
+            let word = word.trim_matches('*');
+            if !word.is_empty() {
+                terms.push(format!("\"{}\"{}", word, if prefix { "*" } else { "" }));
+            }
+        }
+    }
+    (!terms.is_empty()).then(|| terms.join(" "))
+}
+
+///
+/// Finds the files matching `match_query` that the user may see, best matches first
+///
+/// These are the user's own files, the files of their groups and files shared with them, either
+/// on their own or through a shared folder above them. Folders in the trash share nothing
+///
+pub fn search_files(conn: &mut SqliteConnection, user_id: i32, match_query: &str, params: &SearchParams, limit: i64, offset: i64) -> Result<Vec<SearchResultDto>, DbError> {
+    let mut query = diesel::sql_query(
+        "WITH RECURSIVE shared_folders(id, owner_id) AS (\
+         SELECT user_shares.folder_id, user_shares.owner_id FROM user_shares JOIN file_folders ON file_folders.id = user_shares.folder_id \
+         WHERE user_shares.user_id = ? AND user_shares.active = 1 AND user_shares.permission IN (1, 2) AND file_folders.active = 1 \
+         UNION SELECT file_folders.id, shared_folders.owner_id FROM file_folders JOIN shared_folders ON file_folders.parent_folder_id = shared_folders.id \
+         WHERE file_folders.owner_id = shared_folders.owner_id AND file_folders.group_id IS NULL AND file_folders.active = 1) \
+         SELECT files_search.file_id, snippet(files_search, -1, '<b>', '</b>', '…', 12) AS snippet \
+         FROM files_search JOIN files ON files.id = files_search.file_id \
+         WHERE files_search MATCH ? AND files.active = 1 \
+         AND ((files.owner_id = ? AND files.group_id IS NULL) \
+           OR files.group_id IN (SELECT group_id FROM group_members WHERE user_id = ? AND active = 1) \
+           OR (files.group_id IS NULL AND (\
+             EXISTS (SELECT 1 FROM shared_folders WHERE shared_folders.id = files.folder_id AND shared_folders.owner_id = files.owner_id) \
+             OR files.id IN (SELECT file_id FROM user_shares \
+               WHERE user_id = ? AND owner_id = files.owner_id AND active = 1 AND permission IN (1, 2)))))")
+        .into_boxed::<Sqlite>()
+        .bind::<Integer, _>(user_id)
+        .bind::<Text, _>(match_query.to_string())
+        .bind::<Integer, _>(user_id)
+        .bind::<Integer, _>(user_id)
+        .bind::<Integer, _>(user_id);
+
+    if let Some(media_type) = &params.media_type {
+        query = match media_type.strip_suffix("/*") {
+            Some(kind) => query.sql(" AND files.media_type LIKE ?").bind::<Text, _>(format!("{}/%", kind)),
+            None => query.sql(" AND files.media_type = ?").bind::<Text, _>(media_type.clone()),
+        };
+    }
+    if let Some(modified_after) = params.modified_after {
+        query = query.sql(" AND files.updated_at >= ?").bind::<Timestamp, _>(modified_after);
+    }
+    if let Some(modified_before) = params.modified_before {
+        query = query.sql(" AND files.updated_at < ?").bind::<Timestamp, _>(modified_before);
+    }
+    if let Some(folder_id) = &params.folder_id {
+        query = query
+            .sql(" AND files.folder_id IN (WITH RECURSIVE subtree(id) AS (SELECT ? \
+                  UNION SELECT file_folders.id FROM file_folders JOIN subtree ON file_folders.parent_folder_id = subtree.id \
+                  WHERE file_folders.active = 1) SELECT id FROM subtree)")
+            .bind::<Text, _>(folder_id.clone());
+    }
+    let hits = query
+        .sql(" ORDER BY bm25(files_search) LIMIT ? OFFSET ?")
+        .bind::<BigInt, _>(limit)
+        .bind::<BigInt, _>(offset)
+        .load::<SearchHit>(conn)?;
+
+    let ids: Vec<&String> = hits.iter().map(|hit| &hit.file_id).collect();
+    let mut found: HashMap<String, FileDto> = files::table
+        .filter(files::id.eq_any(ids))
+        .load::<FileDto>(conn)?
+        .into_iter()
+        .map(|file| (file.id.clone(), file))
+        .collect();
+
+    Ok(hits
+        .into_iter()
+        .filter_map(|hit| found.remove(&hit.file_id).map(|file| SearchResultDto { file, snippet: hit.snippet }))
+        .collect())
+}
+
+///
+/// Checks whether a file's current contents are the blob `blob_hash`
+///
+pub fn is_current_blob(conn: &mut SqliteConnection, file_id: &str, blob_hash: &str) -> Result<bool, DbError> {
+    let current = files::table
+        .filter(files::id.eq(file_id))
+        .select(files::blob_hash)
+        .first::<Option<String>>(conn)
+        .optional()?;
+    Ok(matches!(current, Some(Some(hash)) if hash == blob_hash))
+}
+
+///
+/// Replaces the indexed text of a file's contents, None removes it
+///
+pub fn set_indexed_content(conn: &mut SqliteConnection, file_id: &str, content: Option<String>) -> Result<usize, DbError> {
+    Ok(diesel::sql_query(
+        "UPDATE files_search SET content = ? \
+         WHERE rowid = (SELECT search_rowid FROM files_search_rowids WHERE file_id = ?)")
+        .bind::<Nullable<Text>, _>(content)
+        .bind::<Text, _>(file_id)
+        .execute(conn)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn words_are_quoted_and_all_have_to_match() {
+        assert_eq!(build_match_query("annual  report").as_deref(), Some("\"annual\" \"report\""));
+        assert_eq!(build_match_query(" \t ").as_deref(), None);
+    }
+
+    #[test]
+    fn quoted_text_is_a_phrase() {
+        assert_eq!(build_match_query("\"annual   report\" 2024").as_deref(), Some("\"annual report\" \"2024\""));
+        assert_eq!(build_match_query("\"\" \" \"").as_deref(), None);
+        // An unbalanced quote runs to the end
+        assert_eq!(build_match_query("draft \"annual report").as_deref(), Some("\"draft\" \"annual report\""));
+    }
+
+    #[test]
+    fn trailing_stars_match_prefixes() {
+        assert_eq!(build_match_query("rep* *port").as_deref(), Some("\"rep\"* \"port\""));
+        assert_eq!(build_match_query("re*port").as_deref(), Some("\"re*port\""));
+        assert_eq!(build_match_query("* **").as_deref(), None);
+    }
+
+    #[test]
+    fn fts5_operators_are_searched_as_words() {
+        assert_eq!(
+            build_match_query("a OR b NOT c NEAR(d e) -f ^g col:h").as_deref(),
+            Some("\"a\" \"OR\" \"b\" \"NOT\" \"c\" \"NEAR(d\" \"e)\" \"-f\" \"^g\" \"col:h\"")
+        );
+    }
+}
@@ -0,0 +1,24 @@
+use std::time::Duration;
+
+use crate::shared::common::DbPool;
+use super::service::delete_stale_sessions;
+
+// How often ended sessions are deleted, they are kept for a week so the revocations can be looked into
+const CLEANUP_INTERVAL: Duration = Duration::from_secs(60 * 60);
+const ENDED_RETENTION_DAYS: i64 = 7;
+
+///
+/// Periodically deletes the sessions that have expired or were revoked
+///
+pub async fn run_cleanup(pool: DbPool) {
+    let mut interval = actix_web::rt::time::interval(CLEANUP_INTERVAL);
+    loop {
+        interval.tick().await;
+        let cutoff = chrono::Utc::now().naive_utc() - chrono::Duration::days(ENDED_RETENTION_DAYS);
+        match pool.get().map_err(|err| err.into()).and_then(|mut conn| delete_stale_sessions(&mut conn, cutoff)) {
+            Ok(0) => {}
+            Ok(deleted) => log::info!("Deleted {} ended sessions", deleted),
+            Err(err) => log::error!("Failed to delete ended sessions: {}", err),
+        }
+    }
+}
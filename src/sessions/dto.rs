@@ -0,0 +1,68 @@
+use diesel::prelude::*;
+use serde::Serialize;
+use utoipa::ToSchema;
+use crate::schema::{session_tokens, sessions};
+
+#[derive(Debug, Clone)]
+#[derive(Queryable, Selectable, Insertable)]
+#[diesel(table_name = sessions)]
+pub struct SessionDto {
+    pub id: String,
+    pub user_id: i32,
+    // Hex SHA-256 of the current refresh token
+    pub refresh_token_hash: String,
+    pub user_agent: Option<String>,
+    pub ip_address: Option<String>,
+    pub last_used_at: Option<chrono::NaiveDateTime>,
+    pub expires_at: chrono::NaiveDateTime,
+    pub revoked_at: Option<chrono::NaiveDateTime>,
+    pub created_at: Option<chrono::NaiveDateTime>,
+    pub updated_at: Option<chrono::NaiveDateTime>,
+    pub created_by: i32,
+    pub updated_by: i32,
+    pub active: bool,
+}
+
+///
+/// A refresh token handed out for a session, seeing a replaced one again means a stolen token
+///
+#[derive(Debug, Clone)]
+#[derive(Queryable, Selectable, Insertable)]
+#[diesel(table_name = session_tokens)]
+pub struct SessionTokenDto {
+    // Hex SHA-256 of the refresh token
+    pub token_hash: String,
+    pub session_id: String,
+    pub created_at: Option<chrono::NaiveDateTime>,
+}
+
+///
+/// A device the user is logged in on
+///
+#[derive(Debug, Clone, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct UserSessionDto {
+    pub id: String,
+    pub user_agent: Option<String>,
+    pub ip_address: Option<String>,
+    pub created_at: Option<chrono::NaiveDateTime>,
+    // Last login or refresh
+    pub last_used_at: Option<chrono::NaiveDateTime>,
+    pub expires_at: chrono::NaiveDateTime,
+    // Is this the session making the request?
+    pub current: bool,
+}
+
+impl UserSessionDto {
+    pub fn from(session: SessionDto, current_id: &str) -> UserSessionDto {
+        UserSessionDto {
+            current: session.id == current_id,
+            id: session.id,
+            user_agent: session.user_agent,
+            ip_address: session.ip_address,
+            created_at: session.created_at,
+            last_used_at: session.last_used_at,
+            expires_at: session.expires_at,
+        }
+    }
+}
@@ -0,0 +1,119 @@
+pub mod cleanup;
+pub mod dto;
+pub mod service;
+
+use actix_web::{
+    delete, get, web, Error, HttpResponse,
+};
+
+use log::info;
+
+use crate::auth::jwt_auth;
+use crate::shared::common::ServiceError;
+use crate::shared::common::AppState;
+use service::{get_sessions, revoke_session, revoke_sessions};
+
+use dto::UserSessionDto;
+
+///
+/// Lists the devices the user is logged in on, most recently used first
+///
+#[utoipa::path(
+    get,
+    tag = "Sessions",
+    path = "/api/sessions",
+    responses(
+        (status = 200, description = "Successfully retrieved the sessions", body = [Vec<UserSessionDto>])
+    )
+)]
+#[get("")]
+pub async fn get_sessions_handler(
+    app: web::Data<AppState>,
+    jwt: jwt_auth::JwtMiddleware,
+) -> Result<HttpResponse, Error> {
+    let mut conn = app
+        .get_connection()
+        .map_err(|err| ServiceError::NotFound(err.to_string()))?;
+
+    match get_sessions(&mut conn, jwt.user_id) {
+        Ok(sessions) => Ok(HttpResponse::Ok().json(sessions
+            .into_iter()
+            .map(|session| UserSessionDto::from(session, &jwt.session_id))
+            .collect::<Vec<UserSessionDto>>())),
+        Err(err) => Err(ServiceError::InternalServerError(err.to_string()).into()),
+    }
+}
+
+///
+/// Revokes a session, logging the user out on that device
+///
+/// Its access token stops working right away and its refresh token can no longer be used
+///
+#[utoipa::path(
+    delete,
+    tag = "Sessions",
+    path = "/api/sessions/{session_id}",
+    responses(
+        (status = 204, description = "Successfully revoked a session")
+    )
+)]
+#[delete("/{session_id}")]
+pub async fn revoke_session_handler(
+    app: web::Data<AppState>,
+    jwt: jwt_auth::JwtMiddleware,
+    path: web::Path<String>,
+) -> Result<HttpResponse, Error> {
+    let session_id = path.to_string();
+
+    let mut conn = app
+        .get_connection()
+        .map_err(|err| ServiceError::NotFound(err.to_string()))?;
+
+    match revoke_session(&mut conn, &session_id, jwt.user_id) {
+        Ok(0) => Err(ServiceError::NotFound(session_id).into()),
+        Ok(_) => {
+            info!("Revoked session: {} of user: {}", session_id, jwt.user_id);
+            Ok(HttpResponse::NoContent().finish())
+        }
+        Err(err) => Err(ServiceError::InternalServerError(err.to_string()).into()),
+    }
+}
+
+///
+/// Revokes all sessions of the user except the current one
+///
+#[utoipa::path(
+    delete,
+    tag = "Sessions",
+    path = "/api/sessions",
+    responses(
+        (status = 204, description = "Successfully revoked the other sessions")
+    )
+)]
+#[delete("")]
+pub async fn revoke_other_sessions_handler(
+    app: web::Data<AppState>,
+    jwt: jwt_auth::JwtMiddleware,
+) -> Result<HttpResponse, Error> {
+    let mut conn = app
+        .get_connection()
+        .map_err(|err| ServiceError::NotFound(err.to_string()))?;
+
+    match revoke_sessions(&mut conn, jwt.user_id, Some(&jwt.session_id)) {
+        Ok(revoked) => {
+            info!("Revoked {} other sessions of user: {}", revoked, jwt.user_id);
+            Ok(HttpResponse::NoContent().finish())
+        }
+        Err(err) => Err(ServiceError::InternalServerError(err.to_string()).into()),
+    }
+}
+
+pub fn config(conf: &mut web::ServiceConfig) {
+    let scope = web::scope("/sessions")
+            .service(get_sessions_handler)
+            .service(revoke_other_sessions_handler)
+            .service(revoke_session_handler)
+            ;
+
+    conf.service(scope);
+}
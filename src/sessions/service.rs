@@ -0,0 +1,190 @@
+use crate::auth::tokens::{generate_token, hash_token};
+use crate::shared::common::DbError;
+use super::dto::{SessionDto, SessionTokenDto};
+use diesel::prelude::*;
+use uuid::Uuid;
+
+use crate::schema::{session_tokens, sessions};
+
+///
+/// Starts a session for the user that lasts `days` unless it is refreshed, returns it and its refresh token
+///
+pub fn create_session(
+    conn: &mut SqliteConnection,
+    user_id: i32,
+    user_agent: Option<String>,
+    ip_address: Option<String>,
+    days: i64,
+) -> Result<(SessionDto, String), DbError> {
+    let token = generate_token();
+    let now = chrono::Utc::now().naive_utc();
+    let session = SessionDto {
+        id: Uuid::new_v4().to_string(),
+        user_id,
+        refresh_token_hash: hash_token(&token),
+        user_agent,
+        ip_address,
+        last_used_at: Some(now),
+        expires_at: now + chrono::Duration::days(days),
+        revoked_at: None,
+        created_at: None,
+        updated_at: None,
+        created_by: user_id,
+        updated_by: user_id,
+        active: true,
+    };
+
+    conn.transaction(|conn| {
+        diesel::insert_into(sessions::table)
+            .values(&session)
+            .execute(conn)?;
+        add_session_token(conn, &session.id, &session.refresh_token_hash)
+    })?;
+
+    Ok((session, token))
+}
+
+///
+/// Swaps a refresh token for a new one and extends its session by `days`
+///
+/// None when the token does not belong to a live session. Presenting any token the session
+/// handed out before means it was copied, the session is revoked so no copy works anymore
+///
+pub fn rotate_session(conn: &mut SqliteConnection, token: &str, days: i64) -> Result<Option<(SessionDto, String)>, DbError> {
+    let hash = hash_token(token);
+    let now = chrono::Utc::now().naive_utc();
+    conn.immediate_transaction(|conn| {
+        let session = sessions::table
+            .filter(sessions::refresh_token_hash.eq(&hash))
+            .filter(sessions::revoked_at.is_null())
+            .filter(sessions::expires_at.gt(now))
+            .filter(sessions::active.eq(true))
+            .first::<SessionDto>(conn)
+            .optional()?;
+
+        let Some(session) = session else {
+            let family = session_tokens::table
+                .filter(session_tokens::token_hash.eq(&hash))
+                .select(session_tokens::session_id);
+            let reused = diesel::update(sessions::table
+                    .filter(sessions::id.eq_any(family))
+                    .filter(sessions::revoked_at.is_null()))
+                .set((
+                    sessions::revoked_at.eq(now),
+                    sessions::updated_at.eq(now)))
+                .execute(conn)?;
+            if reused > 0 {
+                log::warn!("Revoked a session after its old refresh token was used again");
+            }
+            return Ok(None);
+        };
+
+        let token = generate_token();
+        let token_hash = hash_token(&token);
+        let expires_at = now + chrono::Duration::days(days);
+        diesel::update(sessions::table.filter(sessions::id.eq(&session.id)))
+            .set((
+                sessions::refresh_token_hash.eq(&token_hash),
+                sessions::last_used_at.eq(now),
+                sessions::expires_at.eq(expires_at),
+                sessions::updated_at.eq(now)))
+            .execute(conn)?;
+        add_session_token(conn, &session.id, &token_hash)?;
+
+        let session = SessionDto {
+            refresh_token_hash: token_hash,
+            last_used_at: Some(now),
+            expires_at,
+            updated_at: Some(now),
+            ..session
+        };
+        Ok(Some((session, token)))
+    })
+}
+
+// Remembers a refresh token handed out for the session, so seeing it again after a rotation is noticed
+fn add_session_token(conn: &mut SqliteConnection, session_id: &str, token_hash: &str) -> QueryResult<usize> {
+    let token = SessionTokenDto {
+        token_hash: token_hash.to_string(),
+        session_id: session_id.to_string(),
+        created_at: None,
+    };
+    diesel::insert_into(session_tokens::table)
+        .values(&token)
+        .execute(conn)
+}
+
+///
+/// Is the session of the user neither revoked nor expired?
+///
+pub fn is_session_active(conn: &mut SqliteConnection, session_id: &str, user_id: i32) -> Result<bool, DbError> {
+    let count: i64 = sessions::table
+        .filter(sessions::id.eq(session_id))
+        .filter(sessions::user_id.eq(user_id))
+        .filter(sessions::revoked_at.is_null())
+        .filter(sessions::expires_at.gt(chrono::Utc::now().naive_utc()))
+        .filter(sessions::active.eq(true))
+        .count()
+        .get_result(conn)?;
+    Ok(count == 1)
+}
+
+///
+/// Live sessions of the user, most recently used first
+///
+pub fn get_sessions(conn: &mut SqliteConnection, user_id: i32) -> Result<Vec<SessionDto>, DbError> {
+    Ok(sessions::table
+        .filter(sessions::user_id.eq(user_id))
+        .filter(sessions::revoked_at.is_null())
+        .filter(sessions::expires_at.gt(chrono::Utc::now().naive_utc()))
+        .filter(sessions::active.eq(true))
+        .order(sessions::last_used_at.desc())
+        .load::<SessionDto>(conn)?)
+}
+
+pub fn revoke_session(conn: &mut SqliteConnection, session_id: &str, user_id: i32) -> Result<usize, DbError> {
+    let now = chrono::Utc::now().naive_utc();
+    Ok(diesel::update(sessions::table
+            .filter(sessions::id.eq(session_id))
+            .filter(sessions::user_id.eq(user_id))
+            .filter(sessions::revoked_at.is_null()))
+        .set((
+            sessions::revoked_at.eq(now),
+            sessions::updated_by.eq(user_id),
+            sessions::updated_at.eq(now)))
+        .execute(conn)?)
+}
+
+///
+/// Revokes all sessions of the user, except `keep` when given
+///
+pub fn revoke_sessions(conn: &mut SqliteConnection, user_id: i32, keep: Option<&str>) -> Result<usize, DbError> {
+    let now = chrono::Utc::now().naive_utc();
+    let mut query = diesel::update(sessions::table)
+        .filter(sessions::user_id.eq(user_id))
+        .filter(sessions::revoked_at.is_null())
+        .into_boxed();
+    if let Some(keep) = keep {
+        query = query.filter(sessions::id.ne(keep));
+    }
+
+    Ok(query
+        .set((
+            sessions::revoked_at.eq(now),
+            sessions::updated_by.eq(user_id),
+            sessions::updated_at.eq(now)))
+        .execute(conn)?)
+}
+
+///
+/// Deletes the sessions that expired or were revoked before `cutoff`, with the tokens they handed out
+///
+pub fn delete_stale_sessions(conn: &mut SqliteConnection, cutoff: chrono::NaiveDateTime) -> Result<usize, DbError> {
+    let stale = sessions::expires_at.lt(cutoff).or(sessions::revoked_at.lt(cutoff));
+    Ok(conn.transaction(|conn| {
+        diesel::delete(session_tokens::table
+                .filter(session_tokens::session_id.eq_any(sessions::table.filter(stale).select(sessions::id))))
+            .execute(conn)?;
+        diesel::delete(sessions::table.filter(stale)).execute(conn)
+    })?)
+}
@@ -0,0 +1,170 @@
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::io::{Error, ErrorKind};
+use std::rc::Rc;
+use std::sync::Arc;
+
+use async_zip::base::write::ZipFileWriter;
+use async_zip::{Compression, ZipDateTime, ZipEntryBuilder};
+use futures_util::{stream, AsyncWriteExt, StreamExt};
+use tokio::io::DuplexStream;
+use tokio_util::io::ReaderStream;
+
+use crate::shared::common::{ByteStream, StorageService};
+use crate::shared::media_type::is_text_media_type;
+
+// Most the archive runs ahead of the client reading it
+const ARCHIVE_BUFFER_SIZE: usize = 64 * 1024;
+
+// A file or folder in an archive
+struct ArchiveEntry {
+    // Path in the archive, folders end in `/`
+    path: String,
+    // Path and name of the contents in storage, None for folders
+    location: Option<(String, String)>,
+    media_type: Option<String>,
+    modified: Option<chrono::NaiveDateTime>,
+}
+
+///
+/// Collects the files and folders of a ZIP archive, which is then written while it is sent
+///
+/// Names are made unique within their folder, a second `a.txt` becomes `a (2).txt`
+///
+#[derive(Default)]
+pub struct Archive {
+    entries: Vec<ArchiveEntry>,
+    paths: HashSet<String>,
+}
+
+impl Archive {
+    ///
+    /// Adds a folder below `parent` ("" for the top of the archive), returns its path for adding its contents
+    ///
+    pub fn add_folder(&mut self, parent: &str, name: &str, modified: Option<chrono::NaiveDateTime>) -> String {
+        let path = format!("{}/", self.unique_path(parent, name));
+        self.entries.push(ArchiveEntry { path: path.clone(), location: None, media_type: None, modified });
+        path
+    }
+
+    ///
+    /// Adds a file below `parent` whose contents are stored at `location`
+    ///
+    pub fn add_file(&mut self, parent: &str, name: &str, location: (String, String), media_type: Option<String>, modified: Option<chrono::NaiveDateTime>) {
+        let path = self.unique_path(parent, name);
+        self.entries.push(ArchiveEntry { path, location: Some(location), media_type, modified });
+    }
+
+    fn unique_path(&mut self, parent: &str, name: &str) -> String {
+        // Names can't leave their folder
+        let name = match name.replace(['/', '\\'], "_") {
+            name if name.is_empty() || name == "." || name == ".." => "Unknown".to_string(),
+            name => name,
+        };
+        let (stem, extension) = match name.rfind('.') {
+            Some(index) if index > 0 => name.split_at(index),
+            _ => (name.as_str(), ""),
+        };
+
+        let mut path = format!("{}{}", parent, name);
+        let mut copy = 1;
+        while !self.paths.insert(path.to_lowercase()) {
+            copy += 1;
+            path = format!("{}{} ({}){}", parent, stem, copy, extension);
+        }
+        path
+    }
+
+    ///
+    /// Streams the archive, reading the files from storage one after the other
+    ///
+    /// Nothing but the current chunk is held in memory. Files missing in storage are left out,
+    /// any other failure ends the stream with an error so the download is cut off
+    ///
+    pub fn into_stream(self, storage: Arc<dyn StorageService>) -> ByteStream {
+        let (mut output, input) = tokio::io::duplex(ARCHIVE_BUFFER_SIZE);
+        let failure: Rc<RefCell<Option<Error>>> = Rc::new(RefCell::new(None));
+
+        let writer_failure = failure.clone();
+        actix_web::rt::spawn(async move {
+            if let Err(err) = write_archive(storage.as_ref(), self.entries, &mut output).await {
+                log::warn!("Failed to write archive: {}", err);
+                writer_failure.replace(Some(err));
+            }
+            // The reader only sees the end of the archive once the failure is recorded
+            drop(output);
+        });
+
+        let end = stream::once(async move { failure.take() }).filter_map(|failure| async move { failure.map(Err) });
+        Box::pin(ReaderStream::new(input).chain(end))
+    }
+}
+
+async fn write_archive(storage: &dyn StorageService, entries: Vec<ArchiveEntry>, output: &mut DuplexStream) -> Result<(), Error> {
+    let mut zip = ZipFileWriter::with_tokio(output);
+
+    for entry in entries {
+        // Text compresses well, most other media types are compressed already
+        let compression = match &entry.media_type {
+            Some(media_type) if is_text_media_type(media_type) => Compression::Deflate,
+            _ => Compression::Stored,
+        };
+        let mut builder = ZipEntryBuilder::new(entry.path.clone().into(), compression);
+        if let Some(modified) = entry.modified {
+            builder = builder.last_modification_date(ZipDateTime::from_chrono(&modified.and_utc()));
+        }
+
+        let Some((path, name)) = entry.location else {
+            zip.write_entry_whole(builder, &[]).await.map_err(Error::other)?;
+            continue;
+        };
+        let mut input = match storage.retrieve_file(&path, &name).await {
+            Ok(input) => input,
+            Err(err) if err.kind() == ErrorKind::NotFound => {
+                log::warn!("Leaving out missing file: {} of archive", entry.path);
+                continue;
+            }
+            Err(err) => return Err(err),
+        };
+
+        let mut writer = zip.write_entry_stream(builder).await.map_err(Error::other)?;
+        while let Some(chunk) = input.next().await {
+            writer.write_all(&chunk?).await?;
+        }
+        writer.close().await.map_err(Error::other)?;
+    }
+
+    zip.close().await.map_err(Error::other)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_are_made_unique_within_their_folder() {
+        let mut archive = Archive::default();
+        assert_eq!(archive.unique_path("", "a.txt"), "a.txt");
+        // Case doesn't make names different, unzipping on most systems would overwrite them
+        assert_eq!(archive.unique_path("", "A.txt"), "A (2).txt");
+        assert_eq!(archive.unique_path("", "a.txt"), "a (3).txt");
+        assert_eq!(archive.unique_path("docs/", "a.txt"), "docs/a.txt");
+        assert_eq!(archive.unique_path("", "archive.tar.gz"), "archive.tar.gz");
+        assert_eq!(archive.unique_path("", "archive.tar.gz"), "archive.tar (2).gz");
+        assert_eq!(archive.unique_path("", ".profile"), ".profile");
+        assert_eq!(archive.unique_path("", ".profile"), ".profile (2)");
+    }
+
+    #[test]
+    fn names_cannot_leave_their_folder() {
+        let mut archive = Archive::default();
+        assert_eq!(archive.unique_path("docs/", "../../etc/passwd"), "docs/.._.._etc_passwd");
+        assert_eq!(archive.unique_path("docs/", "..\\notes.txt"), "docs/.._notes.txt");
+        assert_eq!(archive.unique_path("docs/", "/root"), "docs/_root");
+        assert_eq!(archive.unique_path("docs/", ".."), "docs/Unknown");
+        assert_eq!(archive.unique_path("docs/", "."), "docs/Unknown (2)");
+        assert_eq!(archive.unique_path("docs/", ""), "docs/Unknown (3)");
+        assert_eq!(archive.add_folder("docs/", "..", None), "docs/Unknown (4)/");
+    }
+}
@@ -1,6 +1,8 @@
 // use std::{sync::{Arc, Mutex}};
 
 use std::io::Error;
+use std::pin::Pin;
+use std::sync::Arc;
 
 use actix_web::{HttpResponse, ResponseError};
 use derive_more::Display;
@@ -8,14 +10,35 @@ use diesel::{
     prelude::*, r2d2::{self, ConnectionManager, PooledConnection}
 };
 
+use actix_web::web::Bytes;
 use async_trait::async_trait;
+use futures_util::Stream;
 
-use crate::file_store::FileStore;
+use crate::mail::MailService;
+use crate::storage::blob_store::BlobStore;
 
 pub type DbError = Box<dyn std::error::Error + Send + Sync>;
 pub type DbPool = r2d2::Pool<ConnectionManager<SqliteConnection>>;
 pub type Connection = PooledConnection<ConnectionManager<SqliteConnection>>;
 
+// Milliseconds a connection waits for another one to finish writing before giving up
+const BUSY_TIMEOUT: u32 = 5000;
+
+///
+/// Sets up every pooled connection, so concurrent writers wait for each other instead of failing
+///
+#[derive(Debug)]
+pub struct ConnectionOptions;
+
+impl r2d2::CustomizeConnection<SqliteConnection, r2d2::Error> for ConnectionOptions {
+    fn on_acquire(&self, conn: &mut SqliteConnection) -> Result<(), r2d2::Error> {
+        diesel::sql_query(format!("PRAGMA busy_timeout = {}", BUSY_TIMEOUT))
+            .execute(conn)
+            .map(|_| ())
+            .map_err(r2d2::Error::QueryError)
+    }
+}
+
 // Format the response as JSON instead of the default text
 // actix_web::error::ErrorBadRequest(err)
 // ref: https://stackoverflow.com/questions/64291039/how-to-return-the-error-description-in-a-invalid-json-request-body-to-the-client
@@ -41,6 +64,18 @@ pub enum ServiceError {
 
     #[display(r#"{{"error":"Object '{}' not Found"}}"#, _0)]
     NotFound(String),
+
+    #[display(r#"{{"error":"{}"}}"#, _0)]
+    PayloadTooLarge(String),
+
+    #[display(r#"{{"error":"{}"}}"#, _0)]
+    Gone(String),
+
+    #[display(r#"{{"error":"{}"}}"#, _0)]
+    Forbidden(String),
+
+    #[display(r#"{{"error":"{}"}}"#, _0)]
+    TooManyRequests(String),
 }
 
 impl ResponseError for ServiceError {
@@ -58,6 +93,18 @@ impl ResponseError for ServiceError {
             ServiceError::NotFound(ref _message) => HttpResponse::NotFound()
                 .content_type("application/json")
                 .body(self.to_string()),
+            ServiceError::PayloadTooLarge(ref _message) => HttpResponse::PayloadTooLarge()
+                .content_type("application/json")
+                .body(self.to_string()),
+            ServiceError::Gone(ref _message) => HttpResponse::Gone()
+                .content_type("application/json")
+                .body(self.to_string()),
+            ServiceError::Forbidden(ref _message) => HttpResponse::Forbidden()
+                .content_type("application/json")
+                .body(self.to_string()),
+            ServiceError::TooManyRequests(ref _message) => HttpResponse::TooManyRequests()
+                .content_type("application/json")
+                .body(self.to_string()),
         }
     }
 }
@@ -68,6 +115,29 @@ pub struct Config {
     pub jwt_secret: String,
     pub _jwt_expires_in: String,
     pub _jwt_maxage: i32,
+    pub storage_service: String,
+    pub trash_retention_days: i64,
+    // Unfinished uploads are removed when they have not received a chunk for this long
+    pub upload_expiry_hours: i64,
+    // Bytes a user may store unless they have their own quota
+    pub default_storage_quota: i64,
+    // Number of workers running background jobs
+    pub job_workers: usize,
+    // Ids of the users allowed to use the admin endpoints
+    pub admin_user_ids: Vec<i32>,
+    // `log`, `file` or `smtp`, see `create_mail_service`
+    pub mail_service: String,
+    pub mail_from: String,
+    // Where the service is reached from outside, used for the links in mails
+    pub public_url: String,
+    // Refuse logins of users that have not verified their email address yet
+    pub require_email_verification: bool,
+    pub email_verification_hours: i64,
+    // Page of the web app that takes the new password, the reset token is added as `?token=`
+    pub password_reset_url: String,
+    pub password_reset_minutes: i64,
+    // Sessions end when they have not been refreshed for this long
+    pub refresh_token_days: i64,
 }
 
 impl Config {
@@ -76,11 +146,40 @@ impl Config {
         let jwt_secret = std::env::var("JWT_SECRET").expect("JWT_SECRET must be set");
         let jwt_expires_in = std::env::var("JWT_EXPIRED_IN").expect("JWT_EXPIRED_IN must be set");
         let jwt_maxage = std::env::var("JWT_MAXAGE").expect("JWT_MAXAGE must be set");
+        let storage_service = std::env::var("STORAGE_SERVICE").unwrap_or("file".to_string());
+        let trash_retention_days = std::env::var("TRASH_RETENTION_DAYS").unwrap_or("30".to_string());
+        let upload_expiry_hours = std::env::var("UPLOAD_EXPIRY_HOURS").unwrap_or("24".to_string());
+        let default_storage_quota = std::env::var("DEFAULT_STORAGE_QUOTA").unwrap_or("10737418240".to_string());
+        let job_workers = std::env::var("JOB_WORKERS").unwrap_or("2".to_string());
+        let admin_user_ids = std::env::var("ADMIN_USER_IDS").unwrap_or_default();
+        let mail_service = std::env::var("MAIL_SERVICE").unwrap_or("log".to_string());
+        let mail_from = std::env::var("MAIL_FROM").unwrap_or("Fly <no-reply@localhost>".to_string());
+        let public_url = std::env::var("PUBLIC_URL").unwrap_or("http://localhost:8090".to_string());
+        let require_email_verification = std::env::var("REQUIRE_EMAIL_VERIFICATION").unwrap_or("false".to_string());
+        let email_verification_hours = std::env::var("EMAIL_VERIFICATION_HOURS").unwrap_or("24".to_string());
+        let public_url = public_url.trim_end_matches('/').to_string();
+        let password_reset_url = std::env::var("PASSWORD_RESET_URL").unwrap_or(format!("{}/reset-password", public_url));
+        let password_reset_minutes = std::env::var("PASSWORD_RESET_MINUTES").unwrap_or("60".to_string());
+        let refresh_token_days = std::env::var("REFRESH_TOKEN_DAYS").unwrap_or("30".to_string());
         Config {
             database_url,
             jwt_secret,
             _jwt_expires_in: jwt_expires_in,
             _jwt_maxage: jwt_maxage.parse::<i32>().unwrap(),
+            storage_service,
+            trash_retention_days: trash_retention_days.parse::<i64>().unwrap(),
+            upload_expiry_hours: upload_expiry_hours.parse::<i64>().unwrap(),
+            default_storage_quota: default_storage_quota.parse::<i64>().unwrap(),
+            job_workers: job_workers.parse::<usize>().unwrap(),
+            admin_user_ids: admin_user_ids.split(',').map(|user_id| user_id.trim()).filter(|user_id| !user_id.is_empty()).map(|user_id| user_id.parse::<i32>().unwrap()).collect(),
+            mail_service,
+            mail_from,
+            public_url,
+            require_email_verification: require_email_verification.parse::<bool>().unwrap(),
+            email_verification_hours: email_verification_hours.parse::<i64>().unwrap(),
+            password_reset_url,
+            password_reset_minutes: password_reset_minutes.parse::<i64>().unwrap(),
+            refresh_token_days: refresh_token_days.parse::<i64>().unwrap(),
         }
     }
 }
@@ -94,17 +193,20 @@ impl Config {
 pub struct AppState {
     pool: DbPool,
     config: Config,
-    // storage: Box<dyn StorageService>,
-    storage: FileStore,
+    storage: Arc<dyn StorageService>,
+    blobs: Arc<BlobStore>,
+    mailer: Arc<dyn MailService>,
     prod_mode: bool,
 }
 
 impl AppState {
-    pub fn new(pool: DbPool, config: Config, storage: FileStore, prod_mode: bool) -> AppState {
+    pub fn new(pool: DbPool, config: Config, storage: Arc<dyn StorageService>, blobs: Arc<BlobStore>, mailer: Arc<dyn MailService>, prod_mode: bool) -> AppState {
         AppState {
             pool,
             config,
             storage,
+            blobs,
+            mailer,
             prod_mode,
         }
     }
@@ -134,9 +236,17 @@ impl AppState {
         self.prod_mode
     }
 
-    pub fn get_storage_service(&self) -> &FileStore { //&Box<dyn StorageService> {
+    pub fn get_storage_service(&self) -> &Arc<dyn StorageService> {
         &self.storage
-    }    
+    }
+
+    pub fn get_blob_store(&self) -> &Arc<BlobStore> {
+        &self.blobs
+    }
+
+    pub fn get_mail_service(&self) -> &Arc<dyn MailService> {
+        &self.mailer
+    }
 }
 
 pub fn build_full_path(user_folder: &str, file_folder: &str) -> String {
@@ -146,12 +256,26 @@ pub fn build_full_path(user_folder: &str, file_folder: &str) -> String {
     }
 }
 
+// Versions uploaded before the blob store existed are kept here, named after their version number
+pub fn build_version_path(user_folder: &str, file_id: &str) -> String {
+    format!("{}/.versions/{}", user_folder, file_id)
+}
+
+/// A stream of byte chunks flowing into or out of a storage service
+pub type ByteStream = Pin<Box<dyn Stream<Item = Result<Bytes, Error>>>>;
+
 #[allow(dead_code)]
-#[async_trait]
-pub trait StorageService {
-    async fn save_file(&mut self, path: String, name: String, input: &mut futures_util::stream::IntoStream<actix_multipart::Field>) -> Result<(), Error>;
-    fn retrieve_file(&self, path: String, name: String) -> Result<Vec<u8>, Error>;
-    fn create_folder(&self, path: String) -> Result<(), Error>;
-    fn list_file_names(&self, path: String) -> Result<Vec<String>, Error>;
-    fn list_folder_names(&self, path: String) -> Result<Vec<String>, Error>;
-}
\ No newline at end of file
+#[async_trait(?Send)]
+pub trait StorageService: Send + Sync {
+    async fn save_file(&self, path: &str, name: &str, input: ByteStream) -> Result<(), Error>;
+    async fn retrieve_file(&self, path: &str, name: &str) -> Result<ByteStream, Error>;
+    async fn retrieve_file_range(&self, path: &str, name: &str, start: u64, length: u64) -> Result<ByteStream, Error>;
+    async fn file_size(&self, path: &str, name: &str) -> Result<u64, Error>;
+    async fn delete_file(&self, path: &str, name: &str) -> Result<(), Error>;
+    async fn move_file(&self, from_path: &str, from_name: &str, to_path: &str, to_name: &str) -> Result<(), Error>;
+    async fn copy_file(&self, from_path: &str, from_name: &str, to_path: &str, to_name: &str) -> Result<(), Error>;
+    fn create_folder(&self, path: &str) -> Result<(), Error>;
+    async fn delete_folder(&self, path: &str) -> Result<(), Error>;
+    async fn list_file_names(&self, path: &str) -> Result<Vec<String>, Error>;
+    async fn list_folder_names(&self, path: &str) -> Result<Vec<String>, Error>;
+}
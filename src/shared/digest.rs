@@ -0,0 +1,74 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use futures_util::StreamExt;
+use sha2::{Digest, Sha256};
+
+use crate::shared::common::ByteStream;
+
+///
+/// SHA-256 checksum and size of the bytes that passed through a `digest_stream`
+///
+#[derive(Default)]
+pub struct StreamDigest {
+    hasher: Sha256,
+    size: u64,
+}
+
+impl StreamDigest {
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    // Hex encoded SHA-256 of everything seen so far
+    pub fn checksum(&self) -> String {
+        hex::encode(self.hasher.clone().finalize())
+    }
+}
+
+///
+/// Wraps `input` so the checksum and size of its content are computed while it is streamed
+///
+/// The digest is only complete once the returned stream has been consumed
+///
+pub fn digest_stream(input: ByteStream) -> (ByteStream, Rc<RefCell<StreamDigest>>) {
+    let digest = Rc::new(RefCell::new(StreamDigest::default()));
+    let stream_digest = digest.clone();
+    let stream: ByteStream = Box::pin(input.inspect(move |chunk| {
+        if let Ok(chunk) = chunk {
+            let mut digest = stream_digest.borrow_mut();
+            digest.hasher.update(chunk);
+            digest.size += chunk.len() as u64;
+        }
+    }));
+    (stream, digest)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::web::Bytes;
+    use futures_util::TryStreamExt;
+
+    async fn digest_of(chunks: &[&'static [u8]]) -> (String, u64) {
+        let chunks: Vec<Result<Bytes, std::io::Error>> = chunks.iter().map(|chunk| Ok(Bytes::from_static(chunk))).collect();
+        let (stream, digest) = digest_stream(Box::pin(futures_util::stream::iter(chunks)));
+        let _: Vec<Bytes> = stream.try_collect().await.unwrap();
+        let digest = digest.borrow();
+        (digest.checksum(), digest.size())
+    }
+
+    #[actix_web::test]
+    async fn digest_does_not_depend_on_how_the_stream_is_split() {
+        let expected = "b94d27b9934d3e08a52e52d7da7dabfac484efe37a5380ee9088f7ace2efcde9";
+        assert_eq!(digest_of(&[b"hello world"]).await, (expected.to_string(), 11));
+        assert_eq!(digest_of(&[b"hel", b"", b"lo wo", b"rld"]).await, (expected.to_string(), 11));
+        assert_eq!(digest_of(&[b"h", b"e", b"l", b"l", b"o", b" ", b"w", b"o", b"r", b"l", b"d"]).await, (expected.to_string(), 11));
+    }
+
+    #[actix_web::test]
+    async fn digest_of_an_empty_stream() {
+        let expected = "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855";
+        assert_eq!(digest_of(&[]).await, (expected.to_string(), 0));
+    }
+}
@@ -122,9 +122,79 @@ impl CreateResponseDto {
 
 }
 
-#[derive(Debug, Clone, Deserialize)]
+const DEFAULT_PAGE_SIZE: i64 = 100;
+const MAX_PAGE_SIZE: i64 = 1000;
+
+///
+/// Filters, sort order and page of a listing
+///
+#[derive(Debug, Clone, Default, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct QueryParams {
     pub folder_id: Option<String>,
+    // Pages start at 1
+    pub page: Option<i64>,
+    pub page_size: Option<i64>,
+    pub sort: Option<SortField>,
+    pub order: Option<SortOrder>,
+    // Either a full media type or a type like `image/*`
+    pub media_type: Option<String>,
+    pub modified_after: Option<chrono::NaiveDateTime>,
+    pub modified_before: Option<chrono::NaiveDateTime>,
+    // False lists the items in the trash, active items are listed by default
+    pub active: Option<bool>,
+}
+
+impl QueryParams {
+    pub fn page(&self) -> i64 {
+        self.page.unwrap_or(1).max(1)
+    }
+
+    pub fn page_size(&self) -> i64 {
+        self.page_size.unwrap_or(DEFAULT_PAGE_SIZE).clamp(1, MAX_PAGE_SIZE)
+    }
+
+    pub fn offset(&self) -> i64 {
+        (self.page() - 1).saturating_mul(self.page_size())
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SortField {
+    #[default]
+    Title,
+    Created,
+    Updated,
+    Size,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SortOrder {
+    #[default]
+    Asc,
+    Desc,
+}
+
+///
+/// One page of a listing together with the total number of items
+///
+#[derive(Debug, Clone, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct PageDto<T> {
+    pub items: Vec<T>,
+    pub page: i64,
+    pub page_size: i64,
+    // Items on all pages
+    pub total: i64,
+    pub total_pages: i64,
+}
+
+impl<T> PageDto<T> {
+    pub fn new(items: Vec<T>, params: &QueryParams, total: i64) -> Self {
+        let page_size = params.page_size();
+        PageDto { items, page: params.page(), page_size, total, total_pages: (total + page_size - 1) / page_size }
+    }
 }
 
@@ -0,0 +1,169 @@
+use std::io::Error;
+
+use futures_util::{StreamExt, TryStreamExt};
+
+use crate::shared::common::ByteStream;
+
+const DEFAULT_MEDIA_TYPE: &str = "application/octet-stream";
+
+// Bytes read from the start of the content before its media type is detected
+const SNIFF_SIZE: usize = 8 * 1024;
+
+// Application types whose contents are plain text
+const TEXT_APPLICATION_TYPES: [&str; 8] = [
+    "application/json",
+    "application/xml",
+    "application/javascript",
+    "application/x-javascript",
+    "application/yaml",
+    "application/x-yaml",
+    "application/toml",
+    "application/x-sh",
+];
+
+///
+/// Works out the media type of an upload
+///
+/// The magic bytes at the start of the content win, then the content type declared by the
+/// client and finally the file extension. Generic declarations like `application/octet-stream`
+/// are ignored so they don't hide a more specific type derived from the extension.
+///
+pub fn detect_media_type(content: &[u8], declared: Option<&str>, filename: Option<&str>) -> String {
+    if let Some(kind) = infer::get(content) {
+        return kind.mime_type().to_string();
+    }
+
+    if let Some(declared) = declared.map(str::trim).filter(|declared| !declared.is_empty() && *declared != DEFAULT_MEDIA_TYPE) {
+        return declared.to_string();
+    }
+
+    filename
+        .and_then(|filename| mime_guess::from_path(filename).first())
+        .map(|mime| mime.essence_str().to_string())
+        .unwrap_or(DEFAULT_MEDIA_TYPE.to_string())
+}
+
+///
+/// Reads the start of `input`, up to 8 KiB or the end of the content, to detect its media type
+///
+/// Returns the media type together with a stream that still yields all of the content
+///
+pub async fn sniff_media_type(mut input: ByteStream, declared: Option<&str>, filename: Option<&str>) -> Result<(String, ByteStream), Error> {
+    // Clients may send tiny chunks, so the magic bytes can be spread over several of them
+    let mut chunks = Vec::new();
+    let mut head = Vec::new();
+    let mut finished = false;
+    while head.len() < SNIFF_SIZE {
+        match input.try_next().await? {
+            Some(chunk) => {
+                head.extend_from_slice(&chunk[..chunk.len().min(SNIFF_SIZE - head.len())]);
+                chunks.push(Ok(chunk));
+            }
+            None => {
+                finished = true;
+                break;
+            }
+        }
+    }
+    let media_type = detect_media_type(&head, declared, filename);
+
+    // Some streams, like multipart fields, must not be polled again once they have ended
+    let buffered = futures_util::stream::iter(chunks);
+    let stream: ByteStream = match finished {
+        true => Box::pin(buffered),
+        false => Box::pin(buffered.chain(input)),
+    };
+    Ok((media_type, stream))
+}
+
+///
+/// Checks whether contents of the media type are text that can be indexed for search
+///
+pub fn is_text_media_type(media_type: &str) -> bool {
+    media_type.starts_with("text/")
+        || media_type.ends_with("+json")
+        || media_type.ends_with("+xml")
+        || TEXT_APPLICATION_TYPES.contains(&media_type)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::web::Bytes;
+
+    const PNG: &[u8] = b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR";
+
+    #[test]
+    fn magic_bytes_win_over_declared_type_and_extension() {
+        assert_eq!(detect_media_type(PNG, Some("text/plain"), Some("notes.txt")), "image/png");
+    }
+
+    #[test]
+    fn declared_type_wins_over_extension() {
+        assert_eq!(detect_media_type(b"hello", Some("text/markdown"), Some("notes.txt")), "text/markdown");
+        // Generic declarations don't hide the extension
+        assert_eq!(detect_media_type(b"hello", Some("application/octet-stream"), Some("notes.txt")), "text/plain");
+        assert_eq!(detect_media_type(b"hello", Some(" "), Some("notes.json")), "application/json");
+    }
+
+    #[test]
+    fn unknown_content_is_octet_stream() {
+        assert_eq!(detect_media_type(b"hello", None, None), DEFAULT_MEDIA_TYPE);
+        assert_eq!(detect_media_type(b"", None, Some("notes")), DEFAULT_MEDIA_TYPE);
+    }
+
+    fn stream_of(chunks: Vec<Vec<u8>>) -> ByteStream {
+        Box::pin(futures_util::stream::iter(chunks.into_iter().map(|chunk| Ok(Bytes::from(chunk)))))
+    }
+
+    async fn read_all(stream: ByteStream) -> Vec<u8> {
+        stream.try_fold(Vec::new(), |mut data, chunk| async move {
+            data.extend_from_slice(&chunk);
+            Ok(data)
+        }).await.unwrap()
+    }
+
+    #[actix_web::test]
+    async fn magic_bytes_split_over_chunks_are_found() {
+        let chunks: Vec<Vec<u8>> = PNG.iter().map(|byte| vec![*byte]).chain([vec![1; 20_000]]).collect();
+        let (media_type, stream) = sniff_media_type(stream_of(chunks), None, Some("image.bin")).await.unwrap();
+        assert_eq!(media_type, "image/png");
+
+        let data = read_all(stream).await;
+        assert_eq!(&data[..PNG.len()], PNG);
+        assert_eq!(data.len(), PNG.len() + 20_000);
+    }
+
+    #[actix_web::test]
+    async fn only_the_first_8_kib_are_sniffed() {
+        // Magic bytes after the sniffed start are not magic bytes
+        let chunks = vec![vec![b'a'; SNIFF_SIZE], PNG.to_vec()];
+        let (media_type, stream) = sniff_media_type(stream_of(chunks), None, Some("notes.txt")).await.unwrap();
+        assert_eq!(media_type, "text/plain");
+        assert_eq!(read_all(stream).await.len(), SNIFF_SIZE + PNG.len());
+    }
+
+    #[actix_web::test]
+    async fn empty_content_falls_back_to_the_declared_type() {
+        let (media_type, stream) = sniff_media_type(stream_of(Vec::new()), Some("text/csv"), None).await.unwrap();
+        assert_eq!(media_type, "text/csv");
+        assert!(read_all(stream).await.is_empty());
+    }
+
+    #[actix_web::test]
+    async fn short_content_is_not_polled_again_after_it_ended() {
+        // Like multipart fields, panics when polled once it has ended
+        let mut ended = false;
+        let mut chunks = vec![Bytes::from_static(PNG)].into_iter();
+        let input: ByteStream = Box::pin(futures_util::stream::poll_fn(move |_| {
+            assert!(!ended, "polled after the end");
+            let chunk = chunks.next();
+            ended = chunk.is_none();
+            std::task::Poll::Ready(chunk.map(Ok))
+        }));
+
+        let (media_type, stream) = sniff_media_type(input, None, None).await.unwrap();
+        assert_eq!(media_type, "image/png");
+        assert_eq!(read_all(stream).await, PNG);
+    }
+}
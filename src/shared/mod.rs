@@ -1,3 +1,7 @@
+pub mod archive;
 pub mod common;
+pub mod digest;
 pub mod dto;
+pub mod media_type;
 
+pub mod quota;
@@ -0,0 +1,57 @@
+use std::cell::Cell;
+use std::io::{Error, ErrorKind};
+use std::rc::Rc;
+
+use futures_util::StreamExt;
+
+use crate::shared::common::ByteStream;
+
+///
+/// Wraps `input` so it fails as soon as more than `limit` bytes have passed through
+///
+/// `exceeded` is set when that happens, so callers can tell a full quota apart from a storage error
+///
+pub fn quota_stream(input: ByteStream, limit: u64, exceeded: Rc<Cell<bool>>) -> ByteStream {
+    let mut received: u64 = 0;
+    Box::pin(input.map(move |chunk| {
+        let chunk = chunk?;
+        received += chunk.len() as u64;
+        if received > limit {
+            exceeded.set(true);
+            return Err(Error::new(ErrorKind::FileTooLarge, "Upload exceeds the storage quota"));
+        }
+        Ok(chunk)
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::web::Bytes;
+    use futures_util::TryStreamExt;
+
+    fn chunks(chunks: &[&'static [u8]]) -> ByteStream {
+        let chunks: Vec<Result<Bytes, Error>> = chunks.iter().map(|chunk| Ok(Bytes::from_static(chunk))).collect();
+        Box::pin(futures_util::stream::iter(chunks))
+    }
+
+    #[actix_web::test]
+    async fn quota_stream_passes_exactly_the_limit() {
+        let exceeded = Rc::new(Cell::new(false));
+        let stream = quota_stream(chunks(&[b"hello", b" ", b"world"]), 11, exceeded.clone());
+        let received: Vec<Bytes> = stream.try_collect().await.unwrap();
+        assert_eq!(received.concat(), b"hello world");
+        assert!(!exceeded.get());
+    }
+
+    #[actix_web::test]
+    async fn quota_stream_trips_one_byte_over_the_limit() {
+        let exceeded = Rc::new(Cell::new(false));
+        let mut stream = quota_stream(chunks(&[b"hello", b" ", b"world"]), 10, exceeded.clone());
+        assert_eq!(stream.try_next().await.unwrap().unwrap(), "hello");
+        assert_eq!(stream.try_next().await.unwrap().unwrap(), " ");
+        assert!(!exceeded.get());
+        assert_eq!(stream.try_next().await.unwrap_err().kind(), ErrorKind::FileTooLarge);
+        assert!(exceeded.get());
+    }
+}
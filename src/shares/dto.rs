@@ -0,0 +1,103 @@
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use crate::files::dto::FileDto;
+use crate::schema::share_links;
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+#[derive(Queryable, Selectable, Insertable)]
+#[diesel(table_name = share_links)]
+#[serde(rename_all = "camelCase")]
+pub struct ShareLinkDto {
+    // The unique identifier for the share link
+    pub id: String,
+    // The unguessable token used in the public URL
+    pub token: String,
+    // The ID of the user who shared the item
+    pub owner_id: i32,
+    // Either the shared file or the shared folder is set
+    pub file_id: Option<String>,
+    pub folder_id: Option<String>,
+    // Argon2 hash of the link's password, never sent to clients
+    #[serde(skip_serializing)]
+    pub password: Option<String>,
+    pub expires_at: Option<chrono::NaiveDateTime>,
+    pub max_downloads: Option<i32>,
+    pub download_count: i32,
+    // Wrong passwords in a row, the link is locked for a while after too many
+    #[serde(skip)]
+    pub failed_attempts: i32,
+    #[serde(skip)]
+    pub locked_until: Option<chrono::NaiveDateTime>,
+    pub created_at: Option<chrono::NaiveDateTime>,
+    pub updated_at: Option<chrono::NaiveDateTime>,
+    pub created_by: i32,
+    pub updated_by: i32,
+    // False once the link has been revoked
+    pub active: bool,
+}
+
+#[derive(Debug, Clone, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct CreateShareLinkDto {
+    pub file_id: Option<String>,
+    pub folder_id: Option<String>,
+    pub password: Option<String>,
+    pub expires_at: Option<chrono::NaiveDateTime>,
+    pub max_downloads: Option<i32>,
+}
+
+///
+/// What an anonymous visitor sees of a share link
+///
+#[derive(Debug, Clone, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct PublicShareDto {
+    pub token: String,
+    pub expires_at: Option<chrono::NaiveDateTime>,
+    pub file: Option<PublicFileDto>,
+    pub folder: Option<PublicFolderDto>,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct PublicFileDto {
+    pub id: String,
+    pub title: String,
+    pub description: Option<String>,
+    pub media_type: Option<String>,
+    pub orginal_filename: Option<String>,
+    pub size: i64,
+    pub updated_at: Option<chrono::NaiveDateTime>,
+}
+
+impl From<FileDto> for PublicFileDto {
+    fn from(file: FileDto) -> Self {
+        PublicFileDto {
+            id: file.id,
+            title: file.title,
+            description: file.description,
+            media_type: file.media_type,
+            orginal_filename: file.orginal_filename,
+            size: file.size,
+            updated_at: file.updated_at,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct PublicFolderDto {
+    pub id: String,
+    pub title: String,
+    pub description: Option<String>,
+    // Folders directly inside this folder, they can be opened through the same link
+    pub folders: Vec<PublicSubfolderDto>,
+    pub files: Vec<PublicFileDto>,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct PublicSubfolderDto {
+    pub id: String,
+    pub title: String,
+}
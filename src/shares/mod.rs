@@ -0,0 +1,375 @@
+pub mod dto;
+pub mod service;
+
+use actix_web::{
+    delete, get, http::StatusCode, post, web, Error, HttpRequest, HttpResponse,
+};
+use diesel::SqliteConnection;
+
+use log::info;
+
+use crate::auth::jwt_auth;
+use crate::files::file_contents_response;
+use crate::files::service::{get_all_files, get_file};
+use crate::folders::service::{get_all_folders_in_folder, get_folder, is_descendant_of};
+use crate::groups::dto::Owner;
+use crate::get_user;
+use crate::shared::common::ServiceError;
+use crate::shared::common::AppState;
+use crate::shared::dto::QueryParams;
+use crate::sharing::dto::Permission;
+use service::{create_share_link, get_share_links, get_share_link_by_token, revoke_share_link, record_download, verify_share_password};
+use service::{record_failed_password, reset_failed_passwords};
+
+use dto::{ShareLinkDto, CreateShareLinkDto, PublicShareDto, PublicFileDto, PublicFolderDto, PublicSubfolderDto};
+
+// Header carrying the password of a protected share link
+const SHARE_PASSWORD: &str = "X-Share-Password";
+
+///
+/// Gets all active share links of the user
+///
+#[utoipa::path(
+    get,
+    tag = "Shares",
+    path = "/api/shares",
+    responses(
+        (status = 200, description = "Successfully retrieved the share links", body = [Vec<ShareLinkDto>])
+    )
+)]
+#[get("")]
+pub async fn get_share_links_handler(
+    app: web::Data<AppState>,
+    jwt: jwt_auth::JwtMiddleware,
+) -> Result<HttpResponse, Error> {
+    let mut conn = app
+        .get_connection()
+        .map_err(|err| ServiceError::NotFound(err.to_string()))?;
+
+    match get_share_links(&mut conn, jwt.user_id) {
+        Ok(links) => Ok(HttpResponse::Ok().json(links)),
+        Err(err) => Err(ServiceError::NotFound(err.to_string()).into()),
+    }
+}
+
+///
+/// Creates a public share link for a file or folder
+///
+/// The link can optionally expire, require a password or allow a limited number of downloads
+///
+#[utoipa::path(
+    post,
+    tag = "Shares",
+    path = "/api/shares",
+    request_body = CreateShareLinkDto,
+    responses(
+        (status = 201, description = "Successfully created a share link", body = [ShareLinkDto])
+    )
+)]
+#[post("")]
+pub async fn create_share_link_handler(
+    app: web::Data<AppState>,
+    jwt: jwt_auth::JwtMiddleware,
+    data: web::Json<CreateShareLinkDto>,
+) -> Result<HttpResponse, Error> {
+    let user_id = jwt.user_id;
+    let link = data.into_inner();
+
+    let mut conn = app
+        .get_connection()
+        .map_err(|err| ServiceError::NotFound(err.to_string()))?;
+
+    match (&link.file_id, &link.folder_id) {
+        (Some(file_id), None) => {
+            get_file(&mut conn, file_id, user_id, Permission::Owner).map_err(|err| ServiceError::NotFound(err.to_string()))?;
+        }
+        (None, Some(folder_id)) => {
+            get_folder(&mut conn, folder_id, user_id).map_err(|err| ServiceError::NotFound(err.to_string()))?;
+        }
+        _ => return Err(ServiceError::BadRequest("Either fileId or folderId must be set".to_string()).into()),
+    }
+    if link.max_downloads.is_some_and(|max_downloads| max_downloads < 1) {
+        return Err(ServiceError::BadRequest("maxDownloads must be at least 1".to_string()).into());
+    }
+    if link.expires_at.is_some_and(|expires_at| expires_at <= chrono::Utc::now().naive_utc()) {
+        return Err(ServiceError::BadRequest("expiresAt must be in the future".to_string()).into());
+    }
+
+    let link = create_share_link(&mut conn, link, user_id).map_err(|err| ServiceError::InternalServerError(err.to_string()))?;
+    info!("Created share link: {} for user: {}", link.id, user_id);
+
+    Ok(HttpResponse::Created().json(link))
+}
+
+///
+/// Revokes a share link
+///
+#[utoipa::path(
+    delete,
+    tag = "Shares",
+    path = "/api/shares/{share_id}",
+    responses(
+        (status = 204, description = "Successfully revoked a share link")
+    )
+)]
+#[delete("/{share_id}")]
+pub async fn revoke_share_link_handler(
+    app: web::Data<AppState>,
+    jwt: jwt_auth::JwtMiddleware,
+    path: web::Path<String>,
+) -> Result<HttpResponse, Error> {
+    let share_id = path.to_string();
+
+    let mut conn = app
+        .get_connection()
+        .map_err(|err| ServiceError::NotFound(err.to_string()))?;
+
+    match revoke_share_link(&mut conn, &share_id, jwt.user_id) {
+        Ok(0) => Err(ServiceError::NotFound(share_id).into()),
+        Ok(_) => Ok(HttpResponse::NoContent().finish()),
+        Err(err) => Err(ServiceError::InternalServerError(err.to_string()).into()),
+    }
+}
+
+///
+/// Gets the item behind a share link, no login required
+///
+/// Password protected links expect the password in the X-Share-Password header
+///
+#[utoipa::path(
+    get,
+    tag = "Shares",
+    path = "/api/public/shares/{token}",
+    responses(
+        (status = 200, description = "Successfully retrieved the shared item", body = [PublicShareDto]),
+        (status = 401, description = "The link requires a password"),
+        (status = 410, description = "The link has expired or reached its download limit")
+    )
+)]
+#[get("/{token}")]
+pub async fn get_public_share_handler(
+    req: HttpRequest,
+    app: web::Data<AppState>,
+    path: web::Path<String>,
+) -> Result<HttpResponse, Error> {
+    let mut conn = app
+        .get_connection()
+        .map_err(|err| ServiceError::NotFound(err.to_string()))?;
+
+    let link = open_share_link(&mut conn, &req, &path)?;
+
+    let mut share = PublicShareDto {
+        token: link.token.clone(),
+        expires_at: link.expires_at,
+        file: None,
+        folder: None,
+    };
+    if let Some(file_id) = &link.file_id {
+        let file = get_file(&mut conn, file_id, link.owner_id, Permission::Owner).map_err(|_| ServiceError::NotFound(link.token.clone()))?;
+        share.file = Some(PublicFileDto::from(file));
+    }
+    if let Some(folder_id) = &link.folder_id {
+        share.folder = Some(public_folder(&mut conn, &link, folder_id)?);
+    }
+
+    Ok(HttpResponse::Ok().json(share))
+}
+
+///
+/// Gets a folder inside a shared folder, no login required
+///
+#[utoipa::path(
+    get,
+    tag = "Shares",
+    path = "/api/public/shares/{token}/folders/{folder_id}",
+    responses(
+        (status = 200, description = "Successfully retrieved the shared folder", body = [PublicFolderDto])
+    )
+)]
+#[get("/{token}/folders/{folder_id}")]
+pub async fn get_public_share_folder_handler(
+    req: HttpRequest,
+    app: web::Data<AppState>,
+    path: web::Path<(String, String)>,
+) -> Result<HttpResponse, Error> {
+    let (token, folder_id) = path.into_inner();
+
+    let mut conn = app
+        .get_connection()
+        .map_err(|err| ServiceError::NotFound(err.to_string()))?;
+
+    let link = open_share_link(&mut conn, &req, &token)?;
+    if !is_shared_folder(&mut conn, &link, &folder_id)? {
+        return Err(ServiceError::NotFound(folder_id).into());
+    }
+
+    Ok(HttpResponse::Ok().json(public_folder(&mut conn, &link, &folder_id)?))
+}
+
+///
+/// Downloads a shared file, no login required
+///
+/// Every full download counts against the link's download limit, range requests do not
+///
+#[utoipa::path(
+    get,
+    tag = "Shares",
+    path = "/api/public/shares/{token}/contents",
+    responses(
+        (status = 200, description = "Successfully downloaded the shared file", body = [Vec<u8>])
+    )
+)]
+#[get("/{token}/contents")]
+pub async fn get_public_share_contents_handler(
+    req: HttpRequest,
+    app: web::Data<AppState>,
+    path: web::Path<String>,
+) -> Result<HttpResponse, Error> {
+    let mut conn = app
+        .get_connection()
+        .map_err(|err| ServiceError::NotFound(err.to_string()))?;
+
+    let link = open_share_link(&mut conn, &req, &path)?;
+    let file_id = link.file_id.clone().ok_or_else(|| ServiceError::NotFound(link.token.clone()))?;
+
+    download_shared_file(&req, &app, &mut conn, &link, &file_id).await
+}
+
+///
+/// Downloads a file inside a shared folder, no login required
+///
+/// Every full download counts against the link's download limit, range requests do not
+///
+#[utoipa::path(
+    get,
+    tag = "Shares",
+    path = "/api/public/shares/{token}/files/{file_id}/contents",
+    responses(
+        (status = 200, description = "Successfully downloaded the shared file", body = [Vec<u8>])
+    )
+)]
+#[get("/{token}/files/{file_id}/contents")]
+pub async fn get_public_share_file_contents_handler(
+    req: HttpRequest,
+    app: web::Data<AppState>,
+    path: web::Path<(String, String)>,
+) -> Result<HttpResponse, Error> {
+    let (token, file_id) = path.into_inner();
+
+    let mut conn = app
+        .get_connection()
+        .map_err(|err| ServiceError::NotFound(err.to_string()))?;
+
+    let link = open_share_link(&mut conn, &req, &token)?;
+    if link.folder_id.is_none() {
+        return Err(ServiceError::NotFound(file_id).into());
+    }
+
+    download_shared_file(&req, &app, &mut conn, &link, &file_id).await
+}
+
+// Loads a share link and checks it can still be used by whoever presents it
+fn open_share_link(conn: &mut SqliteConnection, req: &HttpRequest, token: &str) -> Result<ShareLinkDto, ServiceError> {
+    let link = get_share_link_by_token(conn, token).map_err(|_| ServiceError::NotFound(token.to_string()))?;
+
+    if link.expires_at.is_some_and(|expires_at| expires_at <= chrono::Utc::now().naive_utc()) {
+        return Err(ServiceError::Gone("The share link has expired".to_string()));
+    }
+    if link.max_downloads.is_some_and(|max_downloads| link.download_count >= max_downloads) {
+        return Err(ServiceError::Gone("The share link has reached its download limit".to_string()));
+    }
+
+    if link.password.is_none() {
+        return Ok(link);
+    }
+    // Guessing passwords is slowed down by locking the link, before any hash is checked
+    if link.locked_until.is_some_and(|locked_until| locked_until > chrono::Utc::now().naive_utc()) {
+        return Err(ServiceError::TooManyRequests("Too many wrong passwords, try again later".to_string()));
+    }
+
+    let password = req.headers().get(SHARE_PASSWORD).and_then(|value| value.to_str().ok());
+    if !verify_share_password(&link, password) {
+        // A missing password is a request for the password prompt, not a guess
+        if password.is_some() {
+            record_failed_password(conn, &link).map_err(|err| ServiceError::InternalServerError(err.to_string()))?;
+        }
+        return Err(ServiceError::Unauthorized);
+    }
+    if link.failed_attempts > 0 {
+        reset_failed_passwords(conn, &link.id).map_err(|err| ServiceError::InternalServerError(err.to_string()))?;
+    }
+
+    Ok(link)
+}
+
+// The shared folder itself or any folder below it
+fn is_shared_folder(conn: &mut SqliteConnection, link: &ShareLinkDto, folder_id: &str) -> Result<bool, ServiceError> {
+    match &link.folder_id {
+        Some(shared_folder_id) => is_descendant_of(conn, folder_id, shared_folder_id, &Owner::User(link.owner_id))
+            .map_err(|err| ServiceError::InternalServerError(err.to_string())),
+        None => Ok(false),
+    }
+}
+
+fn public_folder(conn: &mut SqliteConnection, link: &ShareLinkDto, folder_id: &str) -> Result<PublicFolderDto, ServiceError> {
+    let folder = get_folder(conn, folder_id, link.owner_id).map_err(|_| ServiceError::NotFound(folder_id.to_string()))?;
+    // Only the user's own folders can be shared through a link
+    let owner = Owner::User(link.owner_id);
+    let folders = get_all_folders_in_folder(conn, &owner, folder_id.to_string())
+        .map_err(|err| ServiceError::InternalServerError(err.to_string()))?;
+    let files = get_all_files(conn, &owner, &QueryParams { folder_id: Some(folder_id.to_string()), ..Default::default() })
+        .map_err(|err| ServiceError::InternalServerError(err.to_string()))?;
+
+    Ok(PublicFolderDto {
+        id: folder.id,
+        title: folder.title,
+        description: folder.description,
+        folders: folders.into_iter().map(|folder| PublicSubfolderDto { id: folder.id, title: folder.title }).collect(),
+        files: files.into_iter().map(PublicFileDto::from).collect(),
+    })
+}
+
+async fn download_shared_file(
+    req: &HttpRequest,
+    app: &AppState,
+    conn: &mut SqliteConnection,
+    link: &ShareLinkDto,
+    file_id: &String,
+) -> Result<HttpResponse, Error> {
+    let file = get_file(conn, file_id, link.owner_id, Permission::Owner).map_err(|_| ServiceError::NotFound(file_id.to_string()))?;
+    if link.file_id.as_ref() != Some(&file.id) && !is_shared_folder(conn, link, &file.folder_id)? {
+        return Err(ServiceError::NotFound(file_id.to_string()).into());
+    }
+
+    let owner = get_user(conn, link.owner_id).map_err(|err| ServiceError::NotFound(err.to_string()))?;
+    let file_id = file.id.clone();
+    let response = file_contents_response(req, app, &owner, file).await?;
+
+    // Only full downloads count, not the ranges of a player or resumed download, nor revalidations
+    if response.status() == StatusCode::OK {
+        // Another download may have used up the limit since the link was opened
+        if record_download(conn, &link.id).map_err(|err| ServiceError::InternalServerError(err.to_string()))? == 0 {
+            return Err(ServiceError::Gone("The share link has reached its download limit".to_string()).into());
+        }
+        info!("Downloading shared file: {} through share link: {}", file_id, link.id);
+    }
+
+    Ok(response)
+}
+
+pub fn config(conf: &mut web::ServiceConfig) {
+    let scope = web::scope("/shares")
+            .service(get_share_links_handler)
+            .service(create_share_link_handler)
+            .service(revoke_share_link_handler)
+            ;
+    conf.service(scope);
+
+    let public_scope = web::scope("/public/shares")
+            .service(get_public_share_handler)
+            .service(get_public_share_folder_handler)
+            .service(get_public_share_contents_handler)
+            .service(get_public_share_file_contents_handler)
+            ;
+    conf.service(public_scope);
+}
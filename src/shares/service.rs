@@ -0,0 +1,126 @@
+use crate::auth::tokens::generate_token;
+use crate::shared::common::DbError;
+use super::dto::{ShareLinkDto, CreateShareLinkDto};
+use argon2::{PasswordHash, PasswordVerifier};
+use argon2::{
+    password_hash::{rand_core::OsRng, PasswordHasher, SaltString},
+    Argon2,
+};
+use diesel::prelude::*;
+use uuid::Uuid;
+
+use crate::schema::share_links::dsl;
+
+// Wrong passwords allowed in a row before the link is locked, each further one doubles the lock
+const MAX_FAILED_ATTEMPTS: i32 = 5;
+const LOCK_SECONDS: i64 = 60;
+const MAX_LOCK_SECONDS: i64 = 60 * 60;
+
+pub fn create_share_link(conn: &mut SqliteConnection, link: CreateShareLinkDto, owner_id: i32) -> Result<ShareLinkDto, DbError> {
+    let password = match link.password {
+        Some(password) => Some(Argon2::default()
+            .hash_password(password.as_bytes(), &SaltString::generate(&mut OsRng))
+            .map_err(|err| format!("Error while hashing password: {}", err))?
+            .to_string()),
+        None => None,
+    };
+
+    let link = ShareLinkDto {
+        id: Uuid::new_v4().to_string(),
+        token: generate_token(),
+        owner_id,
+        file_id: link.file_id,
+        folder_id: link.folder_id,
+        password,
+        expires_at: link.expires_at,
+        max_downloads: link.max_downloads,
+        download_count: 0,
+        failed_attempts: 0,
+        locked_until: None,
+        created_at: None,
+        updated_at: None,
+        created_by: owner_id,
+        updated_by: owner_id,
+        active: true,
+    };
+
+    diesel::insert_into(dsl::share_links)
+        .values(&link)
+        .execute(conn)?;
+    Ok(link)
+}
+
+pub fn get_share_links(conn: &mut SqliteConnection, owner_id: i32) -> Result<Vec<ShareLinkDto>, DbError> {
+    Ok(dsl::share_links
+        .filter(dsl::owner_id.eq(owner_id))
+        .filter(dsl::active.eq(true))
+        .order(dsl::created_at.desc())
+        .load::<ShareLinkDto>(conn)?)
+}
+
+pub fn get_share_link_by_token(conn: &mut SqliteConnection, token: &str) -> Result<ShareLinkDto, DbError> {
+    let link = dsl::share_links
+        .filter(dsl::token.eq(token))
+        .filter(dsl::active.eq(true))
+        .first::<ShareLinkDto>(conn)
+        .map_err(|err| format!("Error loading share link, Error: {}", err))?;
+    Ok(link)
+}
+
+pub fn revoke_share_link(conn: &mut SqliteConnection, link_id: &str, owner_id: i32) -> Result<usize, DbError> {
+    Ok(diesel::update(dsl::share_links.filter(dsl::id.eq(link_id).and(dsl::owner_id.eq(owner_id)).and(dsl::active.eq(true))))
+        .set((
+            dsl::active.eq(false),
+            dsl::updated_by.eq(owner_id),
+            dsl::updated_at.eq(chrono::Utc::now().naive_utc())))
+        .execute(conn)?)
+}
+
+///
+/// Counts a download against the link's limit, returns zero when the limit has already been reached
+///
+pub fn record_download(conn: &mut SqliteConnection, link_id: &str) -> Result<usize, DbError> {
+    Ok(diesel::update(dsl::share_links
+            .filter(dsl::id.eq(link_id))
+            .filter(dsl::max_downloads.is_null().or(dsl::download_count.nullable().lt(dsl::max_downloads))))
+        .set(dsl::download_count.eq(dsl::download_count + 1))
+        .execute(conn)?)
+}
+
+pub fn verify_share_password(link: &ShareLinkDto, password: Option<&str>) -> bool {
+    match (&link.password, password) {
+        (None, _) => true,
+        (Some(hash), Some(password)) => PasswordHash::new(hash)
+            .is_ok_and(|hash| Argon2::default().verify_password(password.as_bytes(), &hash).is_ok()),
+        (Some(_), None) => false,
+    }
+}
+
+///
+/// Counts a wrong password, after `MAX_FAILED_ATTEMPTS` in a row the link is locked for a minute,
+/// doubling with every further one up to an hour
+///
+pub fn record_failed_password(conn: &mut SqliteConnection, link: &ShareLinkDto) -> Result<usize, DbError> {
+    let failed_attempts = link.failed_attempts + 1;
+    let locked_until = match failed_attempts >= MAX_FAILED_ATTEMPTS {
+        true => {
+            let doublings = (failed_attempts - MAX_FAILED_ATTEMPTS).clamp(0, 20);
+            Some(chrono::Utc::now().naive_utc() + chrono::Duration::seconds((LOCK_SECONDS << doublings).min(MAX_LOCK_SECONDS)))
+        }
+        false => None,
+    };
+
+    Ok(diesel::update(dsl::share_links.filter(dsl::id.eq(&link.id)))
+        .set((
+            dsl::failed_attempts.eq(dsl::failed_attempts + 1),
+            dsl::locked_until.eq(locked_until)))
+        .execute(conn)?)
+}
+
+pub fn reset_failed_passwords(conn: &mut SqliteConnection, link_id: &str) -> Result<usize, DbError> {
+    Ok(diesel::update(dsl::share_links.filter(dsl::id.eq(link_id)))
+        .set((
+            dsl::failed_attempts.eq(0),
+            dsl::locked_until.eq(None::<chrono::NaiveDateTime>)))
+        .execute(conn)?)
+}
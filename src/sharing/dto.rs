@@ -0,0 +1,102 @@
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use crate::files::dto::FileDto;
+use crate::folders::dto::FolderDto;
+use crate::schema::user_shares;
+
+// Values of `files.access_level`
+pub const ACCESS_LEVEL_PRIVATE: i32 = 0;
+pub const ACCESS_LEVEL_SHARED: i32 = 2;
+
+///
+/// Access a user has to a file or folder, ordered from least to most
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Permission {
+    Read = 1,
+    Write = 2,
+    Owner = 3,
+}
+
+impl Permission {
+    // Permissions that can be granted to other users, as stored in `user_shares.permission`
+    pub fn from_granted(permission: i32) -> Option<Permission> {
+        match permission {
+            1 => Some(Permission::Read),
+            2 => Some(Permission::Write),
+            _ => None,
+        }
+    }
+
+    // Permissions of the roles in a group, as stored in `group_members.role`
+    pub fn from_role(role: i32) -> Option<Permission> {
+        match role {
+            1 => Some(Permission::Read),
+            2 => Some(Permission::Write),
+            3 => Some(Permission::Owner),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+#[derive(Queryable, Selectable, Insertable)]
+#[diesel(table_name = user_shares)]
+#[serde(rename_all = "camelCase")]
+pub struct UserShareDto {
+    // The unique identifier for the share
+    pub id: String,
+    // The ID of the user sharing the item
+    pub owner_id: i32,
+    // The ID of the user the item is shared with
+    pub user_id: i32,
+    // Either the shared file or the shared folder is set
+    pub file_id: Option<String>,
+    pub folder_id: Option<String>,
+    // 1: read, 2: read-write
+    pub permission: i32,
+    pub created_at: Option<chrono::NaiveDateTime>,
+    pub updated_at: Option<chrono::NaiveDateTime>,
+    pub created_by: i32,
+    pub updated_by: i32,
+    pub active: bool,
+}
+
+#[derive(Debug, Clone, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct CreateUserShareDto {
+    pub file_id: Option<String>,
+    pub folder_id: Option<String>,
+    // The user to share with
+    pub username: String,
+    // 1: read, 2: read-write
+    pub permission: i32,
+}
+
+///
+/// Files and folders other users have shared with the current user
+///
+#[derive(Debug, Clone, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct SharedWithMeDto {
+    pub files: Vec<SharedFileDto>,
+    pub folders: Vec<SharedFolderDto>,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct SharedFileDto {
+    pub permission: i32,
+    // Username of the owner
+    pub shared_by: String,
+    pub file: FileDto,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct SharedFolderDto {
+    pub permission: i32,
+    // Username of the owner
+    pub shared_by: String,
+    pub folder: FolderDto,
+}
@@ -0,0 +1,171 @@
+pub mod dto;
+pub mod service;
+
+use actix_web::{
+    delete, get, post, web, Error, HttpResponse,
+};
+
+use log::info;
+
+use crate::auth::jwt_auth;
+use crate::auth::service::get_user_by_username;
+use crate::files::service::get_file;
+use crate::folders::service::get_folder;
+use crate::shared::common::ServiceError;
+use crate::shared::common::AppState;
+use crate::shared::dto::CreateResponseDto;
+use service::{create_user_share, get_user_shares, revoke_user_share, get_shared_with_me};
+
+use dto::{UserShareDto, CreateUserShareDto, SharedWithMeDto, Permission};
+
+///
+/// Gets all shares the user has granted to other users
+///
+#[utoipa::path(
+    get,
+    tag = "Sharing",
+    path = "/api/sharing",
+    responses(
+        (status = 200, description = "Successfully retrieved the granted shares", body = [Vec<UserShareDto>])
+    )
+)]
+#[get("")]
+pub async fn get_user_shares_handler(
+    app: web::Data<AppState>,
+    jwt: jwt_auth::JwtMiddleware,
+) -> Result<HttpResponse, Error> {
+    let mut conn = app
+        .get_connection()
+        .map_err(|err| ServiceError::NotFound(err.to_string()))?;
+
+    match get_user_shares(&mut conn, jwt.user_id) {
+        Ok(shares) => Ok(HttpResponse::Ok().json(shares)),
+        Err(err) => Err(ServiceError::NotFound(err.to_string()).into()),
+    }
+}
+
+///
+/// Gets the files and folders other users have shared with the user
+///
+#[utoipa::path(
+    get,
+    tag = "Sharing",
+    path = "/api/sharing/shared-with-me",
+    responses(
+        (status = 200, description = "Successfully retrieved the items shared with the user", body = [SharedWithMeDto])
+    )
+)]
+#[get("/shared-with-me")]
+pub async fn get_shared_with_me_handler(
+    app: web::Data<AppState>,
+    jwt: jwt_auth::JwtMiddleware,
+) -> Result<HttpResponse, Error> {
+    let mut conn = app
+        .get_connection()
+        .map_err(|err| ServiceError::NotFound(err.to_string()))?;
+
+    match get_shared_with_me(&mut conn, jwt.user_id) {
+        Ok(shared) => Ok(HttpResponse::Ok().json(shared)),
+        Err(err) => Err(ServiceError::NotFound(err.to_string()).into()),
+    }
+}
+
+///
+/// Shares a file or folder with another user
+///
+/// Everything inside a shared folder is shared with the same permission, 1 grants read and
+/// 2 read-write access. Sharing an item with the same user again changes the permission
+///
+#[utoipa::path(
+    post,
+    tag = "Sharing",
+    path = "/api/sharing",
+    request_body = CreateUserShareDto,
+    responses(
+        (status = 201, description = "Successfully shared an item", body = [CreateResponseDto])
+    )
+)]
+#[post("")]
+pub async fn create_user_share_handler(
+    app: web::Data<AppState>,
+    jwt: jwt_auth::JwtMiddleware,
+    data: web::Json<CreateUserShareDto>,
+) -> Result<HttpResponse, Error> {
+    let user_id = jwt.user_id;
+    let share = data.into_inner();
+
+    let mut conn = app
+        .get_connection()
+        .map_err(|err| ServiceError::NotFound(err.to_string()))?;
+
+    match (&share.file_id, &share.folder_id) {
+        (Some(file_id), None) => {
+            let file = get_file(&mut conn, file_id, user_id, Permission::Owner).map_err(|err| ServiceError::NotFound(err.to_string()))?;
+            if file.group_id.is_some() {
+                return Err(ServiceError::BadRequest("Group files are shared by adding members to the group".to_string()).into());
+            }
+        }
+        (None, Some(folder_id)) => {
+            get_folder(&mut conn, folder_id, user_id).map_err(|err| ServiceError::NotFound(err.to_string()))?;
+        }
+        _ => return Err(ServiceError::BadRequest("Either fileId or folderId must be set".to_string()).into()),
+    }
+    if Permission::from_granted(share.permission).is_none() {
+        return Err(ServiceError::BadRequest(format!("Invalid permission {}", share.permission)).into());
+    }
+
+    let grantee = get_user_by_username(&mut conn, &share.username).map_err(|err| ServiceError::NotFound(err.to_string()))?;
+    let grantee_id = grantee.id.unwrap_or_default();
+    if grantee_id == user_id {
+        return Err(ServiceError::BadRequest("Items can not be shared with their owner".to_string()).into());
+    }
+
+    match create_user_share(&mut conn, share, grantee_id, user_id) {
+        Ok(share_id) => {
+            info!("Created share: {} from user: {} to user: {}", share_id, user_id, grantee_id);
+            Ok(HttpResponse::Created().json(CreateResponseDto::ok_with_id(share_id)))
+        }
+        Err(err) => Err(ServiceError::InternalServerError(err.to_string()).into()),
+    }
+}
+
+///
+/// Stops sharing an item with a user
+///
+#[utoipa::path(
+    delete,
+    tag = "Sharing",
+    path = "/api/sharing/{share_id}",
+    responses(
+        (status = 204, description = "Successfully revoked a share")
+    )
+)]
+#[delete("/{share_id}")]
+pub async fn revoke_user_share_handler(
+    app: web::Data<AppState>,
+    jwt: jwt_auth::JwtMiddleware,
+    path: web::Path<String>,
+) -> Result<HttpResponse, Error> {
+    let share_id = path.to_string();
+
+    let mut conn = app
+        .get_connection()
+        .map_err(|err| ServiceError::NotFound(err.to_string()))?;
+
+    match revoke_user_share(&mut conn, &share_id, jwt.user_id) {
+        Ok(0) => Err(ServiceError::NotFound(share_id).into()),
+        Ok(_) => Ok(HttpResponse::NoContent().finish()),
+        Err(err) => Err(ServiceError::InternalServerError(err.to_string()).into()),
+    }
+}
+
+pub fn config(conf: &mut web::ServiceConfig) {
+    let scope = web::scope("/sharing")
+            .service(get_user_shares_handler)
+            .service(get_shared_with_me_handler)
+            .service(create_user_share_handler)
+            .service(revoke_user_share_handler)
+            ;
+
+    conf.service(scope);
+}
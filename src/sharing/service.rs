@@ -0,0 +1,254 @@
+use crate::shared::common::DbError;
+use super::dto::{UserShareDto, CreateUserShareDto, SharedWithMeDto, SharedFileDto, SharedFolderDto, Permission};
+use super::dto::{ACCESS_LEVEL_PRIVATE, ACCESS_LEVEL_SHARED};
+use crate::files::dto::FileDto;
+use crate::folders::dto::FolderDto;
+use crate::folders::service::get_folder_ancestors;
+use crate::groups::dto::Owner;
+use crate::groups::service::{get_group_by_folder, get_group_permission, get_member_group};
+use diesel::prelude::*;
+use uuid::Uuid;
+
+use crate::schema::{file_folders, files, user_shares, users};
+
+///
+/// Shares a file or folder with another user, sharing the same item again changes the permission
+///
+pub fn create_user_share(conn: &mut SqliteConnection, share: CreateUserShareDto, user_id: i32, owner_id: i32) -> Result<String, DbError> {
+    conn.transaction(|conn| {
+        let mut query = user_shares::table
+            .filter(user_shares::owner_id.eq(owner_id))
+            .filter(user_shares::user_id.eq(user_id))
+            .filter(user_shares::active.eq(true))
+            .into_boxed();
+        query = match (&share.file_id, &share.folder_id) {
+            (Some(file_id), _) => query.filter(user_shares::file_id.eq(file_id)),
+            (_, Some(folder_id)) => query.filter(user_shares::folder_id.eq(folder_id)),
+            _ => return Err("Either a file or a folder must be shared".into()),
+        };
+        let now = chrono::Utc::now().naive_utc();
+
+        let share_id = match query.select(user_shares::id).first::<String>(conn).optional()? {
+            Some(share_id) => {
+                diesel::update(user_shares::table.filter(user_shares::id.eq(&share_id)))
+                    .set((
+                        user_shares::permission.eq(share.permission),
+                        user_shares::updated_by.eq(owner_id),
+                        user_shares::updated_at.eq(now)))
+                    .execute(conn)?;
+                share_id
+            }
+            None => {
+                let user_share = UserShareDto {
+                    id: Uuid::new_v4().to_string(),
+                    owner_id,
+                    user_id,
+                    file_id: share.file_id.clone(),
+                    folder_id: share.folder_id.clone(),
+                    permission: share.permission,
+                    created_at: None,
+                    updated_at: None,
+                    created_by: owner_id,
+                    updated_by: owner_id,
+                    active: true,
+                };
+                diesel::insert_into(user_shares::table)
+                    .values(&user_share)
+                    .execute(conn)?;
+                user_share.id
+            }
+        };
+
+        // A private file becomes shared, public files stay public
+        if let Some(file_id) = &share.file_id {
+            diesel::update(files::table.filter(files::id.eq(file_id).and(files::access_level.eq(ACCESS_LEVEL_PRIVATE))))
+                .set(files::access_level.eq(ACCESS_LEVEL_SHARED))
+                .execute(conn)?;
+        }
+
+        Ok(share_id)
+    })
+}
+
+pub fn get_user_shares(conn: &mut SqliteConnection, owner_id: i32) -> Result<Vec<UserShareDto>, DbError> {
+    Ok(user_shares::table
+        .filter(user_shares::owner_id.eq(owner_id))
+        .filter(user_shares::active.eq(true))
+        .load::<UserShareDto>(conn)?)
+}
+
+///
+/// Stops sharing an item, a file that is no longer shared with anyone becomes private again
+///
+pub fn revoke_user_share(conn: &mut SqliteConnection, share_id: &str, owner_id: i32) -> Result<usize, DbError> {
+    conn.transaction(|conn| {
+        let share = user_shares::table
+            .filter(user_shares::id.eq(share_id))
+            .filter(user_shares::owner_id.eq(owner_id))
+            .filter(user_shares::active.eq(true))
+            .first::<UserShareDto>(conn)
+            .optional()?;
+        let Some(share) = share else {
+            return Ok(0);
+        };
+
+        let revoked = diesel::update(user_shares::table.filter(user_shares::id.eq(share_id)))
+            .set((
+                user_shares::active.eq(false),
+                user_shares::updated_by.eq(owner_id),
+                user_shares::updated_at.eq(chrono::Utc::now().naive_utc())))
+            .execute(conn)?;
+
+        if let Some(file_id) = share.file_id {
+            let remaining: i64 = user_shares::table
+                .filter(user_shares::file_id.eq(&file_id))
+                .filter(user_shares::active.eq(true))
+                .count()
+                .get_result(conn)?;
+            if remaining == 0 {
+                diesel::update(files::table.filter(files::id.eq(&file_id).and(files::access_level.eq(ACCESS_LEVEL_SHARED))))
+                    .set(files::access_level.eq(ACCESS_LEVEL_PRIVATE))
+                    .execute(conn)?;
+            }
+        }
+
+        Ok(revoked)
+    })
+}
+
+///
+/// Files and folders shared directly with the user, the contents of shared folders are not listed
+///
+pub fn get_shared_with_me(conn: &mut SqliteConnection, user_id: i32) -> Result<SharedWithMeDto, DbError> {
+    let files = user_shares::table
+        .inner_join(files::table.on(files::id.nullable().eq(user_shares::file_id)))
+        .inner_join(users::table.on(users::id.eq(user_shares::owner_id.nullable())))
+        .filter(user_shares::user_id.eq(user_id))
+        .filter(user_shares::active.eq(true))
+        .filter(files::active.eq(true))
+        .select((user_shares::permission, users::username, FileDto::as_select()))
+        .load::<(i32, String, FileDto)>(conn)?
+        .into_iter()
+        .map(|(permission, shared_by, file)| SharedFileDto { permission, shared_by, file })
+        .collect();
+
+    let folders = user_shares::table
+        .inner_join(file_folders::table.on(file_folders::id.nullable().eq(user_shares::folder_id)))
+        .inner_join(users::table.on(users::id.eq(user_shares::owner_id.nullable())))
+        .filter(user_shares::user_id.eq(user_id))
+        .filter(user_shares::active.eq(true))
+        .filter(file_folders::active.eq(true))
+        .select((user_shares::permission, users::username, FolderDto::as_select()))
+        .load::<(i32, String, FolderDto)>(conn)?
+        .into_iter()
+        .map(|(permission, shared_by, folder)| SharedFolderDto { permission, shared_by, folder })
+        .collect();
+
+    Ok(SharedWithMeDto { files, folders })
+}
+
+///
+/// The access the user has to a file, either as its owner or through a share of the file or of a folder above it
+///
+/// Access to group files comes from the user's role in the group alone
+///
+pub fn get_file_permission(conn: &mut SqliteConnection, file: &FileDto, user_id: i32) -> Result<Option<Permission>, DbError> {
+    if let Some(group_id) = &file.group_id {
+        return get_group_permission(conn, group_id, user_id);
+    }
+    if file.owner_id == user_id {
+        return Ok(Some(Permission::Owner));
+    }
+
+    let folders = get_folder_ancestors(conn, &file.folder_id, file.owner_id)?;
+    let granted: Option<i32> = user_shares::table
+        .filter(user_shares::user_id.eq(user_id))
+        .filter(user_shares::owner_id.eq(file.owner_id))
+        .filter(user_shares::active.eq(true))
+        .filter(user_shares::file_id.eq(&file.id).or(user_shares::folder_id.eq_any(&folders)))
+        .select(diesel::dsl::max(user_shares::permission))
+        .first(conn)?;
+    Ok(granted.and_then(Permission::from_granted))
+}
+
+///
+/// The access the user has to a folder, either as its owner or through a share of the folder or of a folder above it
+///
+/// Access to group folders comes from the user's role in the group alone
+///
+pub fn get_folder_permission(conn: &mut SqliteConnection, folder: &FolderDto, user_id: i32) -> Result<Option<Permission>, DbError> {
+    if let Some(group_id) = &folder.group_id {
+        return get_group_permission(conn, group_id, user_id);
+    }
+    if folder.owner_id == user_id {
+        return Ok(Some(Permission::Owner));
+    }
+
+    let folders = get_folder_ancestors(conn, &folder.id, folder.owner_id)?;
+    let granted: Option<i32> = user_shares::table
+        .filter(user_shares::user_id.eq(user_id))
+        .filter(user_shares::owner_id.eq(folder.owner_id))
+        .filter(user_shares::active.eq(true))
+        .filter(user_shares::folder_id.eq_any(&folders))
+        .select(diesel::dsl::max(user_shares::permission))
+        .first(conn)?;
+    Ok(granted.and_then(Permission::from_granted))
+}
+
+///
+/// Ids of the owner's folders shared with the user
+///
+pub fn get_shared_folder_ids(conn: &mut SqliteConnection, user_id: i32, owner_id: i32) -> Result<Vec<String>, DbError> {
+    Ok(user_shares::table
+        .filter(user_shares::user_id.eq(user_id))
+        .filter(user_shares::owner_id.eq(owner_id))
+        .filter(user_shares::active.eq(true))
+        .filter(user_shares::folder_id.is_not_null())
+        .select(user_shares::folder_id.assume_not_null())
+        .load::<String>(conn)?)
+}
+
+///
+/// Loads a folder of any owner, provided the user has at least `permission` on it
+///
+pub fn get_accessible_folder(conn: &mut SqliteConnection, folder_id: &str, user_id: i32, permission: Permission) -> Result<FolderDto, DbError> {
+    let folder = file_folders::table
+        .filter(file_folders::id.eq(folder_id))
+        .filter(file_folders::active.eq(true))
+        .first::<FolderDto>(conn)
+        .map_err(|err| format!("Error loading folder folder_id: {}, user_id: {}, Error: {}", folder_id, user_id, err))?;
+
+    match get_folder_permission(conn, &folder, user_id)? {
+        Some(granted) if granted >= permission => Ok(folder),
+        _ => Err(format!("User {} has no {:?} access to folder_id: {}", user_id, permission, folder_id).into()),
+    }
+}
+
+///
+/// Owner of the items in a folder the user has at least `permission` on, their own, a group's
+/// or one shared with them
+///
+pub fn get_folder_owner(conn: &mut SqliteConnection, folder_id: &str, user_id: i32, permission: Permission) -> Result<Owner, DbError> {
+    if let Some(group) = get_group_by_folder(conn, folder_id)? {
+        return Ok(Owner::Group(get_member_group(conn, &group.id, user_id, permission)?.id));
+    }
+
+    // Users' root folders have no file_folders row, the only one the user can use is their own
+    let has_row = file_folders::table
+        .filter(file_folders::id.eq(folder_id))
+        .count()
+        .get_result::<i64>(conn)? > 0;
+    if !has_row {
+        let is_own_root = users::table
+            .filter(users::id.eq(user_id))
+            .filter(users::folder_id.eq(folder_id))
+            .count()
+            .get_result::<i64>(conn)? == 1;
+        return match is_own_root {
+            true => Ok(Owner::User(user_id)),
+            false => Err(format!("Folder not found folder_id: {}, user_id: {}", folder_id, user_id).into()),
+        };
+    }
+
+    Ok(Owner::from(&get_accessible_folder(conn, folder_id, user_id, permission)?))
+}
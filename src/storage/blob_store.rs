@@ -0,0 +1,113 @@
+use std::io::ErrorKind;
+use std::sync::Arc;
+
+use diesel::SqliteConnection;
+use futures_util::lock::Mutex;
+use uuid::Uuid;
+
+use crate::previews::delete_thumbnails;
+use crate::shared::common::{ByteStream, DbError, StorageService};
+use crate::shared::digest::digest_stream;
+use super::dto::BlobDto;
+use super::service::{add_blob_reference, create_blob, delete_unreferenced_blob, get_blob, get_unreferenced_blobs};
+
+// Blobs live next to the user folders, which are named by UUID so they never clash
+const BLOB_PATH: &str = ".blobs";
+// Contents are written here until their hash is known
+const INCOMING_PATH: &str = ".blobs/.incoming";
+
+///
+/// Content addressed layer on top of a storage service
+///
+/// Every distinct content is stored once, named after its SHA-256, and reference counted in
+/// the `blobs` table. Each file version holds one reference, blobs that are no longer
+/// referenced are removed by `collect_garbage`
+///
+pub struct BlobStore {
+    storage: Arc<dyn StorageService>,
+    // Serialises new references with garbage collection so a blob is never deleted while it is reused
+    lock: Mutex<()>,
+}
+
+impl BlobStore {
+    pub fn new(storage: Arc<dyn StorageService>) -> BlobStore {
+        BlobStore {
+            storage,
+            lock: Mutex::new(()),
+        }
+    }
+
+    ///
+    /// Path and name of a blob in storage, blobs are spread over folders named after the first hash byte
+    ///
+    pub fn location(hash: &str) -> (String, String) {
+        (format!("{}/{}", BLOB_PATH, &hash[..2]), hash.to_string())
+    }
+
+    ///
+    /// Stores `input` and returns its blob with a reference added for the caller
+    ///
+    /// Contents that are already stored are not written again, only their reference count changes
+    ///
+    pub async fn put(&self, conn: &mut SqliteConnection, input: ByteStream) -> Result<BlobDto, DbError> {
+        let incoming = Uuid::new_v4().to_string();
+        let (stream, digest) = digest_stream(input);
+
+        self.storage.create_folder(INCOMING_PATH)?;
+        if let Err(err) = self.storage.save_file(INCOMING_PATH, &incoming, stream).await {
+            let _ = self.storage.delete_file(INCOMING_PATH, &incoming).await;
+            return Err(Box::new(err));
+        }
+        let (hash, size) = {
+            let digest = digest.borrow();
+            (digest.checksum(), digest.size() as i64)
+        };
+
+        let _guard = self.lock.lock().await;
+        if add_blob_reference(conn, &hash)? > 0 {
+            log::info!("Blob {} already stored, discarding the duplicate", hash);
+            self.storage.delete_file(INCOMING_PATH, &incoming).await?;
+        } else {
+            let (path, name) = BlobStore::location(&hash);
+            self.storage.move_file(INCOMING_PATH, &incoming, &path, &name).await?;
+            create_blob(conn, &BlobDto {
+                hash: hash.clone(),
+                size,
+                ref_count: 1,
+                created_at: None,
+            })?;
+        }
+
+        get_blob(conn, &hash)
+    }
+
+    ///
+    /// Adds a reference to a blob that is already stored
+    ///
+    pub async fn add_reference(&self, conn: &mut SqliteConnection, hash: &str) -> Result<BlobDto, DbError> {
+        let _guard = self.lock.lock().await;
+        match add_blob_reference(conn, hash)? {
+            0 => Err(format!("Blob {} not found", hash).into()),
+            _ => get_blob(conn, hash),
+        }
+    }
+
+    ///
+    /// Deletes every blob without references, returns the number of blobs removed
+    ///
+    pub async fn collect_garbage(&self, conn: &mut SqliteConnection) -> Result<usize, DbError> {
+        let _guard = self.lock.lock().await;
+        let mut collected = 0;
+
+        for blob in get_unreferenced_blobs(conn)? {
+            let (path, name) = BlobStore::location(&blob.hash);
+            delete_thumbnails(self.storage.as_ref(), &path, &name).await?;
+            match self.storage.delete_file(&path, &name).await {
+                Err(err) if err.kind() != ErrorKind::NotFound => return Err(Box::new(err)),
+                _ => collected += delete_unreferenced_blob(conn, &blob.hash)?,
+            }
+        }
+
+        Ok(collected)
+    }
+}
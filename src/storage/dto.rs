@@ -0,0 +1,15 @@
+use serde::{Deserialize, Serialize};
+use crate::schema::blobs;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Queryable, Selectable, Insertable)]
+#[diesel(table_name = blobs)]
+#[serde(rename_all = "camelCase")]
+pub struct BlobDto {
+    // Hex encoded SHA-256 of the contents, also the name of the blob in storage
+    pub hash: String,
+    pub size: i64,
+    // Number of file versions using the blob, it is garbage collected once this drops to zero
+    pub ref_count: i32,
+    pub created_at: Option<chrono::NaiveDateTime>,
+}
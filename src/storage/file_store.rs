@@ -0,0 +1,101 @@
+use futures_util::TryStreamExt;
+use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
+use tokio_util::io::ReaderStream;
+
+use crate::shared::common::{ByteStream, StorageService};
+
+use std::{fs, io::{Error, SeekFrom}};
+
+use async_trait::async_trait;
+
+#[derive(Clone)]
+pub struct FileStore {
+    base_path: String
+}
+
+impl FileStore {
+    pub fn new(base_path: String) -> FileStore {
+        FileStore {base_path}
+    }
+
+    // Names of the entries directly under `path` whose type matches `is_dir`
+    async fn list_entries(&self, path: &str, is_dir: bool) -> Result<Vec<String>, Error> {
+        let mut names = Vec::new();
+        let mut entries = tokio::fs::read_dir(format!("{}/{}", self.base_path, path)).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            if entry.file_type().await?.is_dir() == is_dir {
+                names.push(entry.file_name().to_string_lossy().to_string());
+            }
+        }
+        Ok(names)
+    }
+}
+
+#[async_trait(?Send)]
+impl StorageService for FileStore {
+    async fn save_file(&self, path: &str, name: &str, mut input: ByteStream) -> Result<(), Error> {
+        let mut file = tokio::fs::File::create(format!("{}/{}/{}", self.base_path, path, name)).await?;
+
+        // The input is a stream of Bytes objects
+        while let Some(chunk) = input.try_next().await? {
+            file.write_all(&chunk).await?;
+        }
+        file.flush().await?;
+        log::info!("Finished writing file {}", name);
+
+        Ok(())
+    }
+
+    async fn retrieve_file(&self, path: &str, name: &str) -> Result<ByteStream, Error> {
+        let file = tokio::fs::File::open(format!("{}/{}/{}", self.base_path, path, name)).await?;
+        Ok(Box::pin(ReaderStream::new(file)))
+    }
+
+    async fn retrieve_file_range(&self, path: &str, name: &str, start: u64, length: u64) -> Result<ByteStream, Error> {
+        let mut file = tokio::fs::File::open(format!("{}/{}/{}", self.base_path, path, name)).await?;
+        file.seek(SeekFrom::Start(start)).await?;
+        Ok(Box::pin(ReaderStream::new(file.take(length))))
+    }
+
+    async fn file_size(&self, path: &str, name: &str) -> Result<u64, Error> {
+        Ok(tokio::fs::metadata(format!("{}/{}/{}", self.base_path, path, name)).await?.len())
+    }
+
+    async fn delete_file(&self, path: &str, name: &str) -> Result<(), Error> {
+        tokio::fs::remove_file(format!("{}/{}/{}", self.base_path, path, name)).await
+    }
+
+    async fn move_file(&self, from_path: &str, from_name: &str, to_path: &str, to_name: &str) -> Result<(), Error> {
+        tokio::fs::create_dir_all(format!("{}/{}", self.base_path, to_path)).await?;
+        tokio::fs::rename(
+            format!("{}/{}/{}", self.base_path, from_path, from_name),
+            format!("{}/{}/{}", self.base_path, to_path, to_name),
+        ).await
+    }
+
+    async fn copy_file(&self, from_path: &str, from_name: &str, to_path: &str, to_name: &str) -> Result<(), Error> {
+        tokio::fs::create_dir_all(format!("{}/{}", self.base_path, to_path)).await?;
+        tokio::fs::copy(
+            format!("{}/{}/{}", self.base_path, from_path, from_name),
+            format!("{}/{}/{}", self.base_path, to_path, to_name),
+        ).await?;
+        Ok(())
+    }
+
+    fn create_folder(&self, path: &str) -> Result<(), Error> {
+        fs::create_dir_all(format!("{}/{}",self.base_path, path))
+    }
+
+    async fn delete_folder(&self, path: &str) -> Result<(), Error> {
+        tokio::fs::remove_dir_all(format!("{}/{}", self.base_path, path)).await
+    }
+
+    async fn list_file_names(&self, path: &str) -> Result<Vec<String>, Error> {
+        self.list_entries(path, false).await
+    }
+
+    async fn list_folder_names(&self, path: &str) -> Result<Vec<String>, Error> {
+        self.list_entries(path, true).await
+    }
+
+}
@@ -0,0 +1,208 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::{Error, ErrorKind};
+use std::sync::{Arc, RwLock};
+
+use actix_web::web::Bytes;
+use async_trait::async_trait;
+use futures_util::TryStreamExt;
+
+use crate::shared::common::{ByteStream, StorageService};
+
+// Size of the chunks handed out when streaming a stored blob back
+const CHUNK_SIZE: usize = 64 * 1024;
+
+///
+/// Keeps blobs in an in-process map, nothing is written to disk
+///
+/// Intended for tests and ephemeral deployments, all data is lost when the process exits.
+/// Blobs are keyed as `{path}/{name}`, mirroring the layout used by `FileStore`
+///
+#[derive(Clone, Default)]
+pub struct MemoryStore {
+    files: Arc<RwLock<BTreeMap<String, Bytes>>>,
+    folders: Arc<RwLock<BTreeSet<String>>>,
+}
+
+impl MemoryStore {
+    pub fn new() -> MemoryStore {
+        MemoryStore::default()
+    }
+
+    fn key(path: &str, name: &str) -> String {
+        format!("{}/{}", path.trim_end_matches('/'), name)
+    }
+
+    fn prefix(path: &str) -> String {
+        format!("{}/", path.trim_end_matches('/'))
+    }
+
+    fn lock_error<T>(_: T) -> Error {
+        Error::other("Memory store lock poisoned")
+    }
+
+    fn get(&self, path: &str, name: &str) -> Result<Bytes, Error> {
+        let key = MemoryStore::key(path, name);
+        self.files
+            .read()
+            .map_err(MemoryStore::lock_error)?
+            .get(&key)
+            .cloned()
+            .ok_or_else(|| Error::new(ErrorKind::NotFound, format!("File {} not found", key)))
+    }
+
+    // Hands the blob out in CHUNK_SIZE pieces, like a file read from disk would be
+    fn chunked(data: Bytes) -> ByteStream {
+        let chunks: Vec<Result<Bytes, Error>> = (0..data.len())
+            .step_by(CHUNK_SIZE)
+            .map(|start| Ok(data.slice(start..data.len().min(start + CHUNK_SIZE))))
+            .collect();
+
+        Box::pin(futures_util::stream::iter(chunks))
+    }
+
+    // Direct children of `path` found in `keys`
+    fn children<'a>(keys: impl Iterator<Item = &'a String>, path: &str) -> Vec<String> {
+        let prefix = MemoryStore::prefix(path);
+        keys.filter_map(|key| key.strip_prefix(&prefix))
+            .filter(|name| !name.is_empty() && !name.contains('/'))
+            .map(|name| name.to_string())
+            .collect()
+    }
+}
+
+#[async_trait(?Send)]
+impl StorageService for MemoryStore {
+    async fn save_file(&self, path: &str, name: &str, mut input: ByteStream) -> Result<(), Error> {
+        let mut buffer: Vec<u8> = Vec::new();
+        while let Some(chunk) = input.try_next().await? {
+            buffer.extend_from_slice(&chunk);
+        }
+
+        let key = MemoryStore::key(path, name);
+        self.files.write().map_err(MemoryStore::lock_error)?.insert(key, Bytes::from(buffer));
+        log::info!("Finished writing file {}", name);
+
+        Ok(())
+    }
+
+    async fn retrieve_file(&self, path: &str, name: &str) -> Result<ByteStream, Error> {
+        Ok(MemoryStore::chunked(self.get(path, name)?))
+    }
+
+    async fn retrieve_file_range(&self, path: &str, name: &str, start: u64, length: u64) -> Result<ByteStream, Error> {
+        let data = self.get(path, name)?;
+        let start = (start as usize).min(data.len());
+        let end = start.saturating_add(length as usize).min(data.len());
+        Ok(MemoryStore::chunked(data.slice(start..end)))
+    }
+
+    async fn file_size(&self, path: &str, name: &str) -> Result<u64, Error> {
+        Ok(self.get(path, name)?.len() as u64)
+    }
+
+    async fn delete_file(&self, path: &str, name: &str) -> Result<(), Error> {
+        let key = MemoryStore::key(path, name);
+        match self.files.write().map_err(MemoryStore::lock_error)?.remove(&key) {
+            Some(_) => Ok(()),
+            None => Err(Error::new(ErrorKind::NotFound, format!("File {} not found", key))),
+        }
+    }
+
+    async fn move_file(&self, from_path: &str, from_name: &str, to_path: &str, to_name: &str) -> Result<(), Error> {
+        let from = MemoryStore::key(from_path, from_name);
+        let mut files = self.files.write().map_err(MemoryStore::lock_error)?;
+        let data = files
+            .remove(&from)
+            .ok_or_else(|| Error::new(ErrorKind::NotFound, format!("File {} not found", from)))?;
+        files.insert(MemoryStore::key(to_path, to_name), data);
+        Ok(())
+    }
+
+    async fn copy_file(&self, from_path: &str, from_name: &str, to_path: &str, to_name: &str) -> Result<(), Error> {
+        let data = self.get(from_path, from_name)?;
+        self.files.write().map_err(MemoryStore::lock_error)?.insert(MemoryStore::key(to_path, to_name), data);
+        Ok(())
+    }
+
+    fn create_folder(&self, path: &str) -> Result<(), Error> {
+        let mut folders = self.folders.write().map_err(MemoryStore::lock_error)?;
+
+        // Behave like create_dir_all and register every ancestor as well
+        let mut current = String::new();
+        for part in path.split('/').filter(|part| !part.is_empty()) {
+            if !current.is_empty() {
+                current.push('/');
+            }
+            current.push_str(part);
+            folders.insert(current.clone());
+        }
+
+        Ok(())
+    }
+
+    async fn delete_folder(&self, path: &str) -> Result<(), Error> {
+        let path = path.trim_end_matches('/');
+        let prefix = MemoryStore::prefix(path);
+        self.files.write().map_err(MemoryStore::lock_error)?.retain(|key, _| !key.starts_with(&prefix));
+        self.folders.write().map_err(MemoryStore::lock_error)?.retain(|folder| folder != path && !folder.starts_with(&prefix));
+        Ok(())
+    }
+
+    async fn list_file_names(&self, path: &str) -> Result<Vec<String>, Error> {
+        let files = self.files.read().map_err(MemoryStore::lock_error)?;
+        Ok(MemoryStore::children(files.keys(), path))
+    }
+
+    async fn list_folder_names(&self, path: &str) -> Result<Vec<String>, Error> {
+        let folders = self.folders.read().map_err(MemoryStore::lock_error)?;
+        Ok(MemoryStore::children(folders.iter(), path))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stream_of(data: &'static [u8]) -> ByteStream {
+        Box::pin(futures_util::stream::once(async move { Ok::<_, Error>(Bytes::from_static(data)) }))
+    }
+
+    async fn read_all(stream: ByteStream) -> Vec<u8> {
+        stream.try_fold(Vec::new(), |mut data, chunk| async move {
+            data.extend_from_slice(&chunk);
+            Ok(data)
+        }).await.unwrap()
+    }
+
+    #[actix_web::test]
+    async fn memory_store_round_trips() {
+        let store = MemoryStore::new();
+        store.create_folder("root/nested").unwrap();
+        store.save_file("root", "a.txt", stream_of(b"hello world")).await.unwrap();
+        store.save_file("root/nested", "b.txt", stream_of(b"nested")).await.unwrap();
+
+        assert_eq!(read_all(store.retrieve_file("root", "a.txt").await.unwrap()).await, b"hello world");
+        assert_eq!(read_all(store.retrieve_file_range("root", "a.txt", 6, 5).await.unwrap()).await, b"world");
+        assert_eq!(store.file_size("root", "a.txt").await.unwrap(), 11);
+        assert_eq!(store.list_file_names("root").await.unwrap(), vec!["a.txt".to_string()]);
+        assert_eq!(store.list_folder_names("root").await.unwrap(), vec!["nested".to_string()]);
+
+        store.copy_file("root", "a.txt", "root", "copy.txt").await.unwrap();
+        assert_eq!(read_all(store.retrieve_file("root", "copy.txt").await.unwrap()).await, b"hello world");
+        assert_eq!(store.file_size("root", "a.txt").await.unwrap(), 11);
+
+        store.move_file("root", "copy.txt", "root/nested", "moved.txt").await.unwrap();
+        assert_eq!(store.retrieve_file("root", "copy.txt").await.err().unwrap().kind(), ErrorKind::NotFound);
+        assert_eq!(read_all(store.retrieve_file("root/nested", "moved.txt").await.unwrap()).await, b"hello world");
+
+        store.delete_file("root", "a.txt").await.unwrap();
+        assert_eq!(store.delete_file("root", "a.txt").await.err().unwrap().kind(), ErrorKind::NotFound);
+
+        // Removes everything below the folder but leaves look-alike siblings alone
+        store.save_file("root-sibling", "c.txt", stream_of(b"sibling")).await.unwrap();
+        store.delete_folder("root").await.unwrap();
+        assert!(store.list_file_names("root/nested").await.unwrap().is_empty());
+        assert!(store.list_folder_names("root").await.unwrap().is_empty());
+        assert_eq!(store.file_size("root-sibling", "c.txt").await.unwrap(), 7);
+    }
+}
@@ -0,0 +1,46 @@
+pub mod blob_store;
+pub mod dto;
+pub mod file_store;
+pub mod memory_store;
+pub mod s3_store;
+#[cfg(test)]
+mod s3_stand_in;
+pub mod service;
+
+use std::io::{Error, ErrorKind};
+use std::sync::Arc;
+
+use crate::shared::common::{Config, StorageService};
+use file_store::FileStore;
+use memory_store::MemoryStore;
+use s3_store::S3Store;
+
+///
+/// Creates the storage service selected by `Config::storage_service`
+///
+/// Supported values:
+///  * `file` - stores blobs on the local disk under `FILE_STORE_BASE_PATH`
+///  * `s3` - stores blobs in the S3 compatible bucket `S3_BUCKET`, set `S3_ENDPOINT` for MinIO
+///  * `memory` - keeps blobs in process memory, for tests and ephemeral deployments
+///
+pub fn create_storage_service(config: &Config) -> Result<Arc<dyn StorageService>, Error> {
+    match config.storage_service.as_str() {
+        "file" => {
+            let base_path = std::env::var("FILE_STORE_BASE_PATH").expect("FILE_STORE_BASE_PATH must be set");
+            Ok(Arc::new(FileStore::new(base_path)))
+        }
+        "s3" => {
+            let bucket = std::env::var("S3_BUCKET").expect("S3_BUCKET must be set");
+            let region = std::env::var("S3_REGION").unwrap_or("us-east-1".to_string());
+            let endpoint = std::env::var("S3_ENDPOINT").ok();
+            let access_key_id = std::env::var("S3_ACCESS_KEY_ID").expect("S3_ACCESS_KEY_ID must be set");
+            let secret_access_key = std::env::var("S3_SECRET_ACCESS_KEY").expect("S3_SECRET_ACCESS_KEY must be set");
+            Ok(Arc::new(S3Store::new(bucket, region, endpoint, access_key_id, secret_access_key)))
+        }
+        "memory" => Ok(Arc::new(MemoryStore::new())),
+        other => Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!("Unknown storage service: {}", other),
+        )),
+    }
+}
@@ -0,0 +1,269 @@
+// A small S3 compatible server for the S3Store tests, objects are kept in memory
+//
+// Speaks just enough of the REST API for what S3Store sends: objects, ranges, multipart
+// uploads, part copies, listings and batch deletes. Requests are recorded by operation name
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
+
+use actix_web::dev::ServerHandle;
+use actix_web::http::header::{CONTENT_ENCODING, CONTENT_RANGE, ETAG, RANGE};
+use actix_web::http::{Method, StatusCode};
+use actix_web::web::{self, Bytes};
+use actix_web::{App, HttpRequest, HttpResponse, HttpServer};
+use percent_encoding::percent_decode_str;
+
+#[derive(Default)]
+struct Bucket {
+    objects: BTreeMap<String, Bytes>,
+    // Parts of the open multipart uploads by upload id
+    uploads: HashMap<String, BTreeMap<i32, Bytes>>,
+    operations: Vec<&'static str>,
+}
+
+pub struct S3StandIn {
+    pub endpoint: String,
+    bucket: Arc<Mutex<Bucket>>,
+    handle: ServerHandle,
+}
+
+impl S3StandIn {
+    pub async fn start() -> S3StandIn {
+        let bucket = Arc::new(Mutex::new(Bucket::default()));
+        let data = web::Data::from(bucket.clone());
+        let server = HttpServer::new(move || {
+            App::new()
+                .app_data(data.clone())
+                .app_data(web::PayloadConfig::new(64 * 1024 * 1024))
+                .default_service(web::to(handle))
+        })
+        .workers(1)
+        .disable_signals()
+        .bind(("127.0.0.1", 0))
+        .expect("Failed to bind the S3 stand-in");
+
+        let endpoint = format!("http://{}", server.addrs()[0]);
+        let server = server.run();
+        let handle = server.handle();
+        actix_web::rt::spawn(server);
+
+        S3StandIn { endpoint, bucket, handle }
+    }
+
+    // Operations received since the last call
+    pub fn take_operations(&self) -> Vec<&'static str> {
+        std::mem::take(&mut self.bucket.lock().unwrap().operations)
+    }
+
+    pub fn object_keys(&self) -> Vec<String> {
+        self.bucket.lock().unwrap().objects.keys().cloned().collect()
+    }
+}
+
+impl Drop for S3StandIn {
+    fn drop(&mut self) {
+        actix_web::rt::spawn(self.handle.stop(false));
+    }
+}
+
+async fn handle(req: HttpRequest, body: Bytes, bucket: web::Data<Mutex<Bucket>>) -> HttpResponse {
+    let path = percent_decode_str(req.uri().path()).decode_utf8_lossy().to_string();
+    let key = path.trim_start_matches('/').split_once('/').map(|(_, key)| key.to_string()).unwrap_or_default();
+    let query = web::Query::<HashMap<String, String>>::from_query(req.query_string()).map(|query| query.into_inner()).unwrap_or_default();
+    let copy_source = header(&req, "x-amz-copy-source").map(|source| {
+        let source = percent_decode_str(&source).decode_utf8_lossy().to_string();
+        source.trim_start_matches('/').split_once('/').map(|(_, key)| key.to_string()).unwrap_or_default()
+    });
+    let mut bucket = bucket.lock().unwrap();
+
+    match (req.method().clone(), key.is_empty()) {
+        (Method::GET, true) => {
+            bucket.operations.push("ListObjectsV2");
+            list(&bucket, query.get("prefix").map(String::as_str).unwrap_or(""), query.get("delimiter").map(String::as_str))
+        }
+        (Method::POST, true) if query.contains_key("delete") => {
+            bucket.operations.push("DeleteObjects");
+            for key in xml_values(&String::from_utf8_lossy(&body), "Key") {
+                bucket.objects.remove(&key);
+            }
+            xml("<DeleteResult></DeleteResult>".to_string())
+        }
+        (Method::GET, false) => {
+            bucket.operations.push("GetObject");
+            let Some(data) = bucket.objects.get(&key).cloned() else { return no_such_key(&key) };
+            match header(&req, RANGE.as_str()).and_then(|range| byte_range(&range, data.len())) {
+                Some((start, end)) => HttpResponse::PartialContent()
+                    .insert_header((CONTENT_RANGE, format!("bytes {}-{}/{}", start, end, data.len())))
+                    .body(data.slice(start..end + 1)),
+                None => HttpResponse::Ok().body(data),
+            }
+        }
+        (Method::HEAD, false) => {
+            bucket.operations.push("HeadObject");
+            match bucket.objects.get(&key) {
+                // The body is left out of HEAD responses, only its length is sent
+                Some(data) => HttpResponse::Ok().body(data.clone()),
+                None => HttpResponse::NotFound().finish(),
+            }
+        }
+        (Method::PUT, false) => {
+            let data = match copy_source {
+                Some(source) => {
+                    let Some(data) = bucket.objects.get(&source).cloned() else { return no_such_key(&source) };
+                    match header(&req, "x-amz-copy-source-range").and_then(|range| byte_range(&range, data.len())) {
+                        Some((start, end)) => data.slice(start..end + 1),
+                        None => data,
+                    }
+                }
+                None => decode_body(&req, body),
+            };
+
+            let (Some(upload_id), Some(part_number)) = (query.get("uploadId"), query.get("partNumber")) else {
+                if req.headers().contains_key("x-amz-copy-source") {
+                    bucket.operations.push("CopyObject");
+                    bucket.objects.insert(key, data);
+                    return xml("<CopyObjectResult><ETag>\"copy\"</ETag></CopyObjectResult>".to_string());
+                }
+                bucket.operations.push("PutObject");
+                bucket.objects.insert(key, data);
+                return HttpResponse::Ok().insert_header((ETAG, "\"object\"")).finish();
+            };
+
+            let part_number: i32 = part_number.parse().unwrap_or_default();
+            let copied = req.headers().contains_key("x-amz-copy-source");
+            bucket.operations.push(if copied { "UploadPartCopy" } else { "UploadPart" });
+            let Some(parts) = bucket.uploads.get_mut(upload_id) else { return error(StatusCode::NOT_FOUND, "NoSuchUpload", upload_id) };
+            parts.insert(part_number, data);
+            let etag = format!("\"part-{}\"", part_number);
+            match copied {
+                true => xml(format!("<CopyPartResult><ETag>{}</ETag></CopyPartResult>", escape(&etag))),
+                false => HttpResponse::Ok().insert_header((ETAG, etag)).finish(),
+            }
+        }
+        (Method::POST, false) if query.contains_key("uploads") => {
+            bucket.operations.push("CreateMultipartUpload");
+            let upload_id = uuid::Uuid::new_v4().to_string();
+            bucket.uploads.insert(upload_id.clone(), BTreeMap::new());
+            xml(format!(
+                "<InitiateMultipartUploadResult><Key>{}</Key><UploadId>{}</UploadId></InitiateMultipartUploadResult>",
+                escape(&key),
+                upload_id
+            ))
+        }
+        (Method::POST, false) if query.contains_key("uploadId") => {
+            bucket.operations.push("CompleteMultipartUpload");
+            let Some(parts) = bucket.uploads.remove(&query["uploadId"]) else { return error(StatusCode::NOT_FOUND, "NoSuchUpload", &query["uploadId"]) };
+            let listed = xml_values(&String::from_utf8_lossy(&body), "PartNumber");
+            if listed.len() != parts.len() {
+                return error(StatusCode::BAD_REQUEST, "InvalidPart", &key);
+            }
+            let data: Vec<u8> = parts.values().flat_map(|part| part.iter().copied()).collect();
+            bucket.objects.insert(key.clone(), Bytes::from(data));
+            xml(format!("<CompleteMultipartUploadResult><Key>{}</Key><ETag>\"multipart\"</ETag></CompleteMultipartUploadResult>", escape(&key)))
+        }
+        (Method::DELETE, false) if query.contains_key("uploadId") => {
+            bucket.operations.push("AbortMultipartUpload");
+            bucket.uploads.remove(&query["uploadId"]);
+            HttpResponse::NoContent().finish()
+        }
+        (Method::DELETE, false) => {
+            bucket.operations.push("DeleteObject");
+            bucket.objects.remove(&key);
+            HttpResponse::NoContent().finish()
+        }
+        _ => error(StatusCode::NOT_IMPLEMENTED, "NotImplemented", &path),
+    }
+}
+
+fn list(bucket: &Bucket, prefix: &str, delimiter: Option<&str>) -> HttpResponse {
+    let mut contents = String::new();
+    let mut common_prefixes: Vec<String> = Vec::new();
+    for (key, data) in bucket.objects.iter().filter(|(key, _)| key.starts_with(prefix)) {
+        let folder = delimiter.and_then(|delimiter| key[prefix.len()..].find(delimiter).map(|index| &key[..prefix.len() + index + delimiter.len()]));
+        match folder {
+            Some(folder) if !common_prefixes.iter().any(|known| known == folder) => common_prefixes.push(folder.to_string()),
+            Some(_) => {}
+            None => contents.push_str(&format!("<Contents><Key>{}</Key><Size>{}</Size></Contents>", escape(key), data.len())),
+        }
+    }
+
+    let common_prefixes: String = common_prefixes
+        .iter()
+        .map(|folder| format!("<CommonPrefixes><Prefix>{}</Prefix></CommonPrefixes>", escape(folder)))
+        .collect();
+    xml(format!(
+        "<ListBucketResult><Prefix>{}</Prefix><IsTruncated>false</IsTruncated>{}{}</ListBucketResult>",
+        escape(prefix),
+        contents,
+        common_prefixes
+    ))
+}
+
+fn header(req: &HttpRequest, name: &str) -> Option<String> {
+    req.headers().get(name).and_then(|value| value.to_str().ok()).map(str::to_string)
+}
+
+// `bytes=start-end` as inclusive offsets, the end is clamped to the data
+fn byte_range(range: &str, len: usize) -> Option<(usize, usize)> {
+    let (start, end) = range.strip_prefix("bytes=")?.split_once('-')?;
+    let start: usize = start.parse().ok()?;
+    let end = match end {
+        "" => len - 1,
+        end => end.parse::<usize>().ok()?.min(len - 1),
+    };
+    Some((start, end))
+}
+
+// The SDK may send bodies aws-chunked, with their checksum as a trailer
+fn decode_body(req: &HttpRequest, body: Bytes) -> Bytes {
+    let chunked = header(req, CONTENT_ENCODING.as_str()).is_some_and(|encoding| encoding.contains("aws-chunked"));
+    if !chunked {
+        return body;
+    }
+
+    let mut data = Vec::new();
+    let mut rest = &body[..];
+    while let Some(line_end) = rest.windows(2).position(|window| window == b"\r\n") {
+        let size = String::from_utf8_lossy(&rest[..line_end]);
+        let size = usize::from_str_radix(size.split(';').next().unwrap_or_default().trim(), 16).unwrap_or_default();
+        rest = &rest[line_end + 2..];
+        if size == 0 {
+            break;
+        }
+        data.extend_from_slice(&rest[..size]);
+        rest = &rest[size + 2..];
+    }
+    Bytes::from(data)
+}
+
+// Text of every `<tag>` element, unescaped
+fn xml_values(document: &str, tag: &str) -> Vec<String> {
+    let (open, close) = (format!("<{}>", tag), format!("</{}>", tag));
+    document
+        .split(&open)
+        .skip(1)
+        .filter_map(|part| part.split_once(&close).map(|(value, _)| unescape(value)))
+        .collect()
+}
+
+fn escape(value: &str) -> String {
+    value.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+fn unescape(value: &str) -> String {
+    value.replace("&lt;", "<").replace("&gt;", ">").replace("&quot;", "\"").replace("&amp;", "&")
+}
+
+fn xml(document: String) -> HttpResponse {
+    HttpResponse::Ok().content_type("application/xml").body(document)
+}
+
+fn error(status: StatusCode, code: &str, resource: &str) -> HttpResponse {
+    HttpResponse::build(status)
+        .content_type("application/xml")
+        .body(format!("<Error><Code>{}</Code><Resource>{}</Resource></Error>", code, escape(resource)))
+}
+
+fn no_such_key(key: &str) -> HttpResponse {
+    error(StatusCode::NOT_FOUND, "NoSuchKey", key)
+}
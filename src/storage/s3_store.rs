@@ -0,0 +1,565 @@
+use std::io::{Error, ErrorKind};
+
+use async_trait::async_trait;
+use aws_sdk_s3::config::{Builder, Credentials, Region};
+use aws_sdk_s3::primitives::ByteStream as S3ByteStream;
+use aws_sdk_s3::types::{CompletedMultipartUpload, CompletedPart, Delete, ObjectIdentifier};
+use aws_sdk_s3::Client;
+use futures_util::TryStreamExt;
+use percent_encoding::{utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+
+use crate::shared::common::{ByteStream, StorageService};
+
+// S3 requires every part except the last one to be at least 5 MiB
+const PART_SIZE: usize = 8 * 1024 * 1024;
+
+// Largest object CopyObject copies in one request, larger ones are copied in parts of this size
+const MAX_COPY_SIZE: u64 = 5 * 1024 * 1024 * 1024;
+
+// Characters kept as they are in the `x-amz-copy-source` header, everything else is percent encoded
+const COPY_SOURCE: &AsciiSet = &NON_ALPHANUMERIC.remove(b'/').remove(b'-').remove(b'_').remove(b'.').remove(b'~');
+
+///
+/// Stores blobs in an S3 compatible bucket (AWS S3, MinIO, ...)
+///
+/// Objects are keyed as `{path}/{name}`, mirroring the layout used by `FileStore`
+///
+#[derive(Clone)]
+pub struct S3Store {
+    client: Client,
+    bucket: String,
+    max_copy_size: u64,
+}
+
+impl S3Store {
+    pub fn new(
+        bucket: String,
+        region: String,
+        endpoint: Option<String>,
+        access_key_id: String,
+        secret_access_key: String,
+    ) -> S3Store {
+        let mut builder = Builder::new()
+            .region(Region::new(region))
+            .credentials_provider(Credentials::new(access_key_id, secret_access_key, None, None, "fly-service"));
+
+        // MinIO and most other S3 compatible servers only support path style addressing
+        if let Some(endpoint) = endpoint {
+            builder = builder.endpoint_url(endpoint).force_path_style(true);
+        }
+
+        S3Store {
+            client: Client::from_conf(builder.build()),
+            bucket,
+            max_copy_size: MAX_COPY_SIZE,
+        }
+    }
+
+    fn key(path: &str, name: &str) -> String {
+        format!("{}/{}", path.trim_end_matches('/'), name)
+    }
+
+    fn prefix(path: &str) -> String {
+        format!("{}/", path.trim_end_matches('/'))
+    }
+
+    // Source of a copy as `{bucket}/{key}`, the key has to be URL encoded
+    fn copy_source(bucket: &str, key: &str) -> String {
+        format!("{}/{}", bucket, utf8_percent_encode(key, COPY_SOURCE))
+    }
+
+    async fn upload_part(&self, key: &str, upload_id: &str, part_number: i32, data: Vec<u8>) -> Result<CompletedPart, Error> {
+        let part = self.client
+            .upload_part()
+            .bucket(&self.bucket)
+            .key(key)
+            .upload_id(upload_id)
+            .part_number(part_number)
+            .body(S3ByteStream::from(data))
+            .send()
+            .await
+            .map_err(Error::other)?;
+
+        Ok(CompletedPart::builder()
+            .set_e_tag(part.e_tag)
+            .part_number(part_number)
+            .build())
+    }
+
+    async fn upload_parts(&self, key: &str, upload_id: &str, mut buffer: Vec<u8>, mut input: ByteStream) -> Result<(), Error> {
+        let mut parts = Vec::new();
+
+        loop {
+            let chunk = input.try_next().await?;
+            let finished = chunk.is_none();
+            if let Some(chunk) = chunk {
+                buffer.extend_from_slice(&chunk);
+            }
+
+            if buffer.len() >= PART_SIZE || (finished && !buffer.is_empty()) {
+                let data = std::mem::take(&mut buffer);
+                let part_number = parts.len() as i32 + 1;
+                parts.push(self.upload_part(key, upload_id, part_number, data).await?);
+            }
+
+            if finished {
+                break;
+            }
+        }
+
+        self.client
+            .complete_multipart_upload()
+            .bucket(&self.bucket)
+            .key(key)
+            .upload_id(upload_id)
+            .multipart_upload(CompletedMultipartUpload::builder().set_parts(Some(parts)).build())
+            .send()
+            .await
+            .map_err(Error::other)?;
+
+        Ok(())
+    }
+
+    // Copies an object too large for CopyObject with UploadPartCopy, one range per part
+    async fn copy_parts(&self, from: &str, to: &str, upload_id: &str, size: u64) -> Result<(), Error> {
+        let mut parts = Vec::new();
+
+        for (index, start) in (0..size).step_by(self.max_copy_size as usize).enumerate() {
+            let end = (start + self.max_copy_size).min(size) - 1;
+            let part_number = index as i32 + 1;
+            let part = self.client
+                .upload_part_copy()
+                .bucket(&self.bucket)
+                .key(to)
+                .upload_id(upload_id)
+                .part_number(part_number)
+                .copy_source(S3Store::copy_source(&self.bucket, from))
+                .copy_source_range(format!("bytes={}-{}", start, end))
+                .send()
+                .await
+                .map_err(Error::other)?;
+
+            parts.push(CompletedPart::builder()
+                .set_e_tag(part.copy_part_result.and_then(|result| result.e_tag))
+                .part_number(part_number)
+                .build());
+        }
+
+        self.client
+            .complete_multipart_upload()
+            .bucket(&self.bucket)
+            .key(to)
+            .upload_id(upload_id)
+            .multipart_upload(CompletedMultipartUpload::builder().set_parts(Some(parts)).build())
+            .send()
+            .await
+            .map_err(Error::other)?;
+
+        Ok(())
+    }
+
+    async fn copy_multipart(&self, from: &str, to: &str, size: u64) -> Result<(), Error> {
+        let upload = self.client
+            .create_multipart_upload()
+            .bucket(&self.bucket)
+            .key(to)
+            .send()
+            .await
+            .map_err(Error::other)?;
+        let upload_id = upload
+            .upload_id()
+            .ok_or_else(|| Error::other("S3 did not return an upload id"))?
+            .to_string();
+
+        if let Err(err) = self.copy_parts(from, to, &upload_id, size).await {
+            log::error!("Aborting multipart copy of {} to {}: {}", from, to, err);
+            let _ = self.client
+                .abort_multipart_upload()
+                .bucket(&self.bucket)
+                .key(to)
+                .upload_id(&upload_id)
+                .send()
+                .await;
+            return Err(err);
+        }
+
+        Ok(())
+    }
+
+    async fn get_object(&self, key: &str, range: Option<String>) -> Result<ByteStream, Error> {
+        let object = self.client
+            .get_object()
+            .bucket(&self.bucket)
+            .key(key)
+            .set_range(range)
+            .send()
+            .await
+            .map_err(|err| match err.as_service_error() {
+                Some(service_err) if service_err.is_no_such_key() => Error::new(ErrorKind::NotFound, format!("Object {} not found", key)),
+                _ => Error::other(err),
+            })?;
+
+        let stream = futures_util::stream::unfold(object.body, |mut body| async move {
+            body.next()
+                .await
+                .map(|chunk| (chunk.map_err(Error::other), body))
+        });
+
+        Ok(Box::pin(stream))
+    }
+
+    async fn list(&self, path: &str) -> Result<(Vec<String>, Vec<String>), Error> {
+        let prefix = S3Store::prefix(path);
+        let mut files = Vec::new();
+        let mut folders = Vec::new();
+
+        let mut pages = self.client
+            .list_objects_v2()
+            .bucket(&self.bucket)
+            .prefix(&prefix)
+            .delimiter("/")
+            .into_paginator()
+            .send();
+
+        while let Some(page) = pages.next().await {
+            let page = page.map_err(Error::other)?;
+            for object in page.contents() {
+                if let Some(name) = object.key().and_then(|key| key.strip_prefix(prefix.as_str())) {
+                    files.push(name.to_string());
+                }
+            }
+            for common_prefix in page.common_prefixes() {
+                if let Some(folder) = common_prefix.prefix().and_then(|folder| folder.strip_prefix(prefix.as_str())) {
+                    folders.push(folder.trim_end_matches('/').to_string());
+                }
+            }
+        }
+
+        Ok((files, folders))
+    }
+}
+
+#[async_trait(?Send)]
+impl StorageService for S3Store {
+    async fn save_file(&self, path: &str, name: &str, mut input: ByteStream) -> Result<(), Error> {
+        let key = S3Store::key(path, name);
+
+        // Small files are sent with a single PUT, anything larger than a part is streamed as a multipart upload
+        let mut buffer: Vec<u8> = Vec::new();
+        while buffer.len() < PART_SIZE {
+            match input.try_next().await? {
+                Some(chunk) => buffer.extend_from_slice(&chunk),
+                None => {
+                    self.client
+                        .put_object()
+                        .bucket(&self.bucket)
+                        .key(&key)
+                        .body(S3ByteStream::from(buffer))
+                        .send()
+                        .await
+                        .map_err(Error::other)?;
+                    log::info!("Finished writing object {}", key);
+                    return Ok(());
+                }
+            }
+        }
+
+        let upload = self.client
+            .create_multipart_upload()
+            .bucket(&self.bucket)
+            .key(&key)
+            .send()
+            .await
+            .map_err(Error::other)?;
+        let upload_id = upload
+            .upload_id()
+            .ok_or_else(|| Error::other("S3 did not return an upload id"))?
+            .to_string();
+
+        if let Err(err) = self.upload_parts(&key, &upload_id, buffer, input).await {
+            log::error!("Aborting multipart upload of {}: {}", key, err);
+            let _ = self.client
+                .abort_multipart_upload()
+                .bucket(&self.bucket)
+                .key(&key)
+                .upload_id(&upload_id)
+                .send()
+                .await;
+            return Err(err);
+        }
+        log::info!("Finished writing object {}", key);
+
+        Ok(())
+    }
+
+    async fn retrieve_file(&self, path: &str, name: &str) -> Result<ByteStream, Error> {
+        self.get_object(&S3Store::key(path, name), None).await
+    }
+
+    async fn retrieve_file_range(&self, path: &str, name: &str, start: u64, length: u64) -> Result<ByteStream, Error> {
+        // An empty range can not be expressed in a Range header
+        if length == 0 {
+            return Ok(Box::pin(futures_util::stream::empty()));
+        }
+        let range = format!("bytes={}-{}", start, start + length - 1);
+        self.get_object(&S3Store::key(path, name), Some(range)).await
+    }
+
+    async fn file_size(&self, path: &str, name: &str) -> Result<u64, Error> {
+        let key = S3Store::key(path, name);
+        let object = self.client
+            .head_object()
+            .bucket(&self.bucket)
+            .key(&key)
+            .send()
+            .await
+            .map_err(|err| match err.as_service_error() {
+                Some(service_err) if service_err.is_not_found() => Error::new(ErrorKind::NotFound, format!("Object {} not found", key)),
+                _ => Error::other(err),
+            })?;
+
+        Ok(object.content_length().unwrap_or_default() as u64)
+    }
+
+    async fn delete_file(&self, path: &str, name: &str) -> Result<(), Error> {
+        self.client
+            .delete_object()
+            .bucket(&self.bucket)
+            .key(S3Store::key(path, name))
+            .send()
+            .await
+            .map_err(Error::other)?;
+        Ok(())
+    }
+
+    async fn move_file(&self, from_path: &str, from_name: &str, to_path: &str, to_name: &str) -> Result<(), Error> {
+        self.copy_file(from_path, from_name, to_path, to_name).await?;
+        self.delete_file(from_path, from_name).await
+    }
+
+    async fn copy_file(&self, from_path: &str, from_name: &str, to_path: &str, to_name: &str) -> Result<(), Error> {
+        let from = S3Store::key(from_path, from_name);
+        let to = S3Store::key(to_path, to_name);
+
+        // CopyObject is limited to 5 GB, larger objects have to be copied part by part
+        let size = self.file_size(from_path, from_name).await?;
+        if size > self.max_copy_size {
+            return self.copy_multipart(&from, &to, size).await;
+        }
+
+        self.client
+            .copy_object()
+            .bucket(&self.bucket)
+            .copy_source(S3Store::copy_source(&self.bucket, &from))
+            .key(to)
+            .send()
+            .await
+            .map_err(|err| match err.raw_response().map(|response| response.status().as_u16()) {
+                Some(404) => Error::new(ErrorKind::NotFound, format!("Object {} not found", from)),
+                _ => Error::other(err),
+            })?;
+        Ok(())
+    }
+
+    fn create_folder(&self, _path: &str) -> Result<(), Error> {
+        // S3 has no real folders, keys are created along with the objects
+        Ok(())
+    }
+
+    async fn delete_folder(&self, path: &str) -> Result<(), Error> {
+        let mut pages = self.client
+            .list_objects_v2()
+            .bucket(&self.bucket)
+            .prefix(S3Store::prefix(path))
+            .into_paginator()
+            .send();
+
+        // A page holds at most 1000 keys, as many as one DeleteObjects request accepts
+        while let Some(page) = pages.next().await {
+            let page = page.map_err(Error::other)?;
+            let objects = page.contents()
+                .iter()
+                .filter_map(|object| object.key())
+                .map(|key| ObjectIdentifier::builder().key(key).build().map_err(Error::other))
+                .collect::<Result<Vec<_>, Error>>()?;
+            if objects.is_empty() {
+                continue;
+            }
+
+            let deleted = self.client
+                .delete_objects()
+                .bucket(&self.bucket)
+                .delete(Delete::builder().set_objects(Some(objects)).quiet(true).build().map_err(Error::other)?)
+                .send()
+                .await
+                .map_err(Error::other)?;
+            if let Some(error) = deleted.errors().first() {
+                return Err(Error::other(format!(
+                    "Failed to delete object {}: {}",
+                    error.key().unwrap_or_default(),
+                    error.message().unwrap_or_default()
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    async fn list_file_names(&self, path: &str) -> Result<Vec<String>, Error> {
+        Ok(self.list(path).await?.0)
+    }
+
+    async fn list_folder_names(&self, path: &str) -> Result<Vec<String>, Error> {
+        Ok(self.list(path).await?.1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::web::Bytes;
+
+    use crate::storage::s3_stand_in::S3StandIn;
+
+    #[test]
+    fn copy_source_is_url_encoded() {
+        assert_eq!(S3Store::copy_source("bucket", "a b/c+d.txt"), "bucket/a%20b/c%2Bd.txt");
+        assert_eq!(S3Store::copy_source("bucket", "x/y-z_1~.bin"), "bucket/x/y-z_1~.bin");
+    }
+
+    fn stream_of(data: &'static [u8]) -> ByteStream {
+        Box::pin(futures_util::stream::once(async move { Ok::<_, Error>(Bytes::from_static(data)) }))
+    }
+
+    async fn read_all(stream: ByteStream) -> Vec<u8> {
+        stream.try_fold(Vec::new(), |mut data, chunk| async move {
+            data.extend_from_slice(&chunk);
+            Ok(data)
+        }).await.unwrap()
+    }
+
+    fn store_for(stand_in: &S3StandIn) -> S3Store {
+        S3Store::new("fly-test".to_string(), "us-east-1".to_string(), Some(stand_in.endpoint.clone()), "test".to_string(), "test".to_string())
+    }
+
+    #[actix_web::test]
+    async fn s3_store_saves_reads_and_lists_objects() {
+        let stand_in = S3StandIn::start().await;
+        let store = store_for(&stand_in);
+
+        store.save_file("root", "a b+c.txt", stream_of(b"hello world")).await.unwrap();
+        store.save_file("root/root", "x.txt", stream_of(b"nested")).await.unwrap();
+        assert_eq!(stand_in.take_operations(), vec!["PutObject", "PutObject"]);
+
+        assert_eq!(read_all(store.retrieve_file("root", "a b+c.txt").await.unwrap()).await, b"hello world");
+        assert_eq!(read_all(store.retrieve_file_range("root", "a b+c.txt", 6, 5).await.unwrap()).await, b"world");
+        assert!(read_all(store.retrieve_file_range("root", "a b+c.txt", 0, 0).await.unwrap()).await.is_empty());
+        assert_eq!(store.file_size("root", "a b+c.txt").await.unwrap(), 11);
+        assert_eq!(store.retrieve_file("root", "missing.txt").await.err().unwrap().kind(), ErrorKind::NotFound);
+        assert_eq!(store.file_size("root", "missing.txt").await.err().unwrap().kind(), ErrorKind::NotFound);
+
+        // The folder is named like its parent, only the leading prefix may be removed
+        assert_eq!(store.list_folder_names("root").await.unwrap(), vec!["root".to_string()]);
+        assert_eq!(store.list_file_names("root").await.unwrap(), vec!["a b+c.txt".to_string()]);
+        assert_eq!(store.list_file_names("root/root").await.unwrap(), vec!["x.txt".to_string()]);
+    }
+
+    #[actix_web::test]
+    async fn s3_store_streams_large_files_as_multipart_uploads() {
+        let stand_in = S3StandIn::start().await;
+        let store = store_for(&stand_in);
+        let data: Vec<u8> = (0..PART_SIZE + 10).map(|index| index as u8).collect();
+        let chunks: Vec<Result<Bytes, Error>> = data.chunks(1024 * 1024).map(|chunk| Ok(Bytes::copy_from_slice(chunk))).collect();
+
+        store.save_file("root", "large.bin", Box::pin(futures_util::stream::iter(chunks))).await.unwrap();
+        let operations = stand_in.take_operations();
+        assert_eq!(operations.first(), Some(&"CreateMultipartUpload"));
+        assert_eq!(operations.last(), Some(&"CompleteMultipartUpload"));
+        assert!(operations.contains(&"UploadPart"));
+        assert_eq!(read_all(store.retrieve_file("root", "large.bin").await.unwrap()).await, data);
+        assert_eq!(read_all(store.retrieve_file_range("root", "large.bin", PART_SIZE as u64, 10).await.unwrap()).await, &data[PART_SIZE..]);
+    }
+
+    #[actix_web::test]
+    async fn s3_store_copies_large_objects_in_parts() {
+        let stand_in = S3StandIn::start().await;
+        let mut store = store_for(&stand_in);
+        store.save_file("root", "a.txt", stream_of(b"hello world")).await.unwrap();
+
+        store.copy_file("root", "a.txt", "copies", "small.txt").await.unwrap();
+        stand_in.take_operations();
+
+        // Anything above the single copy limit goes through UploadPartCopy
+        store.max_copy_size = 4;
+        store.move_file("root", "a.txt", "copies", "large.txt").await.unwrap();
+        assert_eq!(stand_in.take_operations(), vec![
+            "HeadObject",
+            "CreateMultipartUpload",
+            "UploadPartCopy",
+            "UploadPartCopy",
+            "UploadPartCopy",
+            "CompleteMultipartUpload",
+            "DeleteObject",
+        ]);
+        assert_eq!(read_all(store.retrieve_file("copies", "small.txt").await.unwrap()).await, b"hello world");
+        assert_eq!(read_all(store.retrieve_file("copies", "large.txt").await.unwrap()).await, b"hello world");
+        assert_eq!(store.file_size("root", "a.txt").await.err().unwrap().kind(), ErrorKind::NotFound);
+        assert_eq!(store.copy_file("root", "a.txt", "copies", "b.txt").await.err().unwrap().kind(), ErrorKind::NotFound);
+    }
+
+    #[actix_web::test]
+    async fn s3_store_deletes_folders_in_batches() {
+        let stand_in = S3StandIn::start().await;
+        let store = store_for(&stand_in);
+        for name in ["a.txt", "b.txt", "c.txt"] {
+            store.save_file("root", name, stream_of(b"data")).await.unwrap();
+        }
+        store.save_file("root/nested", "d.txt", stream_of(b"data")).await.unwrap();
+        store.save_file("root-sibling", "e.txt", stream_of(b"data")).await.unwrap();
+        stand_in.take_operations();
+
+        store.delete_folder("root").await.unwrap();
+        assert_eq!(stand_in.take_operations(), vec!["ListObjectsV2", "DeleteObjects"]);
+        assert_eq!(stand_in.object_keys(), vec!["root-sibling/e.txt".to_string()]);
+    }
+
+    ///
+    /// Runs against a MinIO style server, start one and set `S3_TEST_ENDPOINT` and `S3_TEST_BUCKET`
+    ///
+    /// `docker run -p 9000:9000 minio/minio server /data`, then create the bucket and run
+    /// `cargo test -- --ignored s3_store`
+    ///
+    #[actix_web::test]
+    #[ignore]
+    async fn s3_store_against_minio() {
+        let store = S3Store::new(
+            std::env::var("S3_TEST_BUCKET").unwrap_or("fly-test".to_string()),
+            "us-east-1".to_string(),
+            Some(std::env::var("S3_TEST_ENDPOINT").unwrap_or("http://localhost:9000".to_string())),
+            std::env::var("S3_TEST_ACCESS_KEY_ID").unwrap_or("minioadmin".to_string()),
+            std::env::var("S3_TEST_SECRET_ACCESS_KEY").unwrap_or("minioadmin".to_string()),
+        );
+        let root = format!("test-{}", uuid::Uuid::new_v4());
+        let nested = format!("{}/{}", root, root);
+
+        store.save_file(&root, "a b+c.txt", stream_of(b"hello world")).await.unwrap();
+        store.save_file(&nested, "x.txt", stream_of(b"nested")).await.unwrap();
+
+        assert_eq!(read_all(store.retrieve_file(&root, "a b+c.txt").await.unwrap()).await, b"hello world");
+        assert_eq!(read_all(store.retrieve_file_range(&root, "a b+c.txt", 6, 5).await.unwrap()).await, b"world");
+        assert!(read_all(store.retrieve_file_range(&root, "a b+c.txt", 0, 0).await.unwrap()).await.is_empty());
+        assert_eq!(store.file_size(&root, "a b+c.txt").await.unwrap(), 11);
+
+        // The folder is named like its parent, only the leading prefix may be removed
+        assert_eq!(store.list_folder_names(&root).await.unwrap(), vec![root.clone()]);
+        assert_eq!(store.list_file_names(&nested).await.unwrap(), vec!["x.txt".to_string()]);
+
+        store.copy_file(&root, "a b+c.txt", &root, "copy +1.txt").await.unwrap();
+        assert_eq!(read_all(store.retrieve_file(&root, "copy +1.txt").await.unwrap()).await, b"hello world");
+
+        let missing = store.retrieve_file(&root, "missing.txt").await.err().unwrap();
+        assert_eq!(missing.kind(), ErrorKind::NotFound);
+
+        store.delete_folder(&root).await.unwrap();
+        assert!(store.list_file_names(&root).await.unwrap().is_empty());
+    }
+}
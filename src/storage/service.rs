@@ -0,0 +1,45 @@
+use crate::shared::common::DbError;
+use super::dto::BlobDto;
+use diesel::prelude::*;
+
+use crate::schema::blobs::dsl;
+
+pub fn get_blob(conn: &mut SqliteConnection, hash: &str) -> Result<BlobDto, DbError> {
+    let blob = dsl::blobs
+        .filter(dsl::hash.eq(hash))
+        .first::<BlobDto>(conn)
+        .map_err(|err| format!("Error loading blob hash: {}, Error: {}", hash, err))?;
+    Ok(blob)
+}
+
+pub fn create_blob(conn: &mut SqliteConnection, blob: &BlobDto) -> Result<usize, DbError> {
+    Ok(diesel::insert_into(dsl::blobs)
+        .values(blob)
+        .execute(conn)?)
+}
+
+///
+/// Adds a reference to a blob, returns the number of updated blobs so zero means it does not exist
+///
+pub fn add_blob_reference(conn: &mut SqliteConnection, hash: &str) -> Result<usize, DbError> {
+    Ok(diesel::update(dsl::blobs.filter(dsl::hash.eq(hash)))
+        .set(dsl::ref_count.eq(dsl::ref_count + 1))
+        .execute(conn)?)
+}
+
+pub fn release_blob_reference(conn: &mut SqliteConnection, hash: &str) -> Result<usize, DbError> {
+    Ok(diesel::update(dsl::blobs.filter(dsl::hash.eq(hash).and(dsl::ref_count.gt(0))))
+        .set(dsl::ref_count.eq(dsl::ref_count - 1))
+        .execute(conn)?)
+}
+
+pub fn get_unreferenced_blobs(conn: &mut SqliteConnection) -> Result<Vec<BlobDto>, DbError> {
+    Ok(dsl::blobs
+        .filter(dsl::ref_count.le(0))
+        .load::<BlobDto>(conn)?)
+}
+
+pub fn delete_unreferenced_blob(conn: &mut SqliteConnection, hash: &str) -> Result<usize, DbError> {
+    Ok(diesel::delete(dsl::blobs.filter(dsl::hash.eq(hash).and(dsl::ref_count.le(0))))
+        .execute(conn)?)
+}
@@ -3,22 +3,92 @@ use utoipa::OpenApi;
 use crate::auth;
 use crate::files;
 use crate::folders;
+use crate::groups;
+use crate::jobs;
+use crate::search;
+use crate::shares;
+use crate::sessions;
+use crate::sharing;
+use crate::trash;
+use crate::uploads;
 
 #[derive(OpenApi)]
 #[openapi(
     paths(
     // Auth
         auth::register_user_handler, 
+        auth::verify_email_handler,
+        auth::resend_verification_handler,
         auth::login_user_handler,
+        auth::refresh_handler,
+        auth::forgot_password_handler,
+        auth::reset_password_handler,
+        auth::change_password_handler,
         auth::logout_handler,
+        auth::user_usage_handler,
     // Files
         files::get_file_handler,
         files::get_file_contents_handler,
+        files::get_file_thumbnail_handler,
+        files::archive_files_handler,
         files::create_file_handler,
         files::upload_file_handler,
         files::get_all_files_handler,
+        files::update_file_handler,
+        files::delete_file_handler,
+        files::get_file_versions_handler,
+        files::get_file_version_contents_handler,
+        files::promote_file_version_handler,
     // Folders
         folders::get_all_folders_handler,
+        folders::get_folder_handler,
+        folders::get_folder_tree_handler,
+        folders::get_folder_ancestors_handler,
+        folders::archive_folder_handler,
+        folders::create_folder_handler,
+        folders::update_folder_handler,
+        folders::delete_folder_handler,
+    // Trash
+        trash::get_trash_handler,
+        trash::restore_file_handler,
+        trash::restore_folder_handler,
+    // Shares
+        shares::get_share_links_handler,
+        shares::create_share_link_handler,
+        shares::revoke_share_link_handler,
+        shares::get_public_share_handler,
+        shares::get_public_share_folder_handler,
+        shares::get_public_share_contents_handler,
+        shares::get_public_share_file_contents_handler,
+    // Sharing
+        sharing::get_user_shares_handler,
+        sharing::get_shared_with_me_handler,
+        sharing::create_user_share_handler,
+        sharing::revoke_user_share_handler,
+    // Sessions
+        sessions::get_sessions_handler,
+        sessions::revoke_session_handler,
+        sessions::revoke_other_sessions_handler,
+    // Groups
+        groups::get_groups_handler,
+        groups::get_group_handler,
+        groups::create_group_handler,
+        groups::get_group_members_handler,
+        groups::add_group_member_handler,
+        groups::remove_group_member_handler,
+    // Search
+        search::search_handler,
+    // Jobs
+        jobs::get_jobs_handler,
+        jobs::get_job_stats_handler,
+        jobs::get_job_handler,
+        jobs::retry_job_handler,
+    // Uploads
+        uploads::upload_options_handler,
+        uploads::create_upload_handler,
+        uploads::get_upload_offset_handler,
+        uploads::append_upload_handler,
+        uploads::delete_upload_handler,
     ),
     // components(
     //     schemas(
@@ -38,6 +108,15 @@ use crate::folders;
         (name = "fly::api", description = "Fly API", external_docs(url = "http://more.about.our.apis", description = "More about our APIs")),
         (name = "Authentication", description = "Authentication related endpoints"),
         (name = "Files", description = "File management endpoints"),
+        (name = "Folders", description = "Folder management endpoints"),
+        (name = "Trash", description = "Trash and restore endpoints"),
+        (name = "Shares", description = "Public share link endpoints"),
+        (name = "Sharing", description = "Sharing between users"),
+        (name = "Sessions", description = "Devices the user is logged in on"),
+        (name = "Groups", description = "Groups and their members"),
+        (name = "Search", description = "Full-text search over files"),
+        (name = "Jobs", description = "Background jobs, for admins"),
+        (name = "Uploads", description = "Resumable upload endpoints (tus 1.0)"),
     ),
     external_docs(url = "http://more.about.our.apis", description = "More about our APIs")
 )]
@@ -0,0 +1,41 @@
+use crate::mail::create_mail_service;
+use crate::schema::users;
+
+use super::*;
+
+async fn register(app: &impl TestApp, username: &str, email: &str) -> StatusCode {
+    let req = test::TestRequest::post()
+        .uri("/api/auth/register")
+        .set_json(json!({"username": username, "email": email, "password": PASSWORD}))
+        .to_request();
+    test::call_service(app, req).await.status()
+}
+
+#[actix_web::test]
+async fn usernames_and_email_addresses_are_registered_once() {
+    let db = TestDb::new();
+    let app = init_app(&db, db.config()).await;
+
+    assert_eq!(register(&app, "bob", "Bob@Example.com").await, StatusCode::CREATED);
+    assert_eq!(register(&app, "bob", "other@example.com").await, StatusCode::CONFLICT);
+    assert_eq!(register(&app, "robert", "bob@example.COM").await, StatusCode::CONFLICT);
+    assert_eq!(register(&app, "robert", "robert@example.com").await, StatusCode::CREATED);
+
+    // The index holds against writes around the check as well
+    let duplicate = diesel::update(users::table.filter(users::username.eq("robert")))
+        .set(users::email_address.eq("BOB@example.com"))
+        .execute(&mut db.pool.get().unwrap());
+    assert!(duplicate.is_err());
+}
+
+#[actix_web::test]
+async fn mails_are_sent_in_production_mode() {
+    let db = TestDb::new();
+    let mut config = db.config();
+    for service in ["log", "file"] {
+        config.mail_service = service.to_string();
+        assert!(create_mail_service(&config, true).is_err());
+    }
+    config.mail_service = "log".to_string();
+    assert!(create_mail_service(&config, false).is_ok());
+}
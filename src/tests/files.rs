@@ -0,0 +1,154 @@
+use actix_web::http::header::{CONTENT_RANGE, CONTENT_TYPE, ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED, RANGE};
+
+use crate::previews::thumbnail_location;
+use crate::schema::files;
+use crate::shared::common::StorageService;
+
+use super::*;
+
+#[actix_web::test]
+async fn register_login_upload_and_download() {
+    let db = TestDb::new();
+    let app = init_app(&db, db.config()).await;
+    let session = login(&app, "bob").await;
+    let token = session["token"].as_str().unwrap();
+
+    let req = test::TestRequest::get().uri("/api/auth/user").insert_header(bearer(token)).to_request();
+    let user: Value = test::read_body_json(test::call_service(&app, req).await).await;
+    assert_eq!(user["username"], "bob");
+
+    let file_id = create_file(&app, &session).await;
+    assert_eq!(upload(&app, token, &file_id, b"hello world").await, StatusCode::OK);
+    assert_eq!(download(&app, token, &file_id).await, (StatusCode::OK, b"hello world".to_vec()));
+
+    // Without a token nothing is served
+    let req = test::TestRequest::get().uri(&format!("/api/files/{}/contents", file_id)).to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[actix_web::test]
+async fn range_requests_return_parts_of_the_contents() {
+    let db = TestDb::new();
+    let app = init_app(&db, db.config()).await;
+    let session = login(&app, "bob").await;
+    let token = session["token"].as_str().unwrap();
+    let file_id = create_file(&app, &session).await;
+    upload(&app, token, &file_id, b"hello world").await;
+
+    let req = test::TestRequest::get()
+        .uri(&format!("/api/files/{}/contents", file_id))
+        .insert_header(bearer(token))
+        .insert_header((RANGE, "bytes=6-"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::PARTIAL_CONTENT);
+    assert_eq!(resp.headers().get(CONTENT_RANGE).unwrap(), "bytes 6-10/11");
+    assert_eq!(test::read_body(resp).await, "world");
+
+    let req = test::TestRequest::get()
+        .uri(&format!("/api/files/{}/contents", file_id))
+        .insert_header(bearer(token))
+        .insert_header((RANGE, "bytes=20-30"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::RANGE_NOT_SATISFIABLE);
+    assert_eq!(resp.headers().get(CONTENT_RANGE).unwrap(), "bytes */11");
+}
+
+#[actix_web::test]
+async fn conditional_requests_are_answered_with_not_modified() {
+    let db = TestDb::new();
+    let app = init_app(&db, db.config()).await;
+    let session = login(&app, "bob").await;
+    let token = session["token"].as_str().unwrap();
+    let file_id = create_file(&app, &session).await;
+    upload(&app, token, &file_id, b"hello world").await;
+
+    let req = test::TestRequest::get()
+        .uri(&format!("/api/files/{}/contents", file_id))
+        .insert_header(bearer(token))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    let etag = resp.headers().get(ETAG).unwrap().clone();
+    let last_modified = resp.headers().get(LAST_MODIFIED).unwrap().clone();
+
+    // Stored as UTC, so the header is the time of the upload wherever the service runs
+    let modified_at = chrono::DateTime::parse_from_rfc2822(last_modified.to_str().unwrap()).unwrap();
+    assert!((chrono::Utc::now() - modified_at.to_utc()).num_seconds().abs() < 60);
+
+    let req = test::TestRequest::get()
+        .uri(&format!("/api/files/{}/contents", file_id))
+        .insert_header(bearer(token))
+        .insert_header((IF_NONE_MATCH, etag))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::NOT_MODIFIED);
+
+    let req = test::TestRequest::get()
+        .uri(&format!("/api/files/{}/contents", file_id))
+        .insert_header(bearer(token))
+        .insert_header((IF_MODIFIED_SINCE, last_modified))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::NOT_MODIFIED);
+}
+
+fn png(width: u32, height: u32) -> Vec<u8> {
+    let mut data = std::io::Cursor::new(Vec::new());
+    image::DynamicImage::new_rgb8(width, height).write_to(&mut data, image::ImageFormat::Png).unwrap();
+    data.into_inner()
+}
+
+#[actix_web::test]
+async fn thumbnails_are_made_on_request_and_follow_the_contents() {
+    let db = TestDb::new();
+    let app = init_app(&db, db.config()).await;
+    let session = login(&app, "bob").await;
+    let token = session["token"].as_str().unwrap();
+    let file_id = create_file(&app, &session).await;
+    let thumbnail = |query: &str| {
+        test::TestRequest::get()
+            .uri(&format!("/api/files/{}/thumbnail{}", file_id, query))
+            .insert_header(bearer(token))
+            .to_request()
+    };
+
+    upload(&app, token, &file_id, b"hello world").await;
+    assert_eq!(test::call_service(&app, thumbnail("")).await.status(), StatusCode::NOT_FOUND);
+
+    upload(&app, token, &file_id, &png(600, 300)).await;
+    assert_eq!(test::call_service(&app, thumbnail("?size=100")).await.status(), StatusCode::BAD_REQUEST);
+    let resp = test::call_service(&app, thumbnail("?size=128&format=webp")).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(resp.headers().get(CONTENT_TYPE).unwrap(), "image/webp");
+    let etag = resp.headers().get(ETAG).unwrap().clone();
+    let image = image::load_from_memory(&test::read_body(resp).await).unwrap();
+    assert_eq!((image.width(), image.height()), (128, 64));
+
+    // Made once, the other sizes and formats are stored already
+    let blob_hash: Option<String> = files::table.filter(files::id.eq(&file_id)).select(files::blob_hash).first(&mut db.pool.get().unwrap()).unwrap();
+    let (path, name) = BlobStore::location(&blob_hash.unwrap());
+    let (thumbnail_path, _) = thumbnail_location(&path, &name, 128, Default::default());
+    let thumbnails = db.storage.list_file_names(&thumbnail_path).await.unwrap();
+    assert_eq!(thumbnails.iter().filter(|thumbnail| thumbnail.starts_with(&name)).count(), 6);
+    let resp = test::call_service(&app, thumbnail("")).await;
+    assert_eq!(resp.headers().get(CONTENT_TYPE).unwrap(), "image/jpeg");
+    assert_ne!(resp.headers().get(ETAG).unwrap(), &etag);
+
+    let req = test::TestRequest::get()
+        .uri(&format!("/api/files/{}/thumbnail?size=128&format=webp", file_id))
+        .insert_header(bearer(token))
+        .insert_header((IF_NONE_MATCH, etag.clone()))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::NOT_MODIFIED);
+
+    // New contents, new thumbnails
+    upload(&app, token, &file_id, &png(300, 600)).await;
+    let req = test::TestRequest::get()
+        .uri(&format!("/api/files/{}/thumbnail?size=128&format=webp", file_id))
+        .insert_header(bearer(token))
+        .insert_header((IF_NONE_MATCH, etag))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let image = image::load_from_memory(&test::read_body(resp).await).unwrap();
+    assert_eq!((image.width(), image.height()), (64, 128));
+}
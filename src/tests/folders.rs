@@ -0,0 +1,177 @@
+use crate::folders::service::is_descendant_of;
+use crate::groups::dto::Owner;
+use crate::schema::file_folders;
+
+use super::*;
+
+async fn move_folder(app: &impl TestApp, token: &str, folder_id: &str, parent_folder_id: &str) -> StatusCode {
+    let req = test::TestRequest::put()
+        .uri(&format!("/api/folders/{}", folder_id))
+        .insert_header(bearer(token))
+        .set_json(json!({"title": "moved", "parentFolderId": parent_folder_id}))
+        .to_request();
+    test::call_service(app, req).await.status()
+}
+
+#[actix_web::test]
+async fn folders_are_not_moved_into_their_own_subtree() {
+    let db = TestDb::new();
+    let app = init_app(&db, db.config()).await;
+    let session = login(&app, "bob").await;
+    let token = session["token"].as_str().unwrap();
+    let root = session["user"]["folderId"].as_str().unwrap();
+
+    let a = create_folder(&app, token, root, "a").await;
+    let b = create_folder(&app, token, &a, "b").await;
+    let c = create_folder(&app, token, &b, "c").await;
+
+    assert_eq!(move_folder(&app, token, &a, &a).await, StatusCode::BAD_REQUEST);
+    assert_eq!(move_folder(&app, token, &a, &b).await, StatusCode::BAD_REQUEST);
+    assert_eq!(move_folder(&app, token, &a, &c).await, StatusCode::BAD_REQUEST);
+
+    // Moving down into a sibling's subtree or back up is fine
+    let d = create_folder(&app, token, root, "d").await;
+    assert_eq!(move_folder(&app, token, &d, &c).await, StatusCode::OK);
+    assert_eq!(move_folder(&app, token, &c, root).await, StatusCode::OK);
+    assert_eq!(move_folder(&app, token, &b, &c).await, StatusCode::OK);
+}
+
+#[actix_web::test]
+async fn looping_parents_end_the_descendant_check() {
+    let db = TestDb::new();
+    let app = init_app(&db, db.config()).await;
+    let session = login(&app, "bob").await;
+    let token = session["token"].as_str().unwrap();
+    let user_id = session["user"]["id"].as_i64().unwrap() as i32;
+    let root = session["user"]["folderId"].as_str().unwrap();
+
+    let a = create_folder(&app, token, root, "a").await;
+    let b = create_folder(&app, token, &a, "b").await;
+    let mut conn = db.pool.get().unwrap();
+    let owner = Owner::User(user_id);
+    assert!(is_descendant_of(&mut conn, &b, root, &owner).unwrap());
+    assert!(is_descendant_of(&mut conn, &b, &a, &owner).unwrap());
+    assert!(!is_descendant_of(&mut conn, &a, &b, &owner).unwrap());
+
+    // A loop written around the API is cut off instead of walked forever
+    diesel::update(file_folders::table.filter(file_folders::id.eq(&a)))
+        .set(file_folders::parent_folder_id.eq(&b))
+        .execute(&mut conn)
+        .unwrap();
+    assert!(is_descendant_of(&mut conn, &b, root, &owner).is_err());
+}
+
+async fn get_json(app: &impl TestApp, token: &str, uri: &str) -> (StatusCode, Value) {
+    let req = test::TestRequest::get().uri(uri).insert_header(bearer(token)).to_request();
+    let resp = test::call_service(app, req).await;
+    let status = resp.status();
+    match status {
+        StatusCode::OK => (status, test::read_body_json(resp).await),
+        _ => (status, Value::Null),
+    }
+}
+
+// Titles of a list of folders or of tree nodes
+fn titles(folders: &Value) -> Vec<&str> {
+    folders.as_array().unwrap().iter().map(|folder| folder["title"].as_str().unwrap_or_else(|| folder["folder"]["title"].as_str().unwrap())).collect()
+}
+
+#[actix_web::test]
+async fn trees_are_cut_at_their_depth_and_list_files_on_request() {
+    let db = TestDb::new();
+    let app = init_app(&db, db.config()).await;
+    let session = login(&app, "bob").await;
+    let token = session["token"].as_str().unwrap();
+    let root = session["user"]["folderId"].as_str().unwrap();
+
+    let a = create_folder(&app, token, root, "a").await;
+    create_folder(&app, token, root, "z").await;
+    let b = create_folder(&app, token, &a, "b").await;
+    let c = create_folder(&app, token, &b, "c").await;
+    let file_id = create_file_in(&app, token, &b).await;
+
+    let (status, tree) = get_json(&app, token, &format!("/api/folders/{}/tree?depth=2", root)).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(tree["id"], root);
+    assert_eq!(tree["folder"], Value::Null);
+    assert_eq!(titles(&tree["folders"]), ["a", "z"]);
+    assert_eq!(titles(&tree["folders"][0]["folders"]), ["b"]);
+    assert_eq!(tree["folders"][0]["folders"][0]["folders"], json!([]));
+    assert_eq!(tree["folders"][0]["folders"][0]["files"], json!([]));
+
+    let (_, tree) = get_json(&app, token, &format!("/api/folders/{}/tree?files=true", a)).await;
+    assert_eq!(tree["folder"]["title"], "a");
+    let b_tree = &tree["folders"][0];
+    assert_eq!(titles(&b_tree["folders"]), ["c"]);
+    assert_eq!(b_tree["files"][0]["id"], file_id.as_str());
+
+    // Trashed folders leave the tree
+    let req = test::TestRequest::delete().uri(&format!("/api/folders/{}", c)).insert_header(bearer(token)).to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::NO_CONTENT);
+    let (_, tree) = get_json(&app, token, &format!("/api/folders/{}/tree", a)).await;
+    assert_eq!(titles(&tree["folders"]), ["b"]);
+    assert_eq!(tree["folders"][0]["folders"], json!([]));
+}
+
+#[actix_web::test]
+async fn ancestors_stop_at_the_root_or_the_shared_folder() {
+    let db = TestDb::new();
+    let app = init_app(&db, db.config()).await;
+    let bob = login(&app, "bob").await;
+    let alice = login(&app, "alice").await;
+    let (token, alice_token) = (bob["token"].as_str().unwrap(), alice["token"].as_str().unwrap());
+    let root = bob["user"]["folderId"].as_str().unwrap();
+
+    let a = create_folder(&app, token, root, "a").await;
+    let b = create_folder(&app, token, &a, "b").await;
+    let c = create_folder(&app, token, &b, "c").await;
+
+    let (_, ancestors) = get_json(&app, token, &format!("/api/folders/{}/ancestors", c)).await;
+    assert_eq!(titles(&ancestors), ["a", "b", "c"]);
+    let (_, ancestors) = get_json(&app, token, &format!("/api/folders/{}/ancestors", root)).await;
+    assert_eq!(ancestors, json!([]));
+
+    // Alice only sees the folders from the one shared with her down
+    assert_eq!(get_json(&app, alice_token, &format!("/api/folders/{}/ancestors", c)).await.0, StatusCode::NOT_FOUND);
+    let req = test::TestRequest::post()
+        .uri("/api/sharing")
+        .insert_header(bearer(token))
+        .set_json(json!({"folderId": b, "username": "alice", "permission": 1}))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::CREATED);
+    let (_, ancestors) = get_json(&app, alice_token, &format!("/api/folders/{}/ancestors", c)).await;
+    assert_eq!(titles(&ancestors), ["b", "c"]);
+    let (_, tree) = get_json(&app, alice_token, &format!("/api/folders/{}/tree", b)).await;
+    assert_eq!(titles(&tree["folders"]), ["c"]);
+    assert_eq!(get_json(&app, alice_token, &format!("/api/folders/{}/tree", a)).await.0, StatusCode::NOT_FOUND);
+}
+
+#[actix_web::test]
+async fn archives_hold_every_level_of_the_tree() {
+    let db = TestDb::new();
+    let app = init_app(&db, db.config()).await;
+    let session = login(&app, "bob").await;
+    let token = session["token"].as_str().unwrap();
+
+    // Deeper than the ancestor walks go
+    let mut folder_id = session["user"]["folderId"].as_str().unwrap().to_string();
+    for level in 0..300 {
+        folder_id = create_folder(&app, token, &folder_id, &format!("{}", level)).await;
+    }
+    let file_id = create_file_in(&app, token, &folder_id).await;
+    upload(&app, token, &file_id, b"hello world").await;
+
+    let req = test::TestRequest::get()
+        .uri(&format!("/api/folders/{}/archive", session["user"]["folderId"].as_str().unwrap()))
+        .insert_header(bearer(token))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let archive = async_zip::base::read::mem::ZipFileReader::new(test::read_body(resp).await.to_vec()).await.unwrap();
+    let paths: Vec<&str> = archive.file().entries().iter().map(|entry| entry.filename().as_str().unwrap()).collect();
+
+    let deepest = (0..300).map(|level| level.to_string()).collect::<Vec<_>>().join("/");
+    assert_eq!(paths.len(), 301);
+    assert!(paths.contains(&format!("{}/", deepest).as_str()));
+    assert!(paths.contains(&format!("{}/notes.txt", deepest).as_str()));
+}
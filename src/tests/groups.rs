@@ -0,0 +1,90 @@
+use super::*;
+
+async fn create_group(app: &impl TestApp, token: &str) -> Value {
+    let req = test::TestRequest::post().uri("/api/groups").insert_header(bearer(token)).set_json(json!({"name": "team"})).to_request();
+    let resp = test::call_service(app, req).await;
+    assert_eq!(resp.status(), StatusCode::CREATED);
+    let created: Value = test::read_body_json(resp).await;
+
+    let req = test::TestRequest::get().uri(&format!("/api/groups/{}", created["id"].as_str().unwrap())).insert_header(bearer(token)).to_request();
+    let resp = test::call_service(app, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    test::read_body_json(resp).await
+}
+
+async fn add_member(app: &impl TestApp, token: &str, group: &Value, username: &str, role: i32) -> StatusCode {
+    let req = test::TestRequest::post()
+        .uri(&format!("/api/groups/{}/members", group["id"].as_str().unwrap()))
+        .insert_header(bearer(token))
+        .set_json(json!({"username": username, "role": role}))
+        .to_request();
+    test::call_service(app, req).await.status()
+}
+
+async fn remove_member(app: &impl TestApp, token: &str, group: &Value, member: &Value) -> StatusCode {
+    let req = test::TestRequest::delete()
+        .uri(&format!("/api/groups/{}/members/{}", group["id"].as_str().unwrap(), member["user"]["id"]))
+        .insert_header(bearer(token))
+        .to_request();
+    test::call_service(app, req).await.status()
+}
+
+async fn create_group_file(app: &impl TestApp, token: &str, group: &Value) -> StatusCode {
+    let req = test::TestRequest::post()
+        .uri("/api/files")
+        .insert_header(bearer(token))
+        .set_json(json!({"accessLevel": 0, "title": "notes", "folderId": group["folderId"]}))
+        .to_request();
+    test::call_service(app, req).await.status()
+}
+
+#[actix_web::test]
+async fn roles_decide_what_members_can_do() {
+    let db = TestDb::new();
+    let app = init_app(&db, db.config()).await;
+    let bob = login(&app, "bob").await;
+    let alice = login(&app, "alice").await;
+    let carol = login(&app, "carol").await;
+    let (bob_token, alice_token, carol_token) = (bob["token"].as_str().unwrap(), alice["token"].as_str().unwrap(), carol["token"].as_str().unwrap());
+    let group = create_group(&app, bob_token).await;
+
+    // Outsiders don't see the group at all
+    let req = test::TestRequest::get().uri(&format!("/api/groups/{}/members", group["id"].as_str().unwrap())).insert_header(bearer(alice_token)).to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::NOT_FOUND);
+    assert_eq!(create_group_file(&app, alice_token, &group).await, StatusCode::NOT_FOUND);
+
+    // Viewers read, editors also write, only admins manage the members
+    assert_eq!(add_member(&app, bob_token, &group, "alice", 1).await, StatusCode::CREATED);
+    assert_eq!(add_member(&app, bob_token, &group, "carol", 2).await, StatusCode::CREATED);
+    let req = test::TestRequest::get().uri(&format!("/api/groups/{}/members", group["id"].as_str().unwrap())).insert_header(bearer(alice_token)).to_request();
+    let members: Value = test::read_body_json(test::call_service(&app, req).await).await;
+    assert_eq!(members.as_array().unwrap().len(), 3);
+    assert_eq!(create_group_file(&app, alice_token, &group).await, StatusCode::NOT_FOUND);
+    assert_eq!(create_group_file(&app, carol_token, &group).await, StatusCode::CREATED);
+    assert_eq!(add_member(&app, carol_token, &group, "alice", 2).await, StatusCode::NOT_FOUND);
+    assert_eq!(remove_member(&app, carol_token, &group, &alice).await, StatusCode::NOT_FOUND);
+
+    // Anyone can leave, admins remove others
+    assert_eq!(remove_member(&app, alice_token, &group, &alice).await, StatusCode::NO_CONTENT);
+    assert_eq!(remove_member(&app, bob_token, &group, &carol).await, StatusCode::NO_CONTENT);
+    assert_eq!(create_group_file(&app, carol_token, &group).await, StatusCode::NOT_FOUND);
+}
+
+#[actix_web::test]
+async fn the_last_admin_stays_in_the_group() {
+    let db = TestDb::new();
+    let app = init_app(&db, db.config()).await;
+    let bob = login(&app, "bob").await;
+    let alice = login(&app, "alice").await;
+    let (bob_token, alice_token) = (bob["token"].as_str().unwrap(), alice["token"].as_str().unwrap());
+    let group = create_group(&app, bob_token).await;
+
+    assert_eq!(remove_member(&app, bob_token, &group, &bob).await, StatusCode::BAD_REQUEST);
+    assert_eq!(add_member(&app, bob_token, &group, "bob", 2).await, StatusCode::BAD_REQUEST);
+
+    // With a second admin either of them can step down
+    assert_eq!(add_member(&app, bob_token, &group, "alice", 3).await, StatusCode::CREATED);
+    assert_eq!(add_member(&app, bob_token, &group, "bob", 2).await, StatusCode::CREATED);
+    assert_eq!(remove_member(&app, alice_token, &group, &alice).await, StatusCode::BAD_REQUEST);
+    assert_eq!(remove_member(&app, bob_token, &group, &bob).await, StatusCode::NO_CONTENT);
+}
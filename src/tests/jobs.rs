@@ -0,0 +1,86 @@
+use crate::jobs::dto::{Job, JobDto, JOB_FAILED, JOB_QUEUED, JOB_RUNNING};
+use crate::jobs::service::{claim_next_job, enqueue_job, fail_job, get_job, reset_running_jobs, retry_job};
+use crate::schema::jobs;
+
+use super::*;
+
+fn thumbnails_job() -> Job {
+    Job::CreateThumbnails { path: "blobs".to_string(), name: "image".to_string() }
+}
+
+// Lets the wait for the next attempt pass
+fn make_due(conn: &mut SqliteConnection, job_id: &str) {
+    diesel::update(jobs::table.filter(jobs::id.eq(job_id)))
+        .set(jobs::run_at.eq(chrono::Utc::now().naive_utc()))
+        .execute(conn)
+        .unwrap();
+}
+
+fn claim(conn: &mut SqliteConnection) -> Option<JobDto> {
+    claim_next_job(conn).unwrap()
+}
+
+#[actix_web::test]
+async fn failed_attempts_are_retried_later_until_the_job_fails_for_good() {
+    let db = TestDb::new();
+    let mut conn = db.pool.get().unwrap();
+    let job_id = enqueue_job(&mut conn, &thumbnails_job(), 1).unwrap();
+
+    for attempt in 1..=4 {
+        let job = claim(&mut conn).unwrap();
+        assert_eq!((job.id.as_str(), job.status, job.attempts), (job_id.as_str(), JOB_RUNNING, attempt));
+        assert!(claim(&mut conn).is_none());
+
+        let failed_at = chrono::Utc::now().naive_utc();
+        fail_job(&mut conn, &job, "storage is down", false).unwrap();
+        let job = get_job(&mut conn, &job_id).unwrap();
+        assert_eq!((job.status, job.last_error.as_deref()), (JOB_QUEUED, Some("storage is down")));
+        let delay = (job.run_at - failed_at).num_seconds();
+        assert!((30 << (attempt - 1)) - 1 <= delay && delay <= 30 << (attempt - 1), "attempt {} waits {}s", attempt, delay);
+        // Not due before the delay passed
+        assert!(claim(&mut conn).is_none());
+        make_due(&mut conn, &job_id);
+    }
+
+    // The last attempt dead-letters the job, only an admin brings it back
+    let job = claim(&mut conn).unwrap();
+    assert_eq!(job.attempts, job.max_attempts);
+    fail_job(&mut conn, &job, "storage is still down", false).unwrap();
+    assert_eq!(get_job(&mut conn, &job_id).unwrap().status, JOB_FAILED);
+    make_due(&mut conn, &job_id);
+    assert!(claim(&mut conn).is_none());
+
+    assert_eq!(retry_job(&mut conn, &job_id, 1).unwrap(), 1);
+    let job = claim(&mut conn).unwrap();
+    assert_eq!((job.id, job.attempts), (job_id, 1));
+}
+
+#[actix_web::test]
+async fn permanent_failures_are_not_retried() {
+    let db = TestDb::new();
+    let mut conn = db.pool.get().unwrap();
+    let job_id = enqueue_job(&mut conn, &thumbnails_job(), 1).unwrap();
+
+    let job = claim(&mut conn).unwrap();
+    fail_job(&mut conn, &job, "not an image", true).unwrap();
+    let job = get_job(&mut conn, &job_id).unwrap();
+    assert_eq!((job.status, job.attempts), (JOB_FAILED, 1));
+    // Only failed jobs are retried
+    let queued = enqueue_job(&mut conn, &thumbnails_job(), 1).unwrap();
+    assert_eq!(retry_job(&mut conn, &queued, 1).unwrap(), 0);
+}
+
+#[actix_web::test]
+async fn jobs_running_when_the_service_stopped_are_queued_again() {
+    let db = TestDb::new();
+    let mut conn = db.pool.get().unwrap();
+    let running = enqueue_job(&mut conn, &thumbnails_job(), 1).unwrap();
+    claim(&mut conn).unwrap();
+    let queued = enqueue_job(&mut conn, &thumbnails_job(), 1).unwrap();
+
+    assert_eq!(reset_running_jobs(&mut conn).unwrap(), 1);
+    assert_eq!(get_job(&mut conn, &queued).unwrap().status, JOB_QUEUED);
+    // The interrupted attempt still counts
+    let job = claim(&mut conn).unwrap();
+    assert_eq!((job.id, job.attempts), (running, 2));
+}
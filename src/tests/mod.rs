@@ -0,0 +1,176 @@
+// Runs requests through the whole app, each test on its own database with the memory store
+
+mod auth;
+mod files;
+mod folders;
+mod groups;
+mod jobs;
+mod quota;
+mod search;
+mod sessions;
+mod shares;
+mod sharing;
+mod trash;
+mod uploads;
+
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use actix_http::Request;
+use actix_web::dev::{Service, ServiceResponse};
+use actix_web::http::StatusCode;
+use actix_web::http::header::{AUTHORIZATION, CONTENT_TYPE};
+use actix_web::{test, web, App};
+use diesel::prelude::*;
+use diesel::r2d2::{self, ConnectionManager};
+use diesel_migrations::MigrationHarness;
+use serde_json::{json, Value};
+
+use crate::shared::common::{AppState, Config, ConnectionOptions, DbPool};
+use crate::storage::blob_store::BlobStore;
+use crate::storage::memory_store::MemoryStore;
+
+const PASSWORD: &str = "password1";
+
+// A database file of its own, removed again when the test ends, and the storage next to it
+struct TestDb {
+    path: PathBuf,
+    pool: DbPool,
+    storage: MemoryStore,
+}
+
+impl TestDb {
+    fn new() -> TestDb {
+        let path = std::env::temp_dir().join(format!("fly-test-{}.db", uuid::Uuid::new_v4()));
+        let manager = ConnectionManager::<SqliteConnection>::new(path.to_string_lossy());
+        let pool = r2d2::Pool::builder()
+            .connection_customizer(Box::new(ConnectionOptions))
+            .build(manager)
+            .expect("Failed to create pool.");
+        pool.get().unwrap().run_pending_migrations(crate::MIGRATIONS).expect("Failed to run migrations");
+        TestDb { path, pool, storage: MemoryStore::new() }
+    }
+
+    fn config(&self) -> Config {
+        Config {
+            database_url: self.path.to_string_lossy().to_string(),
+            jwt_secret: "secret".to_string(),
+            _jwt_expires_in: "60m".to_string(),
+            _jwt_maxage: 60,
+            storage_service: "memory".to_string(),
+            trash_retention_days: 30,
+            upload_expiry_hours: 24,
+            default_storage_quota: 1024 * 1024,
+            job_workers: 0,
+            admin_user_ids: Vec::new(),
+            mail_service: "log".to_string(),
+            mail_from: "Fly <no-reply@localhost>".to_string(),
+            public_url: "http://localhost:8090".to_string(),
+            require_email_verification: false,
+            email_verification_hours: 24,
+            password_reset_url: "http://localhost:8090/reset-password".to_string(),
+            password_reset_minutes: 60,
+            refresh_token_days: 30,
+        }
+    }
+}
+
+impl Drop for TestDb {
+    fn drop(&mut self) {
+        for suffix in ["", "-wal", "-shm"] {
+            let _ = std::fs::remove_file(format!("{}{}", self.path.to_string_lossy(), suffix));
+        }
+    }
+}
+
+// The app as the tests see it
+trait TestApp: Service<Request, Response = ServiceResponse, Error = actix_web::Error> {}
+
+impl<S: Service<Request, Response = ServiceResponse, Error = actix_web::Error>> TestApp for S {}
+
+async fn init_app(db: &TestDb, config: Config) -> impl TestApp {
+    let storage = Arc::new(db.storage.clone());
+    let blobs = Arc::new(BlobStore::new(storage.clone()));
+    // Production mode only for the app, its mails go to the log
+    let mailer = crate::mail::create_mail_service(&config, false).unwrap();
+    test::init_service(
+        App::new()
+            .app_data(web::Data::new(AppState::new(db.pool.clone(), config, storage, blobs, mailer, true)))
+            .service(web::scope("/api").configure(crate::api_config)),
+    )
+    .await
+}
+
+fn bearer(token: &str) -> (actix_web::http::header::HeaderName, String) {
+    (AUTHORIZATION, format!("Bearer {}", token))
+}
+
+// Registers and logs in a user, returns the login response
+async fn login(app: &impl TestApp, username: &str) -> Value {
+    let req = test::TestRequest::post()
+        .uri("/api/auth/register")
+        .set_json(json!({"username": username, "email": format!("{}@localhost", username), "password": PASSWORD}))
+        .to_request();
+    assert_eq!(test::call_service(app, req).await.status(), StatusCode::CREATED);
+
+    let req = test::TestRequest::post()
+        .uri("/api/auth/login")
+        .set_json(json!({"username": username, "password": PASSWORD}))
+        .to_request();
+    let resp = test::call_service(app, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    test::read_body_json(resp).await
+}
+
+// Creates an empty file in the user's root folder, returns its id
+async fn create_file(app: &impl TestApp, session: &Value) -> String {
+    let token = session["token"].as_str().unwrap();
+    create_file_in(app, token, session["user"]["folderId"].as_str().unwrap()).await
+}
+
+async fn create_file_in(app: &impl TestApp, token: &str, folder_id: &str) -> String {
+    let req = test::TestRequest::post()
+        .uri("/api/files")
+        .insert_header(bearer(token))
+        .set_json(json!({"accessLevel": 0, "title": "notes", "folderId": folder_id}))
+        .to_request();
+    let resp = test::call_service(app, req).await;
+    assert_eq!(resp.status(), StatusCode::CREATED);
+    let created: Value = test::read_body_json(resp).await;
+    created["id"].as_str().unwrap().to_string()
+}
+
+async fn create_folder(app: &impl TestApp, token: &str, parent_folder_id: &str, title: &str) -> String {
+    let req = test::TestRequest::post()
+        .uri("/api/folders")
+        .insert_header(bearer(token))
+        .set_json(json!({"title": title, "parentFolderId": parent_folder_id}))
+        .to_request();
+    let resp = test::call_service(app, req).await;
+    assert_eq!(resp.status(), StatusCode::CREATED);
+    let created: Value = test::read_body_json(resp).await;
+    created["id"].as_str().unwrap().to_string()
+}
+
+async fn upload(app: &impl TestApp, token: &str, file_id: &str, contents: &[u8]) -> StatusCode {
+    let mut body = b"--boundary\r\nContent-Disposition: form-data; name=\"file\"; filename=\"notes.txt\"\r\nContent-Type: text/plain\r\n\r\n".to_vec();
+    body.extend_from_slice(contents);
+    body.extend_from_slice(b"\r\n--boundary--\r\n");
+    let req = test::TestRequest::post()
+        .uri(&format!("/api/files/{}/upload", file_id))
+        .insert_header(bearer(token))
+        .insert_header((CONTENT_TYPE, "multipart/form-data; boundary=boundary"))
+        .set_payload(body)
+        .to_request();
+    test::call_service(app, req).await.status()
+}
+
+async fn download(app: &impl TestApp, token: &str, file_id: &str) -> (StatusCode, Vec<u8>) {
+    let req = test::TestRequest::get()
+        .uri(&format!("/api/files/{}/contents", file_id))
+        .insert_header(bearer(token))
+        .to_request();
+    let resp = test::call_service(app, req).await;
+    let status = resp.status();
+    (status, test::read_body(resp).await.to_vec())
+}
@@ -0,0 +1,55 @@
+use crate::auth::service::{get_available_space, reserve_storage};
+
+use super::*;
+
+#[actix_web::test]
+async fn uploads_over_the_quota_are_refused() {
+    let db = TestDb::new();
+    let mut config = db.config();
+    config.default_storage_quota = 16;
+    let app = init_app(&db, config).await;
+    let session = login(&app, "bob").await;
+    let token = session["token"].as_str().unwrap();
+    let file_id = create_file(&app, &session).await;
+
+    assert_eq!(upload(&app, token, &file_id, &[b'x'; 64]).await, StatusCode::PAYLOAD_TOO_LARGE);
+    assert_eq!(download(&app, token, &file_id).await.0, StatusCode::NOT_FOUND);
+    assert_eq!(upload(&app, token, &file_id, &[b'x'; 16]).await, StatusCode::OK);
+}
+
+#[actix_web::test]
+async fn earlier_versions_count_against_the_quota() {
+    let db = TestDb::new();
+    let mut config = db.config();
+    config.default_storage_quota = 16;
+    let app = init_app(&db, config).await;
+    let session = login(&app, "bob").await;
+    let token = session["token"].as_str().unwrap();
+    let file_id = create_file(&app, &session).await;
+
+    assert_eq!(upload(&app, token, &file_id, &[b'x'; 10]).await, StatusCode::OK);
+    // The first version is kept, so only 6 bytes are left for the second one
+    assert_eq!(upload(&app, token, &file_id, &[b'y'; 10]).await, StatusCode::PAYLOAD_TOO_LARGE);
+    assert_eq!(upload(&app, token, &file_id, &[b'y'; 6]).await, StatusCode::OK);
+
+    let req = test::TestRequest::get().uri("/api/auth/user/usage").insert_header(bearer(token)).to_request();
+    let usage: Value = test::read_body_json(test::call_service(&app, req).await).await;
+    assert_eq!(usage["used"], 16);
+    assert_eq!(usage["versions"], 10);
+    assert_eq!(usage["available"], 0);
+}
+
+#[actix_web::test]
+async fn storage_is_only_reserved_while_it_fits() {
+    let db = TestDb::new();
+    let mut conn = db.pool.get().unwrap();
+    let app = init_app(&db, db.config()).await;
+    let session = login(&app, "bob").await;
+    let user_id = session["user"]["id"].as_i64().unwrap() as i32;
+
+    assert!(reserve_storage(&mut conn, user_id, 10, 16).unwrap());
+    assert!(!reserve_storage(&mut conn, user_id, 7, 16).unwrap());
+    assert!(reserve_storage(&mut conn, user_id, 6, 16).unwrap());
+    assert!(reserve_storage(&mut conn, user_id, -16, 16).unwrap());
+    assert_eq!(get_available_space(&mut conn, user_id, 16).unwrap(), 16);
+}
@@ -0,0 +1,69 @@
+use crate::schema::file_folders;
+
+use super::*;
+
+async fn search(app: &impl TestApp, token: &str, query: &str) -> Vec<String> {
+    let req = test::TestRequest::get()
+        .uri(&format!("/api/search?q={}", percent_encoding::utf8_percent_encode(query, percent_encoding::NON_ALPHANUMERIC)))
+        .insert_header(bearer(token))
+        .to_request();
+    let resp = test::call_service(app, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let results: Value = test::read_body_json(resp).await;
+    results.as_array().unwrap().iter().map(|result| result["file"]["id"].as_str().unwrap().to_string()).collect()
+}
+
+fn set_folder_active(db: &TestDb, folder_id: &str, active: bool) {
+    diesel::update(file_folders::table.filter(file_folders::id.eq(folder_id)))
+        .set(file_folders::active.eq(active))
+        .execute(&mut db.pool.get().unwrap())
+        .unwrap();
+}
+
+#[actix_web::test]
+async fn shared_folders_in_the_trash_share_nothing() {
+    let db = TestDb::new();
+    let app = init_app(&db, db.config()).await;
+    let bob = login(&app, "bob").await;
+    let alice = login(&app, "alice").await;
+    let (token, alice_token) = (bob["token"].as_str().unwrap(), alice["token"].as_str().unwrap());
+
+    let a = create_folder(&app, token, bob["user"]["folderId"].as_str().unwrap(), "a").await;
+    let b = create_folder(&app, token, &a, "b").await;
+    let file_id = create_file_in(&app, token, &b).await;
+    assert_eq!(search(&app, alice_token, "notes").await, Vec::<String>::new());
+
+    let req = test::TestRequest::post()
+        .uri("/api/sharing")
+        .insert_header(bearer(token))
+        .set_json(json!({"folderId": a, "username": "alice", "permission": 1}))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::CREATED);
+    assert_eq!(search(&app, alice_token, "notes").await, [file_id.as_str()]);
+    assert_eq!(search(&app, alice_token, "not*").await, [file_id.as_str()]);
+
+    // Either the folder in between or the shared folder itself going to the trash hides the file
+    set_folder_active(&db, &b, false);
+    assert_eq!(search(&app, alice_token, "notes").await, Vec::<String>::new());
+    set_folder_active(&db, &b, true);
+    set_folder_active(&db, &a, false);
+    assert_eq!(search(&app, alice_token, "notes").await, Vec::<String>::new());
+    assert_eq!(search(&app, token, "notes").await, [file_id.as_str()]);
+}
+
+#[actix_web::test]
+async fn queries_with_fts5_syntax_are_searched_as_text() {
+    let db = TestDb::new();
+    let app = init_app(&db, db.config()).await;
+    let session = login(&app, "bob").await;
+    let token = session["token"].as_str().unwrap();
+    create_file(&app, &session).await;
+
+    for query in ["notes OR", "NEAR(notes", "\"notes", "-notes", "notes*\"", "title:notes"] {
+        search(&app, token, query).await;
+    }
+
+    // Nothing is left to search for
+    let req = test::TestRequest::get().uri("/api/search?q=*").insert_header(bearer(token)).to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::BAD_REQUEST);
+}
@@ -0,0 +1,67 @@
+use super::*;
+
+async fn refresh(app: &impl TestApp, refresh_token: &str) -> ServiceResponse {
+    let req = test::TestRequest::post()
+        .uri("/api/auth/refresh")
+        .set_json(json!({"refreshToken": refresh_token}))
+        .to_request();
+    test::call_service(app, req).await
+}
+
+async fn refreshed(app: &impl TestApp, refresh_token: &str) -> Value {
+    let resp = refresh(app, refresh_token).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    test::read_body_json(resp).await
+}
+
+async fn get_user(app: &impl TestApp, token: &str) -> StatusCode {
+    let req = test::TestRequest::get().uri("/api/auth/user").insert_header(bearer(token)).to_request();
+    test::call_service(app, req).await.status()
+}
+
+#[actix_web::test]
+async fn refresh_tokens_rotate_and_reuse_ends_the_session() {
+    let db = TestDb::new();
+    let app = init_app(&db, db.config()).await;
+    let session = login(&app, "bob").await;
+    let first = session["refreshToken"].as_str().unwrap();
+
+    let second = refreshed(&app, first).await;
+    assert_ne!(second["refreshToken"], first);
+    let token = second["token"].as_str().unwrap();
+    assert_eq!(get_user(&app, token).await, StatusCode::OK);
+
+    // The first token was used already, so it must have been copied and the session ends
+    assert_eq!(refresh(&app, first).await.status(), StatusCode::UNAUTHORIZED);
+    assert_eq!(refresh(&app, second["refreshToken"].as_str().unwrap()).await.status(), StatusCode::UNAUTHORIZED);
+    assert_eq!(get_user(&app, token).await, StatusCode::UNAUTHORIZED);
+}
+
+#[actix_web::test]
+async fn reusing_any_earlier_refresh_token_ends_the_session() {
+    let db = TestDb::new();
+    let app = init_app(&db, db.config()).await;
+    let session = login(&app, "bob").await;
+    // A second device of the same user
+    let req = test::TestRequest::post()
+        .uri("/api/auth/login")
+        .set_json(json!({"username": "bob", "password": PASSWORD}))
+        .to_request();
+    let other: Value = test::read_body_json(test::call_service(&app, req).await).await;
+    let first = session["refreshToken"].as_str().unwrap();
+
+    let second = refreshed(&app, first).await;
+    let third = refreshed(&app, second["refreshToken"].as_str().unwrap()).await;
+    let token = third["token"].as_str().unwrap();
+    assert_eq!(get_user(&app, token).await, StatusCode::OK);
+
+    // Two rotations old, the token was still handed out by this session
+    assert_eq!(refresh(&app, first).await.status(), StatusCode::UNAUTHORIZED);
+    assert_eq!(refresh(&app, second["refreshToken"].as_str().unwrap()).await.status(), StatusCode::UNAUTHORIZED);
+    assert_eq!(refresh(&app, third["refreshToken"].as_str().unwrap()).await.status(), StatusCode::UNAUTHORIZED);
+    assert_eq!(get_user(&app, token).await, StatusCode::UNAUTHORIZED);
+
+    // Other sessions of the user live on
+    assert_eq!(get_user(&app, other["token"].as_str().unwrap()).await, StatusCode::OK);
+    assert_eq!(refresh(&app, other["refreshToken"].as_str().unwrap()).await.status(), StatusCode::OK);
+}
@@ -0,0 +1,57 @@
+use super::*;
+use crate::schema::share_links;
+
+async fn create_share_link(app: &impl TestApp, token: &str, link: Value) -> Value {
+    let req = test::TestRequest::post()
+        .uri("/api/shares")
+        .insert_header(bearer(token))
+        .set_json(link)
+        .to_request();
+    let resp = test::call_service(app, req).await;
+    assert_eq!(resp.status(), StatusCode::CREATED);
+    test::read_body_json(resp).await
+}
+
+async fn download_share(app: &impl TestApp, link: &Value) -> StatusCode {
+    let req = test::TestRequest::get()
+        .uri(&format!("/api/public/shares/{}/contents", link["token"].as_str().unwrap()))
+        .to_request();
+    test::call_service(app, req).await.status()
+}
+
+#[actix_web::test]
+async fn share_links_stop_at_their_download_limit() {
+    let db = TestDb::new();
+    let app = init_app(&db, db.config()).await;
+    let session = login(&app, "bob").await;
+    let token = session["token"].as_str().unwrap();
+    let file_id = create_file(&app, &session).await;
+    upload(&app, token, &file_id, b"hello world").await;
+
+    let link = create_share_link(&app, token, json!({"fileId": file_id, "maxDownloads": 2})).await;
+    assert_eq!(download_share(&app, &link).await, StatusCode::OK);
+    assert_eq!(download_share(&app, &link).await, StatusCode::OK);
+    assert_eq!(download_share(&app, &link).await, StatusCode::GONE);
+}
+
+#[actix_web::test]
+async fn share_links_stop_when_they_expire() {
+    let db = TestDb::new();
+    let app = init_app(&db, db.config()).await;
+    let session = login(&app, "bob").await;
+    let token = session["token"].as_str().unwrap();
+    let file_id = create_file(&app, &session).await;
+    upload(&app, token, &file_id, b"hello world").await;
+
+    let expires_at = chrono::Utc::now().naive_utc() + chrono::Duration::hours(1);
+    let link = create_share_link(&app, token, json!({"fileId": file_id, "expiresAt": expires_at})).await;
+    assert_eq!(download_share(&app, &link).await, StatusCode::OK);
+
+    // Let the hour pass
+    let expired_at = chrono::Utc::now().naive_utc() - chrono::Duration::minutes(1);
+    diesel::update(share_links::table.filter(share_links::id.eq(link["id"].as_str().unwrap())))
+        .set(share_links::expires_at.eq(Some(expired_at)))
+        .execute(&mut db.pool.get().unwrap())
+        .unwrap();
+    assert_eq!(download_share(&app, &link).await, StatusCode::GONE);
+}
@@ -0,0 +1,112 @@
+use crate::files::dto::FileDto;
+use crate::folders::dto::FolderDto;
+use crate::schema::{file_folders, files};
+use crate::sharing::dto::{CreateUserShareDto, Permission};
+use crate::sharing::service::{create_user_share, get_file_permission, get_folder_permission};
+
+use super::*;
+
+async fn share(app: &impl TestApp, token: &str, item: Value) -> String {
+    let req = test::TestRequest::post()
+        .uri("/api/sharing")
+        .insert_header(bearer(token))
+        .set_json(item)
+        .to_request();
+    let resp = test::call_service(app, req).await;
+    assert_eq!(resp.status(), StatusCode::CREATED);
+    let created: Value = test::read_body_json(resp).await;
+    created["id"].as_str().unwrap().to_string()
+}
+
+fn file_permission(db: &TestDb, file_id: &str, user_id: i32) -> Option<Permission> {
+    let mut conn = db.pool.get().unwrap();
+    let file = files::table.filter(files::id.eq(file_id)).first::<FileDto>(&mut conn).unwrap();
+    get_file_permission(&mut conn, &file, user_id).unwrap()
+}
+
+fn folder_permission(db: &TestDb, folder_id: &str, user_id: i32) -> Option<Permission> {
+    let mut conn = db.pool.get().unwrap();
+    let folder = file_folders::table.filter(file_folders::id.eq(folder_id)).first::<FolderDto>(&mut conn).unwrap();
+    get_folder_permission(&mut conn, &folder, user_id).unwrap()
+}
+
+fn user_id(session: &Value) -> i32 {
+    session["user"]["id"].as_i64().unwrap() as i32
+}
+
+#[actix_web::test]
+async fn shares_of_the_item_or_a_folder_above_grant_access() {
+    let db = TestDb::new();
+    let app = init_app(&db, db.config()).await;
+    let bob = login(&app, "bob").await;
+    let alice = login(&app, "alice").await;
+    let (bob_token, alice_token) = (bob["token"].as_str().unwrap(), alice["token"].as_str().unwrap());
+
+    let a = create_folder(&app, bob_token, bob["user"]["folderId"].as_str().unwrap(), "a").await;
+    let b = create_folder(&app, bob_token, &a, "b").await;
+    let file_id = create_file_in(&app, bob_token, &b).await;
+    upload(&app, bob_token, &file_id, b"hello world").await;
+
+    assert_eq!(file_permission(&db, &file_id, user_id(&bob)), Some(Permission::Owner));
+    assert_eq!(file_permission(&db, &file_id, user_id(&alice)), None);
+    assert_eq!(download(&app, alice_token, &file_id).await.0, StatusCode::NOT_FOUND);
+
+    // Sharing a folder shares everything below it
+    let folder_share = share(&app, bob_token, json!({"folderId": a, "username": "alice", "permission": 1})).await;
+    assert_eq!(folder_permission(&db, &b, user_id(&alice)), Some(Permission::Read));
+    assert_eq!(file_permission(&db, &file_id, user_id(&alice)), Some(Permission::Read));
+    assert_eq!(download(&app, alice_token, &file_id).await, (StatusCode::OK, b"hello world".to_vec()));
+    assert_eq!(upload(&app, alice_token, &file_id, b"changed").await, StatusCode::NOT_FOUND);
+    assert_eq!(download(&app, bob_token, &file_id).await.1, b"hello world");
+
+    // The highest of the shares counts
+    share(&app, bob_token, json!({"fileId": file_id, "username": "alice", "permission": 2})).await;
+    assert_eq!(file_permission(&db, &file_id, user_id(&alice)), Some(Permission::Write));
+    assert_eq!(upload(&app, alice_token, &file_id, b"changed").await, StatusCode::OK);
+
+    let req = test::TestRequest::delete().uri(&format!("/api/sharing/{}", folder_share)).insert_header(bearer(bob_token)).to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::NO_CONTENT);
+    assert_eq!(folder_permission(&db, &b, user_id(&alice)), None);
+    assert_eq!(file_permission(&db, &file_id, user_id(&alice)), Some(Permission::Write));
+}
+
+#[actix_web::test]
+async fn group_roles_come_before_ownership_and_shares() {
+    let db = TestDb::new();
+    let app = init_app(&db, db.config()).await;
+    let bob = login(&app, "bob").await;
+    let alice = login(&app, "alice").await;
+    let carol = login(&app, "carol").await;
+    let (bob_token, alice_token) = (bob["token"].as_str().unwrap(), alice["token"].as_str().unwrap());
+
+    let req = test::TestRequest::post().uri("/api/groups").insert_header(bearer(bob_token)).set_json(json!({"name": "team"})).to_request();
+    let created: Value = test::read_body_json(test::call_service(&app, req).await).await;
+    let group_id = created["id"].as_str().unwrap();
+    let req = test::TestRequest::get().uri(&format!("/api/groups/{}", group_id)).insert_header(bearer(bob_token)).to_request();
+    let group: Value = test::read_body_json(test::call_service(&app, req).await).await;
+    let folder_id = create_folder(&app, bob_token, group["folderId"].as_str().unwrap(), "docs").await;
+    let file_id = create_file_in(&app, bob_token, &folder_id).await;
+
+    let add_member = |token: &str, username: &str, role: i32| {
+        test::TestRequest::post()
+            .uri(&format!("/api/groups/{}/members", group_id))
+            .insert_header(bearer(token))
+            .set_json(json!({"username": username, "role": role}))
+            .to_request()
+    };
+    assert_eq!(test::call_service(&app, add_member(bob_token, "alice", 1)).await.status(), StatusCode::CREATED);
+    assert_eq!(file_permission(&db, &file_id, user_id(&bob)), Some(Permission::Owner));
+    assert_eq!(file_permission(&db, &file_id, user_id(&alice)), Some(Permission::Read));
+    assert_eq!(folder_permission(&db, &folder_id, user_id(&alice)), Some(Permission::Read));
+
+    // Shares written around the API don't reach into groups
+    let item = CreateUserShareDto { file_id: Some(file_id.clone()), folder_id: None, username: "carol".to_string(), permission: 2 };
+    create_user_share(&mut db.pool.get().unwrap(), item, user_id(&carol), user_id(&bob)).unwrap();
+    assert_eq!(file_permission(&db, &file_id, user_id(&carol)), None);
+
+    // Creating the file doesn't keep bob's access once his role changes
+    assert_eq!(test::call_service(&app, add_member(bob_token, "alice", 3)).await.status(), StatusCode::CREATED);
+    assert_eq!(test::call_service(&app, add_member(alice_token, "bob", 1)).await.status(), StatusCode::CREATED);
+    assert_eq!(file_permission(&db, &file_id, user_id(&bob)), Some(Permission::Read));
+    assert_eq!(file_permission(&db, &file_id, user_id(&alice)), Some(Permission::Owner));
+}
@@ -0,0 +1,27 @@
+use super::*;
+
+#[actix_web::test]
+async fn deleted_files_are_restored_from_the_trash() {
+    let db = TestDb::new();
+    let app = init_app(&db, db.config()).await;
+    let session = login(&app, "bob").await;
+    let token = session["token"].as_str().unwrap();
+    let file_id = create_file(&app, &session).await;
+    upload(&app, token, &file_id, b"hello world").await;
+
+    let req = test::TestRequest::delete().uri(&format!("/api/files/{}", file_id)).insert_header(bearer(token)).to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::NO_CONTENT);
+    assert_eq!(download(&app, token, &file_id).await.0, StatusCode::NOT_FOUND);
+
+    let req = test::TestRequest::get().uri("/api/trash").insert_header(bearer(token)).to_request();
+    let trash: Value = test::read_body_json(test::call_service(&app, req).await).await;
+    assert_eq!(trash["files"][0]["id"], file_id.as_str());
+
+    let req = test::TestRequest::post().uri(&format!("/api/trash/files/{}/restore", file_id)).insert_header(bearer(token)).to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::OK);
+    assert_eq!(download(&app, token, &file_id).await, (StatusCode::OK, b"hello world".to_vec()));
+
+    let req = test::TestRequest::get().uri("/api/trash").insert_header(bearer(token)).to_request();
+    let trash: Value = test::read_body_json(test::call_service(&app, req).await).await;
+    assert_eq!(trash["files"], json!([]));
+}
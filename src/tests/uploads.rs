@@ -0,0 +1,158 @@
+use actix_web::http::header::LOCATION;
+use actix_web::http::Method;
+use diesel::prelude::*;
+
+use crate::schema::uploads;
+use crate::shared::common::StorageService;
+use crate::uploads::purge::purge_expired_uploads;
+
+use super::*;
+
+fn encode(value: &str) -> String {
+    base64::Engine::encode(&base64::engine::general_purpose::STANDARD, value)
+}
+
+async fn create_upload(app: &impl TestApp, token: &str, length: u64, metadata: &str) -> ServiceResponse {
+    let req = test::TestRequest::post()
+        .uri("/api/uploads")
+        .insert_header(bearer(token))
+        .insert_header(("Tus-Resumable", "1.0.0"))
+        .insert_header(("Upload-Length", length.to_string()))
+        .insert_header(("Upload-Metadata", metadata.to_string()))
+        .to_request();
+    test::call_service(app, req).await
+}
+
+fn patch(location: &str, token: &str, offset: &str, chunk: &'static [u8]) -> Request {
+    test::TestRequest::patch()
+        .uri(location)
+        .insert_header(bearer(token))
+        .insert_header(("Tus-Resumable", "1.0.0"))
+        .insert_header((CONTENT_TYPE, "application/offset+octet-stream"))
+        .insert_header(("Upload-Offset", offset.to_string()))
+        .set_payload(chunk)
+        .to_request()
+}
+
+fn head(location: &str, token: &str) -> Request {
+    test::TestRequest::default()
+        .method(Method::HEAD)
+        .uri(location)
+        .insert_header(bearer(token))
+        .insert_header(("Tus-Resumable", "1.0.0"))
+        .to_request()
+}
+
+async fn usage(app: &impl TestApp, token: &str) -> Value {
+    let req = test::TestRequest::get().uri("/api/auth/user/usage").insert_header(bearer(token)).to_request();
+    test::read_body_json(test::call_service(app, req).await).await
+}
+
+#[actix_web::test]
+async fn tus_uploads_are_finalised_into_the_file() {
+    let db = TestDb::new();
+    let app = init_app(&db, db.config()).await;
+    let session = login(&app, "bob").await;
+    let token = session["token"].as_str().unwrap();
+    let file_id = create_file(&app, &session).await;
+
+    let resp = create_upload(&app, token, 11, &format!("fileId {},filename {},filetype {}", encode(&file_id), encode("notes 1.txt"), encode("text/plain"))).await;
+    assert_eq!(resp.status(), StatusCode::CREATED);
+    assert!(resp.headers().contains_key("Upload-Expires"));
+    let location = resp.headers().get(LOCATION).unwrap().to_str().unwrap().to_string();
+
+    // A short chunk leaves the upload open at the offset it reached
+    let resp = test::call_service(&app, patch(&location, token, "0", b"hello ")).await;
+    assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+    assert_eq!(resp.headers().get("Upload-Offset").unwrap(), "6");
+    assert!(resp.headers().contains_key("Upload-Expires"));
+
+    // A chunk for an offset the upload is not at is refused
+    let resp = test::call_service(&app, patch(&location, token, "0", b"hello ")).await;
+    assert_eq!(resp.status(), StatusCode::CONFLICT);
+    assert_eq!(resp.headers().get("Upload-Offset").unwrap(), "6");
+
+    let resp = test::call_service(&app, head(&location, token)).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(resp.headers().get("Upload-Offset").unwrap(), "6");
+    assert_eq!(resp.headers().get("Upload-Length").unwrap(), "11");
+
+    // More than the rest of the upload is refused, nothing of it is kept
+    let resp = test::call_service(&app, patch(&location, token, "6", b"world and more")).await;
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(test::call_service(&app, head(&location, token)).await.headers().get("Upload-Offset").unwrap(), "6");
+
+    let resp = test::call_service(&app, patch(&location, token, "6", b"world")).await;
+    assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+    assert_eq!(download(&app, token, &file_id).await, (StatusCode::OK, b"hello world".to_vec()));
+
+    // The name and type from Upload-Metadata end up on the file
+    let req = test::TestRequest::get().uri(&format!("/api/files/{}", file_id)).insert_header(bearer(token)).to_request();
+    let file: Value = test::read_body_json(test::call_service(&app, req).await).await;
+    assert_eq!(file["orginalFilename"], "notes 1.txt");
+    assert_eq!(file["mediaType"], "text/plain");
+}
+
+#[actix_web::test]
+async fn open_uploads_hold_their_length_against_the_quota() {
+    let db = TestDb::new();
+    let mut config = db.config();
+    config.default_storage_quota = 16;
+    let app = init_app(&db, config).await;
+    let session = login(&app, "bob").await;
+    let token = session["token"].as_str().unwrap();
+    let first = create_file(&app, &session).await;
+    let second = create_file(&app, &session).await;
+
+    let resp = create_upload(&app, token, 10, &format!("fileId {}", encode(&first))).await;
+    assert_eq!(resp.status(), StatusCode::CREATED);
+    let location = resp.headers().get(LOCATION).unwrap().to_str().unwrap().to_string();
+    assert_eq!(usage(&app, token).await["used"], 10);
+
+    // Neither a second upload nor a plain one fits next to the open upload
+    assert_eq!(create_upload(&app, token, 10, &format!("fileId {}", encode(&second))).await.status(), StatusCode::PAYLOAD_TOO_LARGE);
+    assert_eq!(upload(&app, token, &second, &[b'x'; 10]).await, StatusCode::PAYLOAD_TOO_LARGE);
+
+    // Finishing the upload turns the reservation into the file's version
+    assert_eq!(test::call_service(&app, patch(&location, token, "0", b"0123456789")).await.status(), StatusCode::NO_CONTENT);
+    assert_eq!(usage(&app, token).await["used"], 10);
+
+    // Terminating an upload gives its space back
+    let resp = create_upload(&app, token, 6, &format!("fileId {}", encode(&second))).await;
+    let location = resp.headers().get(LOCATION).unwrap().to_str().unwrap().to_string();
+    assert_eq!(usage(&app, token).await["used"], 16);
+    let req = test::TestRequest::delete().uri(&location).insert_header(bearer(token)).insert_header(("Tus-Resumable", "1.0.0")).to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::NO_CONTENT);
+    assert_eq!(usage(&app, token).await["used"], 10);
+}
+
+#[actix_web::test]
+async fn expired_uploads_are_purged_with_their_chunks() {
+    let db = TestDb::new();
+    let app = init_app(&db, db.config()).await;
+    let session = login(&app, "bob").await;
+    let token = session["token"].as_str().unwrap();
+    let file_id = create_file(&app, &session).await;
+
+    let resp = create_upload(&app, token, 11, &format!("fileId {}", encode(&file_id))).await;
+    let location = resp.headers().get(LOCATION).unwrap().to_str().unwrap().to_string();
+    let upload_id = location.rsplit('/').next().unwrap().to_string();
+    test::call_service(&app, patch(&location, token, "0", b"hello ")).await;
+    let staging_path = format!("{}/.uploads/{}", session["user"]["folderId"].as_str().unwrap(), upload_id);
+    assert_eq!(db.storage.list_file_names(&staging_path).await.unwrap().len(), 1);
+
+    let mut conn = db.pool.get().unwrap();
+    assert_eq!(purge_expired_uploads(&db.pool, &db.storage).await.unwrap(), 0);
+
+    // Let the upload expire
+    diesel::update(uploads::table.filter(uploads::id.eq(&upload_id)))
+        .set(uploads::expires_at.eq(chrono::Utc::now().naive_utc() - chrono::Duration::minutes(1)))
+        .execute(&mut conn)
+        .unwrap();
+    assert_eq!(test::call_service(&app, head(&location, token)).await.status(), StatusCode::NOT_FOUND);
+
+    assert_eq!(purge_expired_uploads(&db.pool, &db.storage).await.unwrap(), 1);
+    assert!(db.storage.list_file_names(&staging_path).await.unwrap().is_empty());
+    assert_eq!(uploads::table.filter(uploads::id.eq(&upload_id)).count().get_result::<i64>(&mut conn).unwrap(), 0);
+    assert_eq!(usage(&app, token).await["used"], 0);
+}
@@ -0,0 +1,12 @@
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use crate::files::dto::FileDto;
+use crate::folders::dto::FolderDto;
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct TrashDto {
+    pub files: Vec<FileDto>,
+    pub folders: Vec<FolderDto>,
+}
@@ -0,0 +1,140 @@
+pub mod dto;
+pub mod purge;
+pub mod service;
+
+use actix_web::{
+    get, post, web, Error, HttpResponse,
+};
+
+use log::info;
+
+use crate::auth::jwt_auth;
+use crate::folders::service::is_valid_parent;
+use crate::groups::dto::Owner;
+use crate::groups::service::get_owner_root;
+use crate::shared::common::ServiceError;
+use crate::shared::common::AppState;
+use service::{get_trashed_files, get_trashed_folders, get_trashed_file, get_trashed_folder, restore_file, restore_folder};
+
+use dto::TrashDto;
+
+///
+/// Gets all files and folders in the user's trash
+///
+/// Includes the trash of the groups the user is an editor or admin of, whoever deleted the items
+///
+#[utoipa::path(
+    get,
+    tag = "Trash",
+    path = "/api/trash",
+
+    responses(
+        (status = 200, description = "Successfully retrieved the trash", body = [TrashDto])
+    )
+)]
+#[get("")]
+pub async fn get_trash_handler(
+    app: web::Data<AppState>,
+    jwt: jwt_auth::JwtMiddleware,
+) -> Result<HttpResponse, Error> {
+    let user_id = jwt.user_id;
+
+    let mut conn = app
+        .get_connection()
+        .map_err(|err| ServiceError::NotFound(err.to_string()))?;
+
+    let files = get_trashed_files(&mut conn, user_id).map_err(|err| ServiceError::InternalServerError(err.to_string()))?;
+    let folders = get_trashed_folders(&mut conn, user_id).map_err(|err| ServiceError::InternalServerError(err.to_string()))?;
+
+    Ok(HttpResponse::Ok().json(TrashDto { files, folders }))
+}
+
+///
+/// Restores a file from the trash
+///
+/// The file's folder must not be in the trash itself
+///
+#[utoipa::path(
+    post,
+    tag = "Trash",
+    path = "/api/trash/files/{file_id}/restore",
+
+    responses(
+        (status = 200, description = "Successfully restored a file")
+    )
+)]
+#[post("/files/{file_id}/restore")]
+pub async fn restore_file_handler(
+    app: web::Data<AppState>,
+    jwt: jwt_auth::JwtMiddleware,
+    path: web::Path<String>,
+) -> Result<HttpResponse, Error> {
+    let user_id = jwt.user_id;
+    let file_id: String = path.to_string();
+
+    let mut conn = app
+        .get_connection()
+        .map_err(|err| ServiceError::NotFound(err.to_string()))?;
+
+    let file = get_trashed_file(&mut conn, &file_id, user_id).map_err(|err| ServiceError::NotFound(err.to_string()))?;
+    let owner = Owner::from(&file);
+    let root_folder_id = get_owner_root(&mut conn, &owner).map_err(|err| ServiceError::NotFound(err.to_string()))?;
+    if !is_valid_parent(&mut conn, &file.folder_id, &root_folder_id, &owner).map_err(|err| ServiceError::InternalServerError(err.to_string()))? {
+        return Err(ServiceError::BadRequest(format!("Folder {} must be restored first", file.folder_id)).into());
+    }
+
+    restore_file(&mut conn, &file_id, user_id).map_err(|err| ServiceError::InternalServerError(err.to_string()))?;
+    info!("Restored file: {} for user: {}", file_id, user_id);
+
+    Ok(HttpResponse::Ok().finish())
+}
+
+///
+/// Restores a folder from the trash
+///
+/// The folder's parent must not be in the trash itself
+///
+#[utoipa::path(
+    post,
+    tag = "Trash",
+    path = "/api/trash/folders/{folder_id}/restore",
+
+    responses(
+        (status = 200, description = "Successfully restored a folder")
+    )
+)]
+#[post("/folders/{folder_id}/restore")]
+pub async fn restore_folder_handler(
+    app: web::Data<AppState>,
+    jwt: jwt_auth::JwtMiddleware,
+    path: web::Path<String>,
+) -> Result<HttpResponse, Error> {
+    let user_id = jwt.user_id;
+    let folder_id: String = path.to_string();
+
+    let mut conn = app
+        .get_connection()
+        .map_err(|err| ServiceError::NotFound(err.to_string()))?;
+
+    let folder = get_trashed_folder(&mut conn, &folder_id, user_id).map_err(|err| ServiceError::NotFound(err.to_string()))?;
+    let owner = Owner::from(&folder);
+    let root_folder_id = get_owner_root(&mut conn, &owner).map_err(|err| ServiceError::NotFound(err.to_string()))?;
+    if !is_valid_parent(&mut conn, &folder.parent_folder_id, &root_folder_id, &owner).map_err(|err| ServiceError::InternalServerError(err.to_string()))? {
+        return Err(ServiceError::BadRequest(format!("Folder {} must be restored first", folder.parent_folder_id)).into());
+    }
+
+    restore_folder(&mut conn, &folder_id, user_id).map_err(|err| ServiceError::InternalServerError(err.to_string()))?;
+    info!("Restored folder: {} for user: {}", folder_id, user_id);
+
+    Ok(HttpResponse::Ok().finish())
+}
+
+pub fn config(conf: &mut web::ServiceConfig) {
+    let scope = web::scope("/trash")
+            .service(get_trash_handler)
+            .service(restore_file_handler)
+            .service(restore_folder_handler)
+            ;
+
+    conf.service(scope);
+}
@@ -0,0 +1,81 @@
+use std::io::ErrorKind;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::shared::common::{build_full_path, build_version_path, DbError, DbPool, StorageService};
+use crate::previews::delete_thumbnails;
+use crate::storage::blob_store::BlobStore;
+use crate::uploads::purge::purge_expired_uploads;
+use super::service::{get_expired_files, get_expired_folders, purge_file, purge_folder};
+
+// How often the trash is checked for expired items
+const PURGE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+///
+/// Periodically purges items that have been in the trash for longer than `retention_days`
+///
+/// Expired uploads are purged along with them
+///
+pub async fn run_purge(pool: DbPool, storage: Arc<dyn StorageService>, blobs: Arc<BlobStore>, retention_days: i64) {
+    let mut interval = actix_web::rt::time::interval(PURGE_INTERVAL);
+    loop {
+        interval.tick().await;
+        match purge_expired(&pool, storage.as_ref(), retention_days).await {
+            Ok(0) => {}
+            Ok(purged) => log::info!("Purged {} expired items from the trash", purged),
+            Err(err) => log::error!("Failed to purge the trash: {}", err),
+        }
+        match purge_expired_uploads(&pool, storage.as_ref()).await {
+            Ok(0) => {}
+            Ok(purged) => log::info!("Purged {} expired uploads", purged),
+            Err(err) => log::error!("Failed to purge expired uploads: {}", err),
+        }
+        match collect_blobs(&pool, blobs.as_ref()).await {
+            Ok(0) => {}
+            Ok(collected) => log::info!("Removed {} unreferenced blobs", collected),
+            Err(err) => log::error!("Failed to collect unreferenced blobs: {}", err),
+        }
+    }
+}
+
+///
+/// Permanently deletes expired trash items, both their records and their contents in storage
+///
+/// The blobs of purged files are released, their contents go with the next `collect_blobs`
+///
+pub async fn purge_expired(pool: &DbPool, storage: &dyn StorageService, retention_days: i64) -> Result<usize, DbError> {
+    let cutoff = chrono::Utc::now().naive_utc() - chrono::Duration::days(retention_days);
+    let mut conn = pool.get()?;
+    let mut purged = 0;
+
+    for (file, user_folder) in get_expired_files(&mut conn, cutoff)? {
+        // Files uploaded before the blob store existed keep their contents next to the user's files
+        let path = build_full_path(&user_folder, &file.folder_id);
+        delete_thumbnails(storage, &path, &file.id).await?;
+        match storage.delete_file(&path, &file.id).await {
+            Err(err) if err.kind() != ErrorKind::NotFound => return Err(Box::new(err)),
+            _ => {}
+        }
+        match storage.delete_folder(&build_version_path(&user_folder, &file.id)).await {
+            Err(err) if err.kind() != ErrorKind::NotFound => return Err(Box::new(err)),
+            _ => purged += purge_file(&mut conn, &file.id)?,
+        }
+    }
+
+    for (folder, user_folder) in get_expired_folders(&mut conn, cutoff)? {
+        match storage.delete_folder(&build_full_path(&user_folder, &folder.id)).await {
+            Err(err) if err.kind() != ErrorKind::NotFound => return Err(Box::new(err)),
+            _ => purged += purge_folder(&mut conn, &folder.id)?,
+        }
+    }
+
+    Ok(purged)
+}
+
+///
+/// Removes blobs that are no longer referenced by any file version
+///
+pub async fn collect_blobs(pool: &DbPool, blobs: &BlobStore) -> Result<usize, DbError> {
+    let mut conn = pool.get()?;
+    blobs.collect_garbage(&mut conn).await
+}
@@ -0,0 +1,144 @@
+use crate::shared::common::DbError;
+use crate::files::dto::FileDto;
+use crate::folders::dto::FolderDto;
+use crate::auth::service::add_storage_used;
+use crate::groups::dto::ROLE_EDITOR;
+use crate::storage::service::release_blob_reference;
+use diesel::prelude::*;
+use diesel::sql_types::{Nullable, Text};
+use diesel::sqlite::Sqlite;
+
+use crate::schema::{file_folders, file_versions, files, group_members, users};
+
+// The groups whose trash the user manages, editors and admins may restore what any member deleted
+fn trash_group_ids(user_id: i32) -> group_members::BoxedQuery<'static, Sqlite, Nullable<Text>> {
+    group_members::table
+        .filter(group_members::user_id.eq(user_id))
+        .filter(group_members::active.eq(true))
+        .filter(group_members::role.ge(ROLE_EDITOR))
+        .select(group_members::group_id.nullable())
+        .into_boxed()
+}
+
+///
+/// The trashed files of the user and of the groups they may write to
+///
+pub fn get_trashed_files(conn: &mut SqliteConnection, user_id: i32) -> Result<Vec<FileDto>, DbError> {
+    Ok(files::table
+        .filter(files::owner_id.eq(user_id).and(files::group_id.is_null()).or(files::group_id.eq_any(trash_group_ids(user_id))))
+        .filter(files::active.eq(false))
+        .order(files::deleted_at.desc())
+        .load::<FileDto>(conn)?)
+}
+
+///
+/// The trashed folders of the user and of the groups they may write to
+///
+pub fn get_trashed_folders(conn: &mut SqliteConnection, user_id: i32) -> Result<Vec<FolderDto>, DbError> {
+    Ok(file_folders::table
+        .filter(file_folders::owner_id.eq(user_id).and(file_folders::group_id.is_null()).or(file_folders::group_id.eq_any(trash_group_ids(user_id))))
+        .filter(file_folders::active.eq(false))
+        .order(file_folders::deleted_at.desc())
+        .load::<FolderDto>(conn)?)
+}
+
+pub fn get_trashed_file(conn: &mut SqliteConnection, file_id: &str, user_id: i32) -> Result<FileDto, DbError> {
+    let file = files::table
+        .filter(files::id.eq(file_id))
+        .filter(files::owner_id.eq(user_id).and(files::group_id.is_null()).or(files::group_id.eq_any(trash_group_ids(user_id))))
+        .filter(files::active.eq(false))
+        .first::<FileDto>(conn)
+        .map_err(|err| format!("Error loading trashed file file_id: {}, user_id: {}, Error: {}", file_id, user_id, err))?;
+    Ok(file)
+}
+
+pub fn get_trashed_folder(conn: &mut SqliteConnection, folder_id: &str, user_id: i32) -> Result<FolderDto, DbError> {
+    let folder = file_folders::table
+        .filter(file_folders::id.eq(folder_id))
+        .filter(file_folders::owner_id.eq(user_id).and(file_folders::group_id.is_null()).or(file_folders::group_id.eq_any(trash_group_ids(user_id))))
+        .filter(file_folders::active.eq(false))
+        .first::<FolderDto>(conn)
+        .map_err(|err| format!("Error loading trashed folder folder_id: {}, user_id: {}, Error: {}", folder_id, user_id, err))?;
+    Ok(folder)
+}
+
+pub fn restore_file(conn: &mut SqliteConnection, file_id: &str, user_id: i32) -> Result<usize, DbError> {
+    Ok(diesel::update(files::table
+            .filter(files::id.eq(file_id))
+            .filter(files::owner_id.eq(user_id).and(files::group_id.is_null()).or(files::group_id.eq_any(trash_group_ids(user_id)))))
+        .set((
+            files::active.eq(true),
+            files::deleted_at.eq(None::<chrono::NaiveDateTime>),
+            files::deleted_by.eq(None::<i32>),
+            files::updated_by.eq(user_id),
+            files::updated_at.eq(chrono::Utc::now().naive_utc())))
+        .execute(conn)?)
+}
+
+pub fn restore_folder(conn: &mut SqliteConnection, folder_id: &str, user_id: i32) -> Result<usize, DbError> {
+    Ok(diesel::update(file_folders::table
+            .filter(file_folders::id.eq(folder_id))
+            .filter(file_folders::owner_id.eq(user_id).and(file_folders::group_id.is_null()).or(file_folders::group_id.eq_any(trash_group_ids(user_id)))))
+        .set((
+            file_folders::active.eq(true),
+            file_folders::deleted_at.eq(None::<chrono::NaiveDateTime>),
+            file_folders::deleted_by.eq(None::<i32>),
+            file_folders::updated_by.eq(user_id),
+            file_folders::updated_at.eq(chrono::Utc::now().naive_utc())))
+        .execute(conn)?)
+}
+
+///
+/// Files that have been in the trash since before `cutoff`, with the root folder of their owner
+///
+pub fn get_expired_files(conn: &mut SqliteConnection, cutoff: chrono::NaiveDateTime) -> Result<Vec<(FileDto, String)>, DbError> {
+    Ok(files::table
+        .inner_join(users::table.on(users::id.eq(files::owner_id.nullable())))
+        .filter(files::active.eq(false))
+        .filter(files::deleted_at.lt(cutoff))
+        .select((FileDto::as_select(), users::folder_id))
+        .load::<(FileDto, String)>(conn)?)
+}
+
+///
+/// Folders that have been in the trash since before `cutoff`, with the root folder of their owner
+///
+pub fn get_expired_folders(conn: &mut SqliteConnection, cutoff: chrono::NaiveDateTime) -> Result<Vec<(FolderDto, String)>, DbError> {
+    Ok(file_folders::table
+        .inner_join(users::table.on(users::id.eq(file_folders::owner_id.nullable())))
+        .filter(file_folders::active.eq(false))
+        .filter(file_folders::deleted_at.lt(cutoff))
+        .select((FolderDto::as_select(), users::folder_id))
+        .load::<(FolderDto, String)>(conn)?)
+}
+
+///
+/// Deletes a trashed file and its versions, releasing the blobs the versions referenced and
+/// the space the versions took from its owner's quota
+///
+pub fn purge_file(conn: &mut SqliteConnection, file_id: &str) -> Result<usize, DbError> {
+    conn.transaction(|conn| {
+        let owner_id: i32 = files::table
+            .filter(files::id.eq(file_id))
+            .select(files::owner_id)
+            .first(conn)?;
+
+        let versions: Vec<(String, i64)> = file_versions::table
+            .filter(file_versions::file_id.eq(file_id))
+            .select((file_versions::checksum, file_versions::size))
+            .load(conn)?;
+        add_storage_used(conn, owner_id, -versions.iter().map(|(_, size)| size).sum::<i64>())?;
+        for (checksum, _) in versions {
+            release_blob_reference(conn, &checksum)?;
+        }
+
+        diesel::delete(file_versions::table.filter(file_versions::file_id.eq(file_id))).execute(conn)?;
+        Ok(diesel::delete(files::table.filter(files::id.eq(file_id).and(files::active.eq(false))))
+            .execute(conn)?)
+    })
+}
+
+pub fn purge_folder(conn: &mut SqliteConnection, folder_id: &str) -> Result<usize, DbError> {
+    Ok(diesel::delete(file_folders::table.filter(file_folders::id.eq(folder_id).and(file_folders::active.eq(false))))
+        .execute(conn)?)
+}
@@ -0,0 +1,44 @@
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use crate::schema::uploads;
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+#[derive(Queryable, Selectable, Insertable)]
+#[diesel(table_name = uploads)]
+#[serde(rename_all = "camelCase")]
+pub struct UploadDto {
+    // The unique identifier for the upload, used in the upload URL
+    pub id: String,
+    // The ID of the user who started the upload
+    pub owner_id: i32,
+    // The file the upload is finalised into
+    pub file_id: String,
+    // Total size of the upload in bytes
+    pub upload_length: i64,
+    // Number of bytes received so far
+    pub upload_offset: i64,
+    pub filename: Option<String>,
+    pub media_type: Option<String>,
+    // True while a PATCH request is appending to the upload
+    pub patching: bool,
+    // The owner of the file, the upload's length counts against their quota until it is finalised
+    pub quota_user_id: i32,
+    // The upload is purged after this unless a PATCH moves it on
+    pub expires_at: chrono::NaiveDateTime,
+    pub created_at: Option<chrono::NaiveDateTime>,
+    pub updated_at: Option<chrono::NaiveDateTime>,
+    pub created_by: i32,
+    pub updated_by: i32,
+    // False once the upload has been finalised into its file
+    pub active: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct CreateUploadDto {
+    pub file_id: String,
+    pub upload_length: i64,
+    pub quota_user_id: i32,
+    pub expires_at: chrono::NaiveDateTime,
+    pub filename: Option<String>,
+    pub media_type: Option<String>,
+}
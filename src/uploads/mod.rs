@@ -0,0 +1,532 @@
+pub mod dto;
+pub mod purge;
+pub mod service;
+
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::io::ErrorKind;
+use std::rc::Rc;
+use std::time::SystemTime;
+
+use actix_web::{
+    delete, head, options, patch, post, web, Error, HttpRequest, HttpResponse,
+};
+use actix_web::http::header::{HttpDate, CACHE_CONTROL, CONTENT_TYPE, LOCATION};
+use actix_web::middleware::DefaultHeaders;
+use base64::Engine;
+use chrono::NaiveDateTime;
+use diesel::SqliteConnection;
+use futures_util::{StreamExt, TryStreamExt};
+use log::info;
+
+use crate::auth::jwt_auth;
+use crate::auth::service::{add_storage_used, reserve_storage};
+use crate::files::service::get_file;
+use crate::files::store_file_version;
+use crate::get_user;
+use crate::shared::common::{AppState, ByteStream, ServiceError, StorageService};
+use crate::shared::dto::UserDto;
+use crate::shared::media_type::sniff_media_type;
+use crate::sharing::dto::Permission;
+use service::{create_upload, get_upload, claim_upload, update_upload_offset, complete_upload, delete_upload};
+
+use dto::{UploadDto, CreateUploadDto};
+
+const TUS_RESUMABLE: &str = "Tus-Resumable";
+const TUS_VERSION: &str = "1.0.0";
+const TUS_EXTENSIONS: &str = "creation,termination,expiration";
+const UPLOAD_LENGTH: &str = "Upload-Length";
+const UPLOAD_OFFSET: &str = "Upload-Offset";
+const UPLOAD_METADATA: &str = "Upload-Metadata";
+const UPLOAD_EXPIRES: &str = "Upload-Expires";
+const OFFSET_CONTENT_TYPE: &str = "application/offset+octet-stream";
+
+///
+/// Describes the tus protocol version and extensions supported by the server
+///
+#[utoipa::path(
+    options,
+    tag = "Uploads",
+    path = "/api/uploads",
+    responses(
+        (status = 204, description = "Supported tus versions and extensions")
+    )
+)]
+#[options("")]
+pub async fn upload_options_handler() -> Result<HttpResponse, Error> {
+    Ok(HttpResponse::NoContent()
+        .insert_header(("Tus-Version", TUS_VERSION))
+        .insert_header(("Tus-Extension", TUS_EXTENSIONS))
+        .finish())
+}
+
+///
+/// Starts a resumable upload (tus creation extension)
+///
+/// The `Upload-Metadata` header must carry the `fileId` of an existing file, `filename`
+/// and `filetype` are stored on the file once the upload completes. An upload of 0 bytes
+/// is complete right away, so it is finalised when it is created.
+///
+/// The upload's length counts against the file owner's quota from the start, and the upload
+/// is removed with its chunks when it has not received a chunk before `Upload-Expires`
+///
+#[utoipa::path(
+    post,
+    tag = "Uploads",
+    path = "/api/uploads",
+    responses(
+        (status = 201, description = "Successfully created an upload, the Location header holds its URL"),
+        (status = 413, description = "The upload exceeds the storage quota")
+    )
+)]
+#[post("")]
+pub async fn create_upload_handler(
+    req: HttpRequest,
+    app: web::Data<AppState>,
+    jwt: jwt_auth::JwtMiddleware,
+) -> Result<HttpResponse, Error> {
+    if let Some(response) = check_tus_version(&req) {
+        return Ok(response);
+    }
+    let user_id = jwt.user_id;
+
+    let upload_length = header_value(&req, UPLOAD_LENGTH)
+        .and_then(|value| value.parse::<i64>().ok())
+        .filter(|value| *value >= 0)
+        .ok_or_else(|| ServiceError::BadRequest(format!("Missing or invalid {} header", UPLOAD_LENGTH)))?;
+    let metadata = parse_metadata(header_value(&req, UPLOAD_METADATA).unwrap_or_default())?;
+    let file_id = metadata
+        .get("fileId")
+        .cloned()
+        .ok_or_else(|| ServiceError::BadRequest("Upload-Metadata must contain a fileId".to_string()))?;
+
+    let mut conn = app
+        .get_connection()
+        .map_err(|err| ServiceError::NotFound(err.to_string()))?;
+
+    let file = get_file(&mut conn, &file_id, user_id, Permission::Write).map_err(|err| ServiceError::NotFound(err.to_string()))?;
+
+    // The final size is known up front, so it is reserved before any data is sent
+    let reserved = reserve_storage(&mut conn, file.owner_id, upload_length, app.get_config().default_storage_quota)
+        .map_err(|err| ServiceError::InternalServerError(err.to_string()))?;
+    if !reserved {
+        return Err(ServiceError::PayloadTooLarge("Upload exceeds the storage quota".to_string()).into());
+    }
+
+    let expires_at = upload_expiry(&app);
+    let created = create_upload(&mut conn, CreateUploadDto {
+        file_id,
+        upload_length,
+        quota_user_id: file.owner_id,
+        expires_at,
+        filename: metadata.get("filename").cloned(),
+        media_type: metadata.get("filetype").cloned(),
+    }, user_id);
+    let upload_id = match created {
+        Ok(upload_id) => upload_id,
+        Err(err) => {
+            let _ = add_storage_used(&mut conn, file.owner_id, -upload_length);
+            return Err(ServiceError::InternalServerError(err.to_string()).into());
+        }
+    };
+
+    info!("Created upload {} of {} bytes", upload_id, upload_length);
+
+    // No PATCH ever follows an empty upload
+    if upload_length == 0 {
+        let user = get_user(&mut conn, user_id).map_err(|err| ServiceError::NotFound(err.to_string()))?;
+        let upload = get_upload(&mut conn, &upload_id, user_id).map_err(|err| ServiceError::NotFound(err.to_string()))?;
+        finalize_upload(&app, &mut conn, &user, &upload).await?;
+    }
+
+    Ok(HttpResponse::Created()
+        .insert_header((LOCATION, format!("/api/uploads/{}", upload_id)))
+        .insert_header((UPLOAD_EXPIRES, http_date(expires_at)))
+        .finish())
+}
+
+///
+/// Gets the current offset of an upload
+///
+#[utoipa::path(
+    head,
+    tag = "Uploads",
+    path = "/api/uploads/{upload_id}",
+    responses(
+        (status = 200, description = "The Upload-Offset header holds the number of bytes received")
+    )
+)]
+#[head("/{upload_id}")]
+pub async fn get_upload_offset_handler(
+    req: HttpRequest,
+    app: web::Data<AppState>,
+    jwt: jwt_auth::JwtMiddleware,
+    path: web::Path<String>,
+) -> Result<HttpResponse, Error> {
+    if let Some(response) = check_tus_version(&req) {
+        return Ok(response);
+    }
+
+    let mut conn = app
+        .get_connection()
+        .map_err(|err| ServiceError::NotFound(err.to_string()))?;
+
+    let upload = get_upload(&mut conn, &path, jwt.user_id).map_err(|err| ServiceError::NotFound(err.to_string()))?;
+
+    Ok(HttpResponse::Ok()
+        .insert_header((UPLOAD_OFFSET, upload.upload_offset.to_string()))
+        .insert_header((UPLOAD_LENGTH, upload.upload_length.to_string()))
+        .insert_header((UPLOAD_EXPIRES, http_date(upload.expires_at)))
+        .insert_header((CACHE_CONTROL, "no-store"))
+        .finish())
+}
+
+///
+/// Appends a chunk to an upload
+///
+/// Once all bytes have been received the staged chunks are assembled into the upload's file.
+/// Only one PATCH at a time appends to an upload, a concurrent one gets 409 Conflict. Every
+/// chunk moves `Upload-Expires` on
+///
+#[utoipa::path(
+    patch,
+    tag = "Uploads",
+    path = "/api/uploads/{upload_id}",
+    responses(
+        (status = 204, description = "Chunk stored, the Upload-Offset header holds the new offset")
+    )
+)]
+#[patch("/{upload_id}")]
+pub async fn append_upload_handler(
+    req: HttpRequest,
+    app: web::Data<AppState>,
+    jwt: jwt_auth::JwtMiddleware,
+    path: web::Path<String>,
+    payload: web::Payload,
+) -> Result<HttpResponse, Error> {
+    if let Some(response) = check_tus_version(&req) {
+        return Ok(response);
+    }
+    if header_value(&req, CONTENT_TYPE.as_str()) != Some(OFFSET_CONTENT_TYPE) {
+        return Ok(HttpResponse::UnsupportedMediaType().finish());
+    }
+    let user_id = jwt.user_id;
+    let offset = header_value(&req, UPLOAD_OFFSET)
+        .and_then(|value| value.parse::<i64>().ok())
+        .ok_or_else(|| ServiceError::BadRequest(format!("Missing or invalid {} header", UPLOAD_OFFSET)))?;
+
+    let mut conn = app
+        .get_connection()
+        .map_err(|err| ServiceError::NotFound(err.to_string()))?;
+
+    let user = get_user(&mut conn, user_id).map_err(|err| ServiceError::NotFound(err.to_string()))?;
+    let upload = get_upload(&mut conn, &path, user_id).map_err(|err| ServiceError::NotFound(err.to_string()))?;
+
+    if !claim_upload(&mut conn, &upload.id, user_id, offset).map_err(|err| ServiceError::InternalServerError(err.to_string()))? {
+        return Ok(HttpResponse::Conflict()
+            .insert_header((UPLOAD_OFFSET, upload.upload_offset.to_string()))
+            .finish());
+    }
+    let claim = UploadClaim { app: &app, upload: &upload, offset: Some(offset) };
+
+    // Each PATCH is staged as its own chunk, named after its offset so the chunks sort in upload order
+    let storage = app.get_storage_service();
+    let staging_path = staging_path(&user.folder_id, &upload.id);
+    let chunk_name = format!("{:020}", offset);
+    storage.create_folder(&staging_path).map_err(|err| ServiceError::InternalServerError(err.to_string()))?;
+
+    let received = Rc::new(Cell::new(0));
+    let remaining = (upload.upload_length - offset) as u64;
+    let stream = limited_stream(payload, remaining, received.clone());
+
+    if let Err(err) = storage.save_file(&staging_path, &chunk_name, stream).await {
+        let _ = storage.delete_file(&staging_path, &chunk_name).await;
+        return Err(match err.kind() {
+            ErrorKind::InvalidData => ServiceError::BadRequest(err.to_string()),
+            _ => ServiceError::InternalServerError(err.to_string()),
+        }.into());
+    }
+    if received.get() == 0 {
+        let _ = storage.delete_file(&staging_path, &chunk_name).await;
+    }
+
+    // The upload stays claimed while it is finalised, so no other request finalises it as well
+    let upload_offset = offset + received.get() as i64;
+    let finalized = match upload_offset == upload.upload_length {
+        true => finalize_upload(&app, &mut conn, &user, &upload).await,
+        false => Ok(()),
+    };
+    let expires_at = upload_expiry(&app);
+    claim.release(&mut conn, upload_offset, expires_at)?;
+    finalized?;
+
+    Ok(HttpResponse::NoContent()
+        .insert_header((UPLOAD_OFFSET, upload_offset.to_string()))
+        .insert_header((UPLOAD_EXPIRES, http_date(expires_at)))
+        .finish())
+}
+
+///
+/// Cancels an upload and discards the chunks received so far (tus termination extension)
+///
+/// The space held for the upload is given back. An upload a PATCH is appending to can't be
+/// cancelled until the PATCH is done
+///
+#[utoipa::path(
+    delete,
+    tag = "Uploads",
+    path = "/api/uploads/{upload_id}",
+    responses(
+        (status = 204, description = "Successfully terminated the upload"),
+        (status = 409, description = "A PATCH is appending to the upload")
+    )
+)]
+#[delete("/{upload_id}")]
+pub async fn delete_upload_handler(
+    req: HttpRequest,
+    app: web::Data<AppState>,
+    jwt: jwt_auth::JwtMiddleware,
+    path: web::Path<String>,
+) -> Result<HttpResponse, Error> {
+    if let Some(response) = check_tus_version(&req) {
+        return Ok(response);
+    }
+    let user_id = jwt.user_id;
+
+    let mut conn = app
+        .get_connection()
+        .map_err(|err| ServiceError::NotFound(err.to_string()))?;
+
+    let user = get_user(&mut conn, user_id).map_err(|err| ServiceError::NotFound(err.to_string()))?;
+    let upload = get_upload(&mut conn, &path, user_id).map_err(|err| ServiceError::NotFound(err.to_string()))?;
+
+    if delete_upload(&mut conn, &upload.id).map_err(|err| ServiceError::InternalServerError(err.to_string()))? == 0 {
+        return Ok(HttpResponse::Conflict().finish());
+    }
+    remove_staged_chunks(app.get_storage_service().as_ref(), &user.folder_id, &upload.id).await?;
+
+    Ok(HttpResponse::NoContent().finish())
+}
+
+// Concatenates the staged chunks into the file's blob and records the upload's name and type on the file
+async fn finalize_upload(app: &AppState, conn: &mut SqliteConnection, user: &UserDto, upload: &UploadDto) -> Result<(), ServiceError> {
+    let user_id = upload.owner_id;
+    let storage = app.get_storage_service().clone();
+    let staging_path = staging_path(&user.folder_id, &upload.id);
+
+    let file = get_file(conn, &upload.file_id, user_id, Permission::Write).map_err(|err| ServiceError::NotFound(err.to_string()))?;
+
+    // Empty uploads never staged a chunk
+    let mut chunks = match storage.list_file_names(&staging_path).await {
+        Ok(chunks) => chunks,
+        Err(err) if err.kind() == ErrorKind::NotFound => Vec::new(),
+        Err(err) => return Err(ServiceError::InternalServerError(err.to_string())),
+    };
+    chunks.sort();
+
+    let chunk_storage = storage.clone();
+    let chunk_path = staging_path.clone();
+    let stream: ByteStream = Box::pin(
+        futures_util::stream::iter(chunks)
+            .then(move |chunk| {
+                let storage = chunk_storage.clone();
+                let path = chunk_path.clone();
+                async move { storage.retrieve_file(&path, &chunk).await }
+            })
+            .try_flatten(),
+    );
+
+    let (media_type, stream) = sniff_media_type(stream, upload.media_type.as_deref(), upload.filename.as_deref())
+        .await
+        .map_err(|err| ServiceError::InternalServerError(err.to_string()))?;
+
+    info!("Finalising upload {} into file {}", upload.id, file.id);
+    let filename = upload.filename.clone().unwrap_or("unknown".to_string());
+    // The space reserved when the upload was created becomes the new version's
+    store_file_version(app, conn, file, stream, media_type, filename, user_id, upload.upload_length).await?;
+    complete_upload(conn, &upload.id, user_id).map_err(|err| ServiceError::InternalServerError(err.to_string()))?;
+
+    remove_staged_chunks(storage.as_ref(), &user.folder_id, &upload.id).await
+}
+
+// The claim of a PATCH on its upload. Dropping it without `release`, like when the request fails
+// or the client goes away, releases the upload at the offset the request started from
+struct UploadClaim<'a> {
+    app: &'a AppState,
+    upload: &'a UploadDto,
+    offset: Option<i64>,
+}
+
+impl UploadClaim<'_> {
+    fn release(mut self, conn: &mut SqliteConnection, upload_offset: i64, expires_at: NaiveDateTime) -> Result<(), ServiceError> {
+        self.offset = None;
+        update_upload_offset(conn, &self.upload.id, self.upload.owner_id, upload_offset, expires_at).map_err(|err| ServiceError::InternalServerError(err.to_string()))?;
+        Ok(())
+    }
+}
+
+impl Drop for UploadClaim<'_> {
+    fn drop(&mut self) {
+        if let Some(offset) = self.offset {
+            let released = self.app.get_connection().and_then(|mut conn| update_upload_offset(&mut conn, &self.upload.id, self.upload.owner_id, offset, self.upload.expires_at));
+            if let Err(err) = released {
+                log::error!("Failed to release upload {}: {}", self.upload.id, err);
+            }
+        }
+    }
+}
+
+async fn remove_staged_chunks(storage: &dyn StorageService, user_folder: &str, upload_id: &str) -> Result<(), ServiceError> {
+    match storage.delete_folder(&staging_path(user_folder, upload_id)).await {
+        Err(err) if err.kind() != ErrorKind::NotFound => Err(ServiceError::InternalServerError(err.to_string())),
+        _ => Ok(()),
+    }
+}
+
+// Chunks are staged below the root folder of the user who started the upload
+fn staging_path(user_folder: &str, upload_id: &str) -> String {
+    format!("{}/.uploads/{}", user_folder, upload_id)
+}
+
+fn upload_expiry(app: &AppState) -> NaiveDateTime {
+    chrono::Utc::now().naive_utc() + chrono::Duration::hours(app.get_config().upload_expiry_hours)
+}
+
+fn http_date(at: NaiveDateTime) -> String {
+    HttpDate::from(SystemTime::from(at.and_utc())).to_string()
+}
+
+// Streams the request body, counting the bytes that were passed on. A body longer than `limit`
+// fails the stream, while a dropped connection just ends it so the bytes received so far are kept
+fn limited_stream(payload: web::Payload, limit: u64, received: Rc<Cell<u64>>) -> ByteStream {
+    Box::pin(futures_util::stream::unfold((payload, false), move |(mut payload, failed)| {
+        let received = received.clone();
+        async move {
+            if failed {
+                return None;
+            }
+            match payload.next().await {
+                Some(Ok(chunk)) => {
+                    let total = received.get() + chunk.len() as u64;
+                    if total > limit {
+                        let err = std::io::Error::new(ErrorKind::InvalidData, "Upload exceeds its Upload-Length");
+                        return Some((Err(err), (payload, true)));
+                    }
+                    received.set(total);
+                    Some((Ok(chunk), (payload, false)))
+                }
+                Some(Err(err)) => {
+                    log::warn!("Upload interrupted after {} bytes: {}", received.get(), err);
+                    None
+                }
+                None => None,
+            }
+        }
+    }))
+}
+
+fn check_tus_version(req: &HttpRequest) -> Option<HttpResponse> {
+    match header_value(req, TUS_RESUMABLE) {
+        Some(TUS_VERSION) => None,
+        _ => Some(HttpResponse::PreconditionFailed()
+            .insert_header(("Tus-Version", TUS_VERSION))
+            .finish()),
+    }
+}
+
+fn header_value<'a>(req: &'a HttpRequest, name: &str) -> Option<&'a str> {
+    req.headers().get(name).and_then(|value| value.to_str().ok())
+}
+
+// Upload-Metadata is a comma separated list of `key base64(value)` pairs, the value may be omitted
+fn parse_metadata(header: &str) -> Result<HashMap<String, String>, ServiceError> {
+    let mut metadata = HashMap::new();
+    for pair in header.split(',').map(str::trim).filter(|pair| !pair.is_empty()) {
+        let mut parts = pair.splitn(2, ' ');
+        let key = parts.next().unwrap_or_default().to_string();
+        let value = match parts.next() {
+            Some(encoded) => base64::engine::general_purpose::STANDARD
+                .decode(encoded.trim())
+                .ok()
+                .and_then(|decoded| String::from_utf8(decoded).ok())
+                .ok_or_else(|| ServiceError::BadRequest(format!("Invalid Upload-Metadata value for {}", key)))?,
+            None => String::new(),
+        };
+        metadata.insert(key, value);
+    }
+    Ok(metadata)
+}
+
+pub fn config(conf: &mut web::ServiceConfig) {
+    let scope = web::scope("/uploads")
+            .wrap(DefaultHeaders::new().add((TUS_RESUMABLE, TUS_VERSION)))
+            .service(upload_options_handler)
+            .service(create_upload_handler)
+            .service(get_upload_offset_handler)
+            .service(append_upload_handler)
+            .service(delete_upload_handler)
+            ;
+
+    conf.service(scope);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::error::PayloadError;
+    use actix_web::web::Bytes;
+    use actix_web::test::TestRequest;
+    use actix_web::FromRequest;
+
+    async fn payload_of(chunks: Vec<Result<Bytes, PayloadError>>) -> web::Payload {
+        let (req, _) = TestRequest::default().to_http_parts();
+        let stream: std::pin::Pin<Box<dyn futures_util::Stream<Item = Result<Bytes, PayloadError>>>> = Box::pin(futures_util::stream::iter(chunks));
+        web::Payload::from_request(&req, &mut actix_web::dev::Payload::from(stream)).await.unwrap()
+    }
+
+    #[actix_web::test]
+    async fn limited_stream_counts_the_bytes_passed_on() {
+        let received = Rc::new(Cell::new(0));
+        let payload = payload_of(vec![Ok(Bytes::from_static(b"hello ")), Ok(Bytes::from_static(b"world"))]).await;
+        let chunks: Vec<Bytes> = limited_stream(payload, 11, received.clone()).try_collect().await.unwrap();
+        assert_eq!(chunks.concat(), b"hello world");
+        assert_eq!(received.get(), 11);
+    }
+
+    #[actix_web::test]
+    async fn limited_stream_fails_past_the_limit() {
+        let received = Rc::new(Cell::new(0));
+        let payload = payload_of(vec![Ok(Bytes::from_static(b"hello ")), Ok(Bytes::from_static(b"world"))]).await;
+        let mut stream = limited_stream(payload, 8, received.clone());
+        assert_eq!(stream.try_next().await.unwrap().unwrap(), "hello ");
+        assert_eq!(stream.try_next().await.unwrap_err().kind(), ErrorKind::InvalidData);
+        assert!(stream.next().await.is_none());
+        assert_eq!(received.get(), 6);
+    }
+
+    #[actix_web::test]
+    async fn limited_stream_keeps_what_came_before_a_dropped_connection() {
+        let received = Rc::new(Cell::new(0));
+        let payload = payload_of(vec![Ok(Bytes::from_static(b"hello ")), Err(PayloadError::Incomplete(None)), Ok(Bytes::from_static(b"world"))]).await;
+        let chunks: Vec<Bytes> = limited_stream(payload, 11, received.clone()).try_collect().await.unwrap();
+        assert_eq!(chunks.concat(), b"hello ");
+        assert_eq!(received.get(), 6);
+    }
+
+    #[test]
+    fn metadata_values_are_base64_decoded() {
+        let metadata = parse_metadata("fileId YWJj, filename bm90ZXMgMS50eHQ=,is_confidential").unwrap();
+        assert_eq!(metadata.len(), 3);
+        assert_eq!(metadata["fileId"], "abc");
+        assert_eq!(metadata["filename"], "notes 1.txt");
+        assert_eq!(metadata["is_confidential"], "");
+        assert!(parse_metadata("").unwrap().is_empty());
+    }
+
+    #[test]
+    fn invalid_metadata_values_are_refused() {
+        assert!(parse_metadata("filename not-base64!").is_err());
+        // Valid base64, but not UTF-8
+        assert!(parse_metadata("filename /w==").is_err());
+    }
+}
@@ -0,0 +1,22 @@
+use crate::shared::common::{DbError, DbPool, StorageService};
+use super::remove_staged_chunks;
+use super::service::{delete_upload, get_expired_uploads};
+
+///
+/// Removes uploads that have expired, their records, staged chunks and the space they held
+///
+/// Uploads a PATCH is appending to are left for the next run
+///
+pub async fn purge_expired_uploads(pool: &DbPool, storage: &dyn StorageService) -> Result<usize, DbError> {
+    let mut conn = pool.get()?;
+    let mut purged = 0;
+
+    for (upload, user_folder) in get_expired_uploads(&mut conn, chrono::Utc::now().naive_utc())? {
+        if delete_upload(&mut conn, &upload.id)? == 1 {
+            remove_staged_chunks(storage, &user_folder, &upload.id).await.map_err(|err| err.to_string())?;
+            purged += 1;
+        }
+    }
+
+    Ok(purged)
+}
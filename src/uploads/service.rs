@@ -0,0 +1,127 @@
+use crate::auth::service::add_storage_used;
+use crate::shared::common::DbError;
+use super::dto::{UploadDto, CreateUploadDto};
+use diesel::prelude::*;
+use uuid::Uuid;
+
+use crate::schema::uploads::dsl;
+use crate::schema::users;
+
+pub fn create_upload(conn: &mut SqliteConnection, upload: CreateUploadDto, owner_id: i32) -> Result<String, DbError> {
+    let upload = UploadDto {
+        id: Uuid::new_v4().to_string(),
+        owner_id,
+        file_id: upload.file_id,
+        upload_length: upload.upload_length,
+        upload_offset: 0,
+        filename: upload.filename,
+        media_type: upload.media_type,
+        patching: false,
+        quota_user_id: upload.quota_user_id,
+        expires_at: upload.expires_at,
+        created_at: None,
+        updated_at: None,
+        created_by: owner_id,
+        updated_by: owner_id,
+        active: true,
+    };
+
+    diesel::insert_into(dsl::uploads)
+        .values(&upload)
+        .execute(conn)?;
+    Ok(upload.id)
+}
+
+///
+/// Gets an upload of the user, expired uploads are treated as gone already
+///
+pub fn get_upload(conn: &mut SqliteConnection, upload_id: &str, user_id: i32) -> Result<UploadDto, DbError> {
+    let upload = dsl::uploads
+        .filter(dsl::id.eq(upload_id))
+        .filter(dsl::owner_id.eq(user_id))
+        .filter(dsl::expires_at.gt(chrono::Utc::now().naive_utc()))
+        .first::<UploadDto>(conn)
+        .map_err(|err| format!("Error loading upload upload_id: {}, user_id: {}, Error: {}", upload_id, user_id, err))?;
+    Ok(upload)
+}
+
+///
+/// Claims an active upload for a PATCH starting at `upload_offset`
+///
+/// Checks the offset and claims the upload in one statement, so of two concurrent requests
+/// for the same offset only one gets the upload. False when the offset is not the upload's
+/// current one or another request has it
+///
+pub fn claim_upload(conn: &mut SqliteConnection, upload_id: &str, user_id: i32, upload_offset: i64) -> Result<bool, DbError> {
+    let claimed = diesel::update(dsl::uploads
+            .filter(dsl::id.eq(upload_id))
+            .filter(dsl::owner_id.eq(user_id))
+            .filter(dsl::active.eq(true))
+            .filter(dsl::upload_offset.eq(upload_offset))
+            .filter(dsl::patching.eq(false)))
+        .set(dsl::patching.eq(true))
+        .execute(conn)?;
+    Ok(claimed == 1)
+}
+
+///
+/// Records the offset reached by a PATCH and releases the upload for the next one
+///
+pub fn update_upload_offset(conn: &mut SqliteConnection, upload_id: &str, user_id: i32, upload_offset: i64, expires_at: chrono::NaiveDateTime) -> Result<usize, DbError> {
+    Ok(diesel::update(dsl::uploads.filter(dsl::id.eq(upload_id).and(dsl::owner_id.eq(user_id))))
+        .set((
+            dsl::upload_offset.eq(upload_offset),
+            dsl::patching.eq(false),
+            dsl::expires_at.eq(expires_at),
+            dsl::updated_by.eq(user_id),
+            dsl::updated_at.eq(chrono::Utc::now().naive_utc())))
+        .execute(conn)?)
+}
+
+pub fn complete_upload(conn: &mut SqliteConnection, upload_id: &str, user_id: i32) -> Result<usize, DbError> {
+    Ok(diesel::update(dsl::uploads.filter(dsl::id.eq(upload_id).and(dsl::owner_id.eq(user_id))))
+        .set((
+            dsl::active.eq(false),
+            dsl::updated_by.eq(user_id),
+            dsl::updated_at.eq(chrono::Utc::now().naive_utc())))
+        .execute(conn)?)
+}
+
+///
+/// Deletes an upload no PATCH is appending to, giving back the space an active upload held
+///
+/// Zero when the upload is gone or a PATCH has it
+///
+pub fn delete_upload(conn: &mut SqliteConnection, upload_id: &str) -> Result<usize, DbError> {
+    conn.transaction(|conn| {
+        let upload = match dsl::uploads.filter(dsl::id.eq(upload_id)).first::<UploadDto>(conn).optional()? {
+            Some(upload) if !upload.patching => upload,
+            _ => return Ok(0),
+        };
+        if upload.active {
+            add_storage_used(conn, upload.quota_user_id, -upload.upload_length)?;
+        }
+        Ok(diesel::delete(dsl::uploads.filter(dsl::id.eq(upload_id))).execute(conn)?)
+    })
+}
+
+///
+/// Uploads that expired before `now`, with the root folder of the user who started them
+///
+pub fn get_expired_uploads(conn: &mut SqliteConnection, now: chrono::NaiveDateTime) -> Result<Vec<(UploadDto, String)>, DbError> {
+    Ok(dsl::uploads
+        .inner_join(users::table.on(users::id.eq(dsl::owner_id.nullable())))
+        .filter(dsl::expires_at.le(now))
+        .filter(dsl::patching.eq(false))
+        .select((UploadDto::as_select(), users::folder_id))
+        .load::<(UploadDto, String)>(conn)?)
+}
+
+///
+/// Releases the uploads that PATCH requests held when the service stopped
+///
+pub fn release_uploads(conn: &mut SqliteConnection) -> Result<usize, DbError> {
+    Ok(diesel::update(dsl::uploads.filter(dsl::patching.eq(true)))
+        .set(dsl::patching.eq(false))
+        .execute(conn)?)
+}